@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use alloy::{contract::Error as ContractError, transports::http::reqwest::Url};
-use std::str::FromStr;
+use std::{path::PathBuf, str::FromStr};
 use thiserror::Error;
 
 use rollups_state_manager::StateAccessError;
@@ -24,9 +24,25 @@ pub enum BlockchainReaderError {
         source: ProviderErrors,
     },
 
+    #[error("Epoch `{epoch_number}` has not been sealed on chain")]
+    EpochNotSealed { epoch_number: u64 },
+
     #[error("Parse error: {0}")]
     ParseError(<Url as FromStr>::Err),
 
+    #[error("Could not read inputs from `{}`: {source}", path.display())]
+    InputFileIo {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid block at `{}` line {line}: {reason}", path.display())]
+    InputFile {
+        path: PathBuf,
+        line: usize,
+        reason: String,
+    },
+
     #[error(transparent)]
     StateManagerError {
         #[from]
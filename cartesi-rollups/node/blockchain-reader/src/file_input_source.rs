@@ -0,0 +1,226 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Inputs and sealed epochs read from local files instead of a chain, for development and
+//! deterministic replays. Each line of a file is a block, numbered from one, such as
+//! `{"inputs": ["0x01", "0x0203"], "seal_epoch": true}`: its inputs go to the open epoch, which is
+//! then sealed if `seal_epoch` is set. Inputs are the data the machine receives, and epochs have no
+//! root tournament. Blocks may be appended while the node runs.
+
+use alloy::primitives::Address;
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use rollups_state_manager::{Blob, Epoch, Input, InputId};
+
+use crate::{
+    error::{BlockchainReaderError, Result},
+    input_source::{ConsensusBatch, InputSource},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Line {
+    /// hex encoded
+    #[serde(default)]
+    inputs: Vec<String>,
+    #[serde(default)]
+    seal_epoch: bool,
+}
+
+#[derive(Debug)]
+struct Block {
+    inputs: Vec<Blob>,
+    seal_epoch: bool,
+}
+
+/// Reads blocks from a JSONL file, or from the `.jsonl` files of a directory in file name order.
+#[derive(Clone, Debug)]
+pub struct FileInputSource {
+    path: PathBuf,
+}
+
+impl FileInputSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    fn read_blocks(&self) -> Result<Vec<Block>> {
+        let files = if self.path.is_dir() {
+            let mut files: Vec<PathBuf> = fs::read_dir(&self.path)
+                .map_err(|source| io_error(&self.path, source))?
+                .filter_map(|entry| Some(entry.ok()?.path()))
+                .filter(|path| path.extension().is_some_and(|e| e == "jsonl"))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![self.path.clone()]
+        };
+
+        let mut blocks = Vec::new();
+        for file in files {
+            let content = fs::read_to_string(&file).map_err(|source| io_error(&file, source))?;
+
+            // a last line without a newline may still be being written
+            let lines = content.split_inclusive('\n').filter(|l| l.ends_with('\n'));
+            for (i, line) in lines.enumerate() {
+                let invalid = |reason: String| BlockchainReaderError::InputFile {
+                    path: file.clone(),
+                    line: i + 1,
+                    reason,
+                };
+
+                let line: Line = if line.trim().is_empty() {
+                    Line::default()
+                } else {
+                    serde_json::from_str(line).map_err(|e| invalid(e.to_string()))?
+                };
+                let inputs = line
+                    .inputs
+                    .iter()
+                    .map(|input| {
+                        alloy::hex::decode(input).map_err(|e| invalid(format!("{input}: {e}")))
+                    })
+                    .collect::<Result<_>>()?;
+                blocks.push(Block {
+                    inputs,
+                    seal_epoch: line.seal_epoch,
+                });
+            }
+        }
+
+        Ok(blocks)
+    }
+}
+
+fn io_error(path: &Path, source: std::io::Error) -> BlockchainReaderError {
+    BlockchainReaderError::InputFileIo {
+        path: path.to_owned(),
+        source,
+    }
+}
+
+#[async_trait(?Send)]
+impl InputSource for FileInputSource {
+    async fn next_batch(
+        &mut self,
+        prev_block: u64,
+        _last_input: Option<InputId>,
+        _last_sealed_epoch: Option<Epoch>,
+    ) -> Result<Option<ConsensusBatch>> {
+        let blocks = self.read_blocks()?;
+        let last_block = blocks.len() as u64;
+        if last_block <= prev_block {
+            return Ok(None);
+        }
+
+        // input ids depend on every seal before them, so blocks are always replayed from the first
+        let mut batch = ConsensusBatch {
+            last_processed_block: last_block,
+            ..Default::default()
+        };
+        let mut epoch_number = 0;
+        let mut input_index_in_epoch = 0;
+        let mut input_count = 0;
+        for (block_number, block) in (1..).zip(blocks) {
+            let is_new = block_number > prev_block;
+
+            for input in block.inputs {
+                if is_new {
+                    batch.inputs.push(Input {
+                        id: InputId {
+                            epoch_number,
+                            input_index_in_epoch,
+                        },
+                        data: input,
+                        metadata: None,
+                    });
+                }
+                input_index_in_epoch += 1;
+                input_count += 1;
+            }
+
+            if block.seal_epoch {
+                if is_new {
+                    batch.epochs.push(Epoch {
+                        epoch_number,
+                        input_index_boundary: input_count,
+                        root_tournament: Address::ZERO,
+                        block_created_number: block_number,
+                    });
+                }
+                epoch_number += 1;
+                input_index_in_epoch = 0;
+            }
+        }
+
+        Ok(Some(batch))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+    fn ids(batch: &ConsensusBatch) -> Vec<(u64, u64)> {
+        batch
+            .inputs
+            .iter()
+            .map(|i| (i.id.epoch_number, i.id.input_index_in_epoch))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_file_input_source() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("inputs.jsonl");
+        fs::write(
+            &path,
+            concat!(
+                "{\"inputs\": [\"0x01\", \"0x02\"], \"seal_epoch\": true}\n",
+                "\n",
+                "{\"inputs\": [\"0x03\"]}\n",
+                "{\"inputs\": [\"0x04\"], \"seal",
+            ),
+        )?;
+        let mut source = FileInputSource::new(&path);
+
+        let batch = source.next_batch(0, None, None).await?.unwrap();
+        assert_eq!(batch.last_processed_block, 3);
+        assert_eq!(ids(&batch), vec![(0, 0), (0, 1), (1, 0)]);
+        assert_eq!(batch.inputs[2].data, vec![3]);
+        assert_eq!(batch.epochs.len(), 1);
+        assert_eq!(batch.epochs[0].epoch_number, 0);
+        assert_eq!(batch.epochs[0].input_index_boundary, 2);
+        assert_eq!(batch.epochs[0].block_created_number, 1);
+
+        assert!(source.next_batch(3, None, None).await?.is_none());
+
+        // finishing the last line and appending another
+        let mut content = fs::read_to_string(&path)?;
+        content.push_str("_epoch\": true}\n{\"inputs\": [\"0x05\"]}\n");
+        fs::write(&path, content)?;
+
+        let batch = source.next_batch(3, None, None).await?.unwrap();
+        assert_eq!(batch.last_processed_block, 5);
+        assert_eq!(ids(&batch), vec![(1, 1), (2, 0)]);
+        assert_eq!(batch.epochs.len(), 1);
+        assert_eq!(batch.epochs[0].epoch_number, 1);
+        assert_eq!(batch.epochs[0].input_index_boundary, 4);
+
+        fs::write(&path, "{\"inputs\": [\"0xzz\"]}\n")?;
+        assert!(matches!(
+            source.next_batch(0, None, None).await,
+            Err(BlockchainReaderError::InputFile { line: 1, .. })
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,29 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use async_trait::async_trait;
+use rollups_state_manager::{Epoch, Input, InputId};
+
+use crate::error::Result;
+
+/// Inputs and sealed epochs up to `last_processed_block`, inserted into the state all at once.
+#[derive(Clone, Debug, Default)]
+pub struct ConsensusBatch {
+    pub last_processed_block: u64,
+    pub inputs: Vec<Input>,
+    pub epochs: Vec<Epoch>,
+}
+
+/// Where the consensus data of an application comes from, read by a
+/// [BlockchainReader](crate::BlockchainReader) into its state.
+#[async_trait(?Send)]
+pub trait InputSource {
+    /// Reads what was added after `prev_block`, given the last input and sealed epoch already in
+    /// the state; none if there is nothing new yet.
+    async fn next_batch(
+        &mut self,
+        prev_block: u64,
+        last_input: Option<InputId>,
+        last_sealed_epoch: Option<Epoch>,
+    ) -> Result<Option<ConsensusBatch>>;
+}
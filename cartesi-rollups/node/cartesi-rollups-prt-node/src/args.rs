@@ -4,17 +4,28 @@
 use alloy::{primitives::Address, providers::DynProvider, transports::http::reqwest::Url};
 use alloy_chains::NamedChain;
 use clap::{ArgGroup, Parser, Subcommand};
-use rollups_blockchain_reader::AddressBook;
+use rollups_blockchain_reader::{AddressBook, fetch_checkpoint};
 use rollups_state_manager::{
-    StateAccessError, StateManager, persistent_state_access::PersistentStateAccess,
+    StateAccessError, StateManager,
+    console::ConsoleCapture,
+    persistent_state_access::PersistentStateAccess,
+    postgres_state_access::PostgresStateAccess,
+    retention::{RetentionPolicy, SnapshotRetention},
 };
-use std::{fmt, path::PathBuf, time::Duration};
+use std::{fmt, fs, path::PathBuf, time::Duration};
 
-use crate::provider::create_provider;
+use crate::{
+    provider::create_provider,
+    state_access::{StateAccess, StateBackend},
+};
 
 const CANNON_CHAIN_ID: u64 = 31337;
 const ANVIL_URL: &str = "http://127.0.0.1:8545";
 const SLEEP_DURATION: u64 = 30;
+const MAX_SLEEP_DURATION: u64 = 300;
+const BLOCK_TIME: u64 = 12;
+const CONSOLE_MAX_FILE_SIZE: u64 = 1 << 20;
+const CONSOLE_MAX_FILES: usize = 1000;
 
 #[derive(Clone, Parser)]
 #[command(name = "cartesi_prt_args")]
@@ -37,15 +48,59 @@ pub struct PRTArgs {
     pub web3_chain_id: u64,
 
     #[clap(subcommand)]
-    pub signer: SignerArgs,
+    pub command: Command,
 
     /// polling sleep interval
     #[arg(long, env, default_value_t = SLEEP_DURATION)]
     pub sleep_duration_seconds: u64,
 
+    /// longest interval between reactions to tournaments when no clock is ticking
+    #[arg(long, env, default_value_t = MAX_SLEEP_DURATION)]
+    pub max_sleep_duration_seconds: u64,
+
+    /// expected interval between blocks, used to schedule reactions to tournament clocks
+    #[arg(long, env, default_value_t = BLOCK_TIME, value_parser = clap::value_parser!(u64).range(1..))]
+    pub block_time_seconds: u64,
+
     #[arg(long, env, default_value_os_t = std::env::temp_dir())]
     pub state_dir: PathBuf,
 
+    /// database the node state is kept in; machine snapshots always stay in `state_dir`
+    #[arg(long, env, value_enum, default_value_t = StateBackend::Sqlite)]
+    pub state_backend: StateBackend,
+
+    /// connection string of the PostgreSQL database, when `state_backend` is `postgres`
+    #[arg(long, env, required_if_eq("state_backend", "postgres"))]
+    pub database_url: Option<postgres::Config>,
+
+    /// start a fresh node from this machine snapshot instead of replaying from genesis
+    #[arg(long, env, requires = "trusted_snapshot_epoch")]
+    pub trusted_snapshot_path: Option<PathBuf>,
+
+    /// sealed epoch that `trusted_snapshot_path` is the initial state of
+    #[arg(long, env, requires = "trusted_snapshot_path")]
+    pub trusted_snapshot_epoch: Option<u64>,
+
+    /// epoch boundary snapshots to keep: `all`, `last:<n>` or `every:<k>`
+    #[arg(long, env, default_value_t = RetentionPolicy::default())]
+    pub snapshot_retention: RetentionPolicy,
+
+    /// archive evicted epoch boundary snapshots as compressed tarballs in this directory
+    #[arg(long, env)]
+    pub snapshot_archive_dir: Option<PathBuf>,
+
+    /// capture the application console of each input into `state_dir/console/<epoch>-<input>.log`
+    #[arg(long, env)]
+    pub capture_console: bool,
+
+    /// bytes of console kept per input when `capture_console` is set, the rest is dropped
+    #[arg(long, env, default_value_t = CONSOLE_MAX_FILE_SIZE)]
+    pub console_max_file_size: u64,
+
+    /// console logs kept when `capture_console` is set, the ones of the oldest inputs are removed
+    #[arg(long, env, default_value_t = CONSOLE_MAX_FILES)]
+    pub console_max_files: usize,
+
     /// error codes to retry `get_logs` with shorter block range
     #[arg(long, env, default_values = &["-32005", "-32600", "-32602", "-32616"])]
     // -32005 Infura
@@ -54,6 +109,19 @@ pub struct PRTArgs {
     pub long_block_range_error_codes: Vec<String>,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    #[command(flatten)]
+    Signer(SignerArgs),
+
+    /// rebuild the node state from chain into a new or empty directory, compare its settlements
+    /// with the ones in `state_dir` and on chain, then exit
+    Reindex {
+        /// directory to rebuild the state into
+        reindex_dir: PathBuf,
+    },
+}
+
 #[derive(Subcommand, Debug, Clone)]
 pub enum SignerArgs {
     /// private‐key signer
@@ -106,32 +174,63 @@ pub struct PRTConfig {
     // Provider
     pub chain_id: NamedChain,
     pub ethereum_gateway: Url,
-    pub signer_address: Address,
+    /// none when reindexing, which only reads from chain
+    pub signer_address: Option<Address>,
 
     // State
     pub state_dir: PathBuf,
+    pub state_backend: StateBackend,
+    pub snapshot_retention: SnapshotRetention,
+    pub reindex_dir: Option<PathBuf>,
+    pub console: Option<ConsoleCapture>,
 
     // Misc
     pub sleep_duration: Duration,
+    pub max_sleep_duration: Duration,
+    pub block_time: Duration,
     pub long_block_range_error_codes: Vec<String>,
 
     // private
-    signer: SignerArgs,
+    signer: Option<SignerArgs>,
+    database: Option<postgres::Config>,
 }
 
 impl fmt::Display for PRTConfig {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.address_book)?;
         writeln!(f, "Machine path: {}", self.machine_path.display())?;
-        writeln!(f, "Signer address: {}", self.signer_address)?;
+        if let Some(signer_address) = &self.signer_address {
+            writeln!(f, "Signer address: {}", signer_address)?;
+        }
         writeln!(f, "Chain Id: {} ({})", self.chain_id, self.chain_id as u64)?;
         writeln!(f, "Ethereum gateway: <redacted>")?;
         writeln!(f, "State directory: {}", self.state_dir.display())?;
+        writeln!(f, "State backend: {:?}", self.state_backend)?;
+        writeln!(f, "Snapshot retention: {}", self.snapshot_retention.policy)?;
+        if let Some(archive_dir) = &self.snapshot_retention.archive_dir {
+            writeln!(f, "Snapshot archive directory: {}", archive_dir.display())?;
+        }
+        if let Some(reindex_dir) = &self.reindex_dir {
+            writeln!(f, "Reindex directory: {}", reindex_dir.display())?;
+        }
+        if let Some(console) = &self.console {
+            writeln!(
+                f,
+                "Console capture: {} bytes per input, {} files",
+                console.max_file_size, console.max_files
+            )?;
+        }
         writeln!(
             f,
             "Sleep duration: {} seconds",
             self.sleep_duration.as_secs()
         )?;
+        writeln!(
+            f,
+            "Max sleep duration: {} seconds",
+            self.max_sleep_duration.as_secs()
+        )?;
+        writeln!(f, "Block time: {} seconds", self.block_time.as_secs())?;
         write!(f, "Long block range error codes: [")?;
         for (i, item) in self.long_block_range_error_codes.iter().enumerate() {
             if i > 0 {
@@ -145,7 +244,7 @@ impl fmt::Display for PRTConfig {
 }
 
 impl PRTConfig {
-    pub fn setup() -> (Self, PersistentStateAccess) {
+    pub fn setup() -> anyhow::Result<(Self, StateAccess)> {
         let rt = tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()
@@ -153,58 +252,167 @@ impl PRTConfig {
         rt.block_on(async move { Self::_setup().await })
     }
 
-    pub fn state_access(&self) -> Result<PersistentStateAccess, StateAccessError> {
-        PersistentStateAccess::new(&self.state_dir)
+    pub fn state_access(&self) -> Result<StateAccess, StateAccessError> {
+        let retention = self.snapshot_retention.clone();
+        Ok(match (self.state_backend, &self.database) {
+            (StateBackend::Postgres, Some(database)) => {
+                let state =
+                    PostgresStateAccess::new(database, &self.state_dir)?.with_retention(retention);
+                StateAccess::Postgres(match &self.console {
+                    Some(console) => state.with_console(console.clone()),
+                    None => state,
+                })
+            }
+            _ => {
+                let state = PersistentStateAccess::new(&self.state_dir)?.with_retention(retention);
+                StateAccess::Sqlite(match &self.console {
+                    Some(console) => state.with_console(console.clone()),
+                    None => state,
+                })
+            }
+        })
     }
 
     pub async fn provider(&self) -> DynProvider {
-        create_provider(&self.ethereum_gateway, self.chain_id, &self.signer)
+        create_provider(&self.ethereum_gateway, self.chain_id, self.signer.as_ref())
             .await
             .1
     }
 
-    async fn _setup() -> (Self, PersistentStateAccess) {
+    async fn _setup() -> anyhow::Result<(Self, StateAccess)> {
         let args = PRTArgs::parse();
 
+        let (signer, reindex_dir) = match args.command.clone() {
+            Command::Signer(signer) => (Some(signer), None),
+            Command::Reindex { reindex_dir } => (None, Some(reindex_dir)),
+        };
+        if let Some(reindex_dir) = &reindex_dir {
+            if args.trusted_snapshot_path.is_some() {
+                anyhow::bail!("a reindex replays from genesis, not from a trusted snapshot");
+            }
+            // an existing state would be resumed instead of rebuilt from chain
+            if fs::read_dir(reindex_dir).is_ok_and(|mut d| d.next().is_some()) {
+                anyhow::bail!("reindex directory {} is not empty", reindex_dir.display());
+            }
+        }
+
         let chain_id = args
             .web3_chain_id
             .try_into()
             .expect("fail to convert chain id");
 
         let (signer_address, provider) =
-            create_provider(&args.web3_rpc_url, chain_id, &args.signer).await;
+            create_provider(&args.web3_rpc_url, chain_id, signer.as_ref()).await;
         let address_book = AddressBook::new(args.app_address, &provider).await;
 
-        let mut state_manager = PersistentStateAccess::migrate(
-            &args.state_dir,
-            &args.machine_path,
-            address_book.genesis_block_number,
-        )
-        .expect("could not create `state_manager`");
-
-        let mut machine = state_manager
-            .snapshot(0, 0)
-            .unwrap()
-            .expect("epoch zero should always exist");
-        assert_eq!(
-            machine.state_hash().unwrap(),
-            address_book.initial_hash,
-            "local machine initial hash doesn't match on-chain"
-        );
-
-        (
+        let state_manager = match (
+            args.trusted_snapshot_path.clone(),
+            args.trusted_snapshot_epoch,
+        ) {
+            (Some(snapshot_path), Some(epoch_number)) => {
+                let checkpoint = fetch_checkpoint(
+                    &provider,
+                    &address_book,
+                    epoch_number,
+                    snapshot_path,
+                    args.long_block_range_error_codes.clone(),
+                )
+                .await
+                .expect("could not fetch checkpoint");
+
+                // the snapshot is checked against the state hash sealed on chain
+                match &args.database_url {
+                    Some(database) if args.state_backend == StateBackend::Postgres => {
+                        PostgresStateAccess::migrate_from_checkpoint(
+                            database,
+                            &args.state_dir,
+                            &checkpoint,
+                        )
+                        .map(StateAccess::Postgres)
+                    }
+                    _ => {
+                        PersistentStateAccess::migrate_from_checkpoint(&args.state_dir, &checkpoint)
+                            .map(StateAccess::Sqlite)
+                    }
+                }
+                .expect("could not create `state_manager` from checkpoint")
+            }
+            _ => {
+                // a reindex starts over in its own directory, leaving the previous state untouched
+                let mut state_manager = match (&reindex_dir, &args.database_url) {
+                    (Some(reindex_dir), _) => PersistentStateAccess::migrate(
+                        reindex_dir,
+                        &args.machine_path,
+                        address_book.genesis_block_number,
+                    )
+                    .map(StateAccess::Sqlite),
+                    (None, Some(database)) if args.state_backend == StateBackend::Postgres => {
+                        PostgresStateAccess::migrate(
+                            database,
+                            &args.state_dir,
+                            &args.machine_path,
+                            address_book.genesis_block_number,
+                        )
+                        .map(StateAccess::Postgres)
+                    }
+                    _ => PersistentStateAccess::migrate(
+                        &args.state_dir,
+                        &args.machine_path,
+                        address_book.genesis_block_number,
+                    )
+                    .map(StateAccess::Sqlite),
+                }
+                .expect("could not create `state_manager`");
+
+                let mut machine = state_manager
+                    .snapshot(0, 0)
+                    .unwrap()
+                    .expect("epoch zero should always exist");
+                assert_eq!(
+                    machine.state_hash().unwrap(),
+                    address_book.initial_hash,
+                    "local machine initial hash doesn't match on-chain"
+                );
+
+                state_manager
+            }
+        };
+
+        let (state_dir, reindex_dir) = match reindex_dir {
+            Some(_) => (args.state_dir, Some(state_manager.state_dir().to_owned())),
+            None => (state_manager.state_dir().to_owned(), None),
+        };
+        let console = args.capture_console.then(|| {
+            ConsoleCapture::new(
+                &state_dir,
+                args.console_max_file_size,
+                args.console_max_files,
+            )
+        });
+
+        Ok((
             Self {
                 address_book,
-                state_dir: state_manager.state_dir().to_owned(),
+                state_dir,
+                state_backend: args.state_backend,
+                snapshot_retention: SnapshotRetention {
+                    policy: args.snapshot_retention,
+                    archive_dir: args.snapshot_archive_dir,
+                },
+                reindex_dir,
+                console,
                 machine_path: args.machine_path,
                 chain_id,
                 signer_address,
                 ethereum_gateway: args.web3_rpc_url,
                 sleep_duration: Duration::from_secs(args.sleep_duration_seconds),
-                signer: args.signer,
+                max_sleep_duration: Duration::from_secs(args.max_sleep_duration_seconds),
+                block_time: Duration::from_secs(args.block_time_seconds),
+                signer,
+                database: args.database_url,
                 long_block_range_error_codes: args.long_block_range_error_codes,
             },
             state_manager,
-        )
+        ))
     }
 }
@@ -0,0 +1,82 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Runs the inputs and epochs of a local file through the machine, without a chain, printing the
+//! settlement of every sealed epoch. See [rollups_blockchain_reader::file_input_source] for the
+//! file format.
+
+use anyhow::Result;
+use clap::Parser;
+use env_logger::Env;
+use log::info;
+use std::{path::PathBuf, thread, time::Duration};
+
+use rollups_blockchain_reader::{BlockchainReader, file_input_source::FileInputSource};
+use rollups_machine_runner::MachineRunner;
+use rollups_state_manager::{StateManager, persistent_state_access::PersistentStateAccess};
+
+#[derive(Debug, Parser)]
+#[command(name = "rollups-replay")]
+#[command(about = "Replays inputs from local files, without a chain")]
+struct Args {
+    /// JSONL file of blocks, or directory of them
+    #[arg(long, env)]
+    inputs: PathBuf,
+
+    /// path to machine template image
+    #[arg(long, env)]
+    machine_path: PathBuf,
+
+    #[arg(long, env)]
+    state_dir: PathBuf,
+
+    /// keep replaying blocks as they are appended, instead of exiting
+    #[arg(long)]
+    follow: bool,
+
+    /// polling sleep interval when following
+    #[arg(long, env, default_value_t = 1)]
+    sleep_duration_seconds: u64,
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+    let sleep_duration = Duration::from_secs(args.sleep_duration_seconds);
+
+    let mut state = PersistentStateAccess::migrate(&args.state_dir, &args.machine_path, 0)?;
+    let mut blockchain_reader = BlockchainReader::with_source(
+        PersistentStateAccess::new(&args.state_dir)?,
+        FileInputSource::new(&args.inputs),
+        sleep_duration,
+    );
+    let mut machine_runner =
+        MachineRunner::new(PersistentStateAccess::new(&args.state_dir)?, sleep_duration)?;
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+
+    let mut next_settled = 0;
+    loop {
+        let block = rt.block_on(blockchain_reader.sync())?;
+        info!("read inputs and sealed epochs up to block {block}");
+
+        machine_runner.process_rollup()?;
+
+        while let Some(settlement) = state.settlement_info(next_settled)? {
+            println!(
+                "epoch {} settled with computation hash {} and outputs root 0x{}",
+                next_settled,
+                settlement.computation_hash.to_hex(),
+                alloy::hex::encode(settlement.output_merkle)
+            );
+            next_settled += 1;
+        }
+
+        if !args.follow {
+            return Ok(());
+        }
+        thread::sleep(sleep_duration);
+    }
+}
@@ -3,6 +3,8 @@
 
 pub mod args;
 pub mod provider;
+pub mod reindex;
+pub mod state_access;
 
 use args::PRTConfig;
 
@@ -10,7 +12,10 @@ use log::{error, info};
 use std::{sync::Arc, thread};
 use tokio::sync::Mutex;
 
-use cartesi_prt_core::tournament::EthArenaSender;
+use cartesi_prt_core::{
+    strategy::scheduler::{Deadlines, Scheduler},
+    tournament::EthArenaSender,
+};
 use rollups_blockchain_reader::BlockchainReader;
 use rollups_epoch_manager::EpochManager;
 use rollups_machine_runner::MachineRunner;
@@ -61,13 +66,12 @@ pub fn create_blockchain_reader_task(
                     let blockchain_reader = BlockchainReader::new(
                         state_manager,
                         params.address_book,
+                        params.provider().await,
                         params.sleep_duration,
                         params.long_block_range_error_codes.clone(),
                     );
 
-                    blockchain_reader
-                        .execution_loop(inner_watch, params.provider().await)
-                        .await
+                    blockchain_reader.execution_loop(inner_watch).await
                 })
                 .inspect_err(|e| error!("{e}"))
             });
@@ -77,7 +81,12 @@ pub fn create_blockchain_reader_task(
         .expect("failed to spawn blockchain reader thread")
 }
 
-pub fn create_epoch_manager_task(watch: Watch, parameters: &PRTConfig) -> thread::JoinHandle<()> {
+/// Spawns the epoch manager; `deadlines` is updated with the tournament clock deadlines it observes.
+pub fn create_epoch_manager_task(
+    watch: Watch,
+    parameters: &PRTConfig,
+    deadlines: Deadlines,
+) -> thread::JoinHandle<()> {
     let params = parameters.clone();
     let inner_watch = watch.clone();
 
@@ -89,14 +98,24 @@ pub fn create_epoch_manager_task(watch: Watch, parameters: &PRTConfig) -> thread
                 rt.block_on(async move {
                     let state_manager = params.state_access().unwrap();
                     let provider = params.provider().await;
-                    let arena_sender = EthArenaSender::new(provider.clone())
-                        .expect("could not create arena sender");
+                    let arena_sender = EthArenaSender::new(
+                        provider.clone(),
+                        params
+                            .signer_address
+                            .expect("running the node requires a signer"),
+                    )
+                    .expect("could not create arena sender");
 
                     let epoch_manager = EpochManager::new(
                         Arc::new(Mutex::new(arena_sender)),
                         params.address_book.consensus,
                         state_manager,
-                        params.sleep_duration,
+                        Scheduler::new(
+                            params.block_time,
+                            params.sleep_duration,
+                            params.max_sleep_duration,
+                        ),
+                        deadlines,
                         params.long_block_range_error_codes.clone(),
                     );
 
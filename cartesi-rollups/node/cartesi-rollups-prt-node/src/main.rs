@@ -1,9 +1,10 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+use cartesi_prt_core::strategy::scheduler::Deadlines;
 use cartesi_rollups_prt_node::{
     args::PRTConfig, create_blockchain_reader_task, create_epoch_manager_task,
-    create_machine_runner_task,
+    create_machine_runner_task, reindex::reindex,
 };
 use rollups_state_manager::sync::Watch;
 
@@ -15,19 +16,41 @@ fn main() -> Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     info!("Hello from PRT Rollup Node!");
 
-    let (config, _state_manager) = PRTConfig::setup();
+    let (config, _state_manager) = PRTConfig::setup()?;
     info!("Running with config:\n{}", config);
 
+    if config.reindex_dir.is_some() {
+        let mismatches = reindex(&config)?;
+        for mismatch in &mismatches {
+            println!("{}", mismatch);
+        }
+        if !mismatches.is_empty() {
+            anyhow::bail!("{} epoch(s) recomputed differently", mismatches.len());
+        }
+        info!("reindexed state matches the previous one and the chain");
+        return Ok(());
+    }
+
     // spawn workers
     let watch = Watch::default();
     let blockchain_reader_task = create_blockchain_reader_task(watch.clone(), &config);
-    let epoch_manager_task = create_epoch_manager_task(watch.clone(), &config);
+    let deadlines = Deadlines::default();
+    let epoch_manager_task = create_epoch_manager_task(watch.clone(), &config, deadlines.clone());
     let machine_runner_task = create_machine_runner_task(watch.clone(), &config);
 
     // monitor status
+    let mut next_deadline = None;
     let err = loop {
         match watch.wait(std::time::Duration::from_millis(1000)) {
-            std::ops::ControlFlow::Continue(()) => continue,
+            std::ops::ControlFlow::Continue(()) => {
+                let deadline = deadlines.get().first().copied();
+                if deadline != next_deadline {
+                    if let Some(d) = &deadline {
+                        info!("next deadline: {d}");
+                    }
+                    next_deadline = deadline;
+                }
+            }
             std::ops::ControlFlow::Break(e) => break e,
         }
     };
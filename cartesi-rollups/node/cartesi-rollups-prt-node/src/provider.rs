@@ -112,18 +112,29 @@ async fn create_client(url: &Url) -> RpcClient {
         .transport(transport, is_local)
 }
 
+/// Provider signing with `signer`, or a read-only one without it.
 pub async fn create_provider(
     url: &Url,
     arg_chain_id: NamedChain,
-    signer: &SignerArgs,
-) -> (Address, DynProvider) {
+    signer: Option<&SignerArgs>,
+) -> (Option<Address>, DynProvider) {
     let client = create_client(url).await;
-    let (address, wallet) = create_signer(arg_chain_id, signer).await;
-
-    let provider = ProviderBuilder::new()
-        .wallet(wallet)
-        .with_chain(arg_chain_id)
-        .connect_client(client);
+    let (address, provider) = match signer {
+        Some(signer) => {
+            let (address, wallet) = create_signer(arg_chain_id, signer).await;
+            let provider = ProviderBuilder::new()
+                .wallet(wallet)
+                .with_chain(arg_chain_id)
+                .connect_client(client);
+            (Some(address), provider.erased())
+        }
+        None => {
+            let provider = ProviderBuilder::new()
+                .with_chain(arg_chain_id)
+                .connect_client(client);
+            (None, provider.erased())
+        }
+    };
 
     let chain_id = provider
         .get_chain_id()
@@ -134,5 +145,5 @@ pub async fn create_provider(
         "provider chain_id does not match args chain_id"
     );
 
-    (address, provider.erased())
+    (address, provider)
 }
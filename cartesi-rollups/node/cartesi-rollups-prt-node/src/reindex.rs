@@ -0,0 +1,234 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Rebuilds the node state from chain and the template machine, in a directory of its own, and
+//! checks the recomputed settlements against the previous state and against the chain.
+
+use std::{collections::HashMap, fmt};
+
+use anyhow::Result;
+use cartesi_machine::types::Hash;
+use log::{info, warn};
+
+use rollups_blockchain_reader::{BlockchainReader, fetch_settled_epochs};
+use rollups_machine_runner::MachineRunner;
+use rollups_state_manager::{
+    Settlement, StateManager, persistent_state_access::PersistentStateAccess,
+};
+
+use crate::{args::PRTConfig, create_runtime};
+
+#[derive(Clone, Debug)]
+pub enum Mismatch {
+    /// recomputed settlement differs from the one in the previous state
+    PreviousState {
+        epoch_number: u64,
+        previous: Settlement,
+        recomputed: Settlement,
+    },
+    /// recomputed outputs Merkle root differs from the one settled on chain
+    OnChain {
+        epoch_number: u64,
+        settled: Hash,
+        recomputed: Hash,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mismatch::PreviousState {
+                epoch_number,
+                previous,
+                recomputed,
+            } => write!(
+                f,
+                "epoch {} was settled with computation hash {} and outputs root 0x{} in the previous state, recomputed {} and 0x{}",
+                epoch_number,
+                previous.computation_hash.to_hex(),
+                alloy::hex::encode(previous.output_merkle),
+                recomputed.computation_hash.to_hex(),
+                alloy::hex::encode(recomputed.output_merkle)
+            ),
+            Mismatch::OnChain {
+                epoch_number,
+                settled,
+                recomputed,
+            } => write!(
+                f,
+                "epoch {} was settled on chain with outputs root 0x{}, recomputed 0x{}",
+                epoch_number,
+                alloy::hex::encode(settled),
+                alloy::hex::encode(recomputed)
+            ),
+        }
+    }
+}
+
+/// Reads every input and sealed epoch into `config.reindex_dir`, replays them all, and returns the
+/// epochs whose recomputed settlement differs from the one in `config.state_dir` or on chain. A
+/// previous state that can't be read is only warned about.
+pub fn reindex(config: &PRTConfig) -> Result<Vec<Mismatch>> {
+    let reindex_dir = config
+        .reindex_dir
+        .as_ref()
+        .expect("reindex requires a reindex directory");
+    let fresh_state = || -> Result<PersistentStateAccess> {
+        Ok(PersistentStateAccess::new(reindex_dir)?
+            .with_retention(config.snapshot_retention.clone()))
+    };
+
+    let rt = create_runtime("Reindex");
+    rt.block_on(async move {
+        let provider = config.provider().await;
+
+        let mut blockchain_reader = BlockchainReader::new(
+            fresh_state()?,
+            config.address_book,
+            provider.clone(),
+            config.sleep_duration,
+            config.long_block_range_error_codes.clone(),
+        );
+        let block = blockchain_reader.sync().await?;
+        info!("read inputs and sealed epochs up to block {block}");
+
+        MachineRunner::new(fresh_state()?, config.sleep_duration)?.process_rollup()?;
+
+        let settled: HashMap<u64, Hash> = fetch_settled_epochs(
+            &provider,
+            &config.address_book,
+            config.long_block_range_error_codes.clone(),
+        )
+        .await?
+        .into_iter()
+        .map(|s| (s.epoch_number, s.outputs_merkle_root))
+        .collect();
+
+        let previous = config
+            .state_access()
+            .inspect_err(|e| warn!("could not open previous state, comparing with chain only: {e}"))
+            .ok();
+
+        compare(&mut fresh_state()?, previous, &settled)
+    })
+}
+
+fn compare(
+    recomputed: &mut impl StateManager,
+    mut previous: Option<impl StateManager>,
+    settled: &HashMap<u64, Hash>,
+) -> Result<Vec<Mismatch>> {
+    let mut mismatches = Vec::new();
+
+    for epoch_number in 0..recomputed.epoch_count()? {
+        let Some(settlement) = recomputed.settlement_info(epoch_number)? else {
+            continue;
+        };
+
+        if let Some(state) = &mut previous {
+            match state.settlement_info(epoch_number) {
+                Ok(Some(p))
+                    if p.computation_hash != settlement.computation_hash
+                        || p.output_merkle != settlement.output_merkle =>
+                {
+                    mismatches.push(Mismatch::PreviousState {
+                        epoch_number,
+                        previous: p,
+                        recomputed: settlement.clone(),
+                    });
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    warn!("could not read previous state, comparing with chain only: {e}");
+                    previous = None;
+                }
+            }
+        }
+
+        match settled.get(&epoch_number) {
+            Some(root) if *root != settlement.output_merkle => {
+                mismatches.push(Mismatch::OnChain {
+                    epoch_number,
+                    settled: *root,
+                    recomputed: settlement.output_merkle,
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloy::primitives::Address;
+    use rollups_state_manager::{
+        Epoch, Input, InputId, in_memory_state_access::InMemoryStateAccess, processing,
+    };
+    use std::path::Path;
+
+    /// State of two sealed epochs of two inputs each, the last one being `last_input`.
+    fn state(last_input: &[u8]) -> Result<InMemoryStateAccess> {
+        let mut state =
+            InMemoryStateAccess::new(Path::new("../../../test/programs/echo/machine-image"), 0)?;
+
+        let inputs: Vec<_> = [(0, 0), (0, 1), (1, 0), (1, 1)]
+            .into_iter()
+            .map(|(epoch_number, input_index_in_epoch)| Input {
+                id: InputId {
+                    epoch_number,
+                    input_index_in_epoch,
+                },
+                data: if (epoch_number, input_index_in_epoch) == (1, 1) {
+                    last_input.to_vec()
+                } else {
+                    vec![epoch_number as u8, input_index_in_epoch as u8]
+                },
+                metadata: None,
+            })
+            .collect();
+        let epochs = [(0, 2), (1, 4)].map(|(epoch_number, input_index_boundary)| Epoch {
+            epoch_number,
+            input_index_boundary,
+            root_tournament: Address::ZERO,
+            block_created_number: epoch_number,
+        });
+        state.insert_consensus_data(10, inputs.iter(), epochs.iter())?;
+        processing::process_rollup(&mut state)?;
+
+        Ok(state)
+    }
+
+    #[test]
+    fn test_compare() -> Result<()> {
+        let mut recomputed = state(b"hello")?;
+        let mut settled: HashMap<u64, Hash> = (0..2)
+            .map(|e| Ok((e, recomputed.settlement_info(e)?.unwrap().output_merkle)))
+            .collect::<Result<_>>()?;
+
+        assert!(compare(&mut recomputed, Some(state(b"hello")?), &settled)?.is_empty());
+
+        // the previous state differs from the last input on, the chain on the first epoch
+        settled.insert(0, [1; 32]);
+        let mismatches = compare(&mut recomputed, Some(state(b"world")?), &settled)?;
+        assert!(matches!(
+            mismatches[..],
+            [
+                Mismatch::OnChain {
+                    epoch_number: 0,
+                    settled: [1, ..],
+                    ..
+                },
+                Mismatch::PreviousState {
+                    epoch_number: 1,
+                    ..
+                },
+            ]
+        ));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,189 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use std::path::{Path, PathBuf};
+
+use cartesi_prt_core::strategy::scheduler::Deadline;
+use clap::ValueEnum;
+use rollups_state_manager::{
+    CommitmentLeaf, Epoch, Input, InputExecution, InputId, InputProgress, Settlement, StateManager,
+    persistent_state_access::PersistentStateAccess, postgres_state_access::PostgresStateAccess,
+    rollups_machine::RollupsMachine, state_manager::Result,
+};
+
+/// Where the node keeps its state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum StateBackend {
+    /// SQLite database in the state directory
+    Sqlite,
+    /// PostgreSQL database at `database_url`
+    Postgres,
+}
+
+/// State of the node, in whichever [StateBackend] was selected.
+#[derive(Debug)]
+pub enum StateAccess {
+    Sqlite(PersistentStateAccess),
+    Postgres(PostgresStateAccess),
+}
+
+macro_rules! dispatch {
+    ($self:ident, $access:ident => $call:expr) => {
+        match $self {
+            StateAccess::Sqlite($access) => $call,
+            StateAccess::Postgres($access) => $call,
+        }
+    };
+}
+
+impl StateAccess {
+    pub fn state_dir(&self) -> &Path {
+        dispatch!(self, s => s.state_dir())
+    }
+}
+
+impl StateManager for StateAccess {
+    //
+    // Consensus Data
+    //
+
+    fn epoch(&mut self, epoch_number: u64) -> Result<Option<Epoch>> {
+        dispatch!(self, s => s.epoch(epoch_number))
+    }
+
+    fn epoch_count(&mut self) -> Result<u64> {
+        dispatch!(self, s => s.epoch_count())
+    }
+
+    fn last_sealed_epoch(&mut self) -> Result<Option<Epoch>> {
+        dispatch!(self, s => s.last_sealed_epoch())
+    }
+
+    fn input(&mut self, id: &InputId) -> Result<Option<Input>> {
+        dispatch!(self, s => s.input(id))
+    }
+
+    fn inputs(&mut self, epoch_number: u64) -> Result<Vec<Vec<u8>>> {
+        dispatch!(self, s => s.inputs(epoch_number))
+    }
+
+    fn input_count(&mut self, epoch_number: u64) -> Result<u64> {
+        dispatch!(self, s => s.input_count(epoch_number))
+    }
+
+    fn last_input(&mut self) -> Result<Option<InputId>> {
+        dispatch!(self, s => s.last_input())
+    }
+
+    fn insert_consensus_data<'a>(
+        &mut self,
+        last_processed_block: u64,
+        inputs: impl Iterator<Item = &'a Input>,
+        epochs: impl Iterator<Item = &'a Epoch>,
+    ) -> Result<()> {
+        dispatch!(self, s => s.insert_consensus_data(last_processed_block, inputs, epochs))
+    }
+
+    fn latest_processed_block(&mut self) -> Result<u64> {
+        dispatch!(self, s => s.latest_processed_block())
+    }
+
+    //
+    // Rollup Data
+    //
+    fn advance_accepted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        dispatch!(self, s => s.advance_accepted(machine, leafs))
+    }
+
+    fn advance_reverted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        dispatch!(self, s => s.advance_reverted(machine, leafs))
+    }
+
+    fn insert_input_execution(&mut self, id: &InputId, execution: &InputExecution) -> Result<()> {
+        dispatch!(self, s => s.insert_input_execution(id, execution))
+    }
+
+    fn input_execution(&mut self, id: &InputId) -> Result<Option<InputExecution>> {
+        dispatch!(self, s => s.input_execution(id))
+    }
+
+    fn input_executions(&mut self, epoch_number: u64) -> Result<Vec<InputExecution>> {
+        dispatch!(self, s => s.input_executions(epoch_number))
+    }
+
+    fn set_deadlines(&mut self, deadlines: &[Deadline]) -> Result<()> {
+        dispatch!(self, s => s.set_deadlines(deadlines))
+    }
+
+    fn deadlines(&mut self) -> Result<Vec<Deadline>> {
+        dispatch!(self, s => s.deadlines())
+    }
+
+    fn save_input_progress(
+        &mut self,
+        machine: &mut RollupsMachine,
+        progress: &InputProgress,
+    ) -> Result<()> {
+        dispatch!(self, s => s.save_input_progress(machine, progress))
+    }
+
+    fn input_progress(&mut self) -> Result<Option<(RollupsMachine, InputProgress)>> {
+        dispatch!(self, s => s.input_progress())
+    }
+
+    fn advance_halted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        dispatch!(self, s => s.advance_halted(machine, leafs))
+    }
+
+    fn halted_at(&mut self) -> Result<Option<InputId>> {
+        dispatch!(self, s => s.halted_at())
+    }
+
+    fn epoch_state_hashes(&mut self, epoch_number: u64) -> Result<Vec<CommitmentLeaf>> {
+        dispatch!(self, s => s.epoch_state_hashes(epoch_number))
+    }
+
+    fn settlement_info(&mut self, epoch_number: u64) -> Result<Option<Settlement>> {
+        dispatch!(self, s => s.settlement_info(epoch_number))
+    }
+
+    fn roll_epoch(&mut self) -> Result<()> {
+        dispatch!(self, s => s.roll_epoch())
+    }
+
+    fn snapshot(&mut self, epoch_number: u64, input_number: u64) -> Result<Option<RollupsMachine>> {
+        dispatch!(self, s => s.snapshot(epoch_number, input_number))
+    }
+
+    fn latest_snapshot(&mut self) -> Result<RollupsMachine> {
+        dispatch!(self, s => s.latest_snapshot())
+    }
+
+    fn next_input_id(&mut self) -> Result<InputId> {
+        dispatch!(self, s => s.next_input_id())
+    }
+
+    //
+    // Directory
+    //
+
+    fn snapshot_dir(&mut self, epoch_number: u64, input_number: u64) -> Result<Option<PathBuf>> {
+        dispatch!(self, s => s.snapshot_dir(epoch_number, input_number))
+    }
+
+    fn epoch_directory(&mut self, epoch_number: u64) -> Result<PathBuf> {
+        dispatch!(self, s => s.epoch_directory(epoch_number))
+    }
+}
@@ -8,15 +8,18 @@ use alloy::{
     providers::{DynProvider, Provider},
 };
 use error::Result;
-use log::{debug, info, trace};
+use log::{debug, info, trace, warn};
 use num_traits::cast::ToPrimitive;
-use std::{ops::ControlFlow, sync::Arc, time::Duration};
+use std::{ops::ControlFlow, sync::Arc, time::Instant};
 use tokio::sync::Mutex;
 
 use cartesi_dave_contracts::dave_consensus::DaveConsensus;
 use cartesi_prt_core::{
     db::dispute_state_access::{Input, Leaf},
-    strategy::player::Player,
+    strategy::{
+        player::Player,
+        scheduler::{Deadlines, Scheduler},
+    },
     tournament::{ArenaSender, allow_revert_rethrow_others},
 };
 use rollups_state_manager::{Epoch, Proof, StateManager, sync::Watch};
@@ -24,7 +27,8 @@ use rollups_state_manager::{Epoch, Proof, StateManager, sync::Watch};
 pub struct EpochManager<AS: ArenaSender, SM: StateManager> {
     arena_sender: Arc<Mutex<AS>>,
     consensus: Address,
-    sleep_duration: Duration,
+    scheduler: Scheduler,
+    deadlines: Deadlines,
     long_block_range_error_codes: Vec<String>,
     state_manager: SM,
     last_react_epoch: (Option<Player<AS>>, u64),
@@ -35,13 +39,15 @@ impl<AS: ArenaSender, SM: StateManager> EpochManager<AS, SM> {
         arena_sender: Arc<Mutex<AS>>,
         consensus_address: Address,
         state_manager: SM,
-        sleep_duration: Duration,
+        scheduler: Scheduler,
+        deadlines: Deadlines,
         long_block_range_error_codes: Vec<String>,
     ) -> Self {
         Self {
             arena_sender,
             consensus: consensus_address,
-            sleep_duration,
+            scheduler,
+            deadlines,
             long_block_range_error_codes,
             state_manager,
             last_react_epoch: (None, 0),
@@ -53,14 +59,40 @@ impl<AS: ArenaSender, SM: StateManager> EpochManager<AS, SM> {
 
         loop {
             self.try_settle_epoch(&dave_consensus).await?;
-            self.try_react_epoch(provider.clone()).await?;
 
-            if matches!(watch.wait(self.sleep_duration), ControlFlow::Break(_)) {
+            let disputed_epoch = self.disputed_epoch()?;
+            if self.scheduler.should_react(disputed_epoch, Instant::now()) {
+                self.try_react_epoch(provider.clone()).await?;
+
+                let deadlines = self.deadlines.get();
+                if let Some(deadline) = deadlines.first() {
+                    debug!("next deadline: {deadline}");
+                }
+                // kept in the state too, where they can be queried from outside the node
+                self.state_manager.set_deadlines(&deadlines)?;
+                self.scheduler.reacted(&deadlines, Instant::now());
+            }
+
+            // the `machine-runner` rolls a sealed epoch as soon as it sees it, so poll for its
+            // settlement every block, joining the tournament right after
+            let sleep_duration = if awaiting_settlement(&mut self.state_manager)? {
+                self.scheduler.poll_sleep_until_joined(Instant::now())
+            } else {
+                self.scheduler.poll_sleep(Instant::now())
+            };
+            trace!("sleeping for {} seconds", sleep_duration.as_secs());
+
+            if matches!(watch.wait(sleep_duration), ControlFlow::Break(_)) {
                 break Ok(());
             }
         }
     }
 
+    /// Handle to the clock deadlines observed in the latest dispute reaction.
+    pub fn deadlines(&self) -> Deadlines {
+        self.deadlines.clone()
+    }
+
     pub async fn try_settle_epoch(
         &mut self,
         dave_consensus: &DaveConsensus::DaveConsensusInstance<
@@ -112,7 +144,21 @@ impl<AS: ArenaSender, SM: StateManager> EpochManager<AS, SM> {
         Ok(())
     }
 
+    /// Last sealed epoch, once the `machine-runner` inserted its settlement values.
+    fn disputed_epoch(&mut self) -> Result<Option<u64>> {
+        let Some(last_sealed_epoch) = self.state_manager.last_sealed_epoch()? else {
+            return Ok(None);
+        };
+        let settlement = self
+            .state_manager
+            .settlement_info(last_sealed_epoch.epoch_number)?;
+        Ok(settlement.map(|_| last_sealed_epoch.epoch_number))
+    }
+
     async fn try_react_epoch(&mut self, provider: DynProvider) -> Result<()> {
+        // deadlines are only meaningful while there's a dispute to react to
+        self.deadlines.set(Vec::new());
+
         // participate in last sealed epoch tournament
         if let Some(last_sealed_epoch) = self.state_manager.last_sealed_epoch()? {
             match self
@@ -143,12 +189,14 @@ impl<AS: ArenaSender, SM: StateManager> EpochManager<AS, SM> {
         last_sealed_epoch: &Epoch,
     ) -> Result<()> {
         self.get_latest_player(last_sealed_epoch, provider)?;
-        self.last_react_epoch
+        let player = self
+            .last_react_epoch
             .0
             .as_mut()
-            .expect("prt player should be instantiated")
-            .react()
-            .await?;
+            .expect("prt player should be instantiated");
+        let result = player.react().await;
+        self.deadlines.set(player.deadlines().to_vec());
+        result?;
 
         Ok(())
     }
@@ -168,6 +216,31 @@ impl<AS: ArenaSender, SM: StateManager> EpochManager<AS, SM> {
         if self.last_react_epoch.0.is_none()
             || self.last_react_epoch.1 != last_sealed_epoch.epoch_number
         {
+            if let Some(player) = &self.last_react_epoch.0 {
+                let epoch_number = self.last_react_epoch.1;
+                info!(
+                    "dispute of epoch {} ledger: {}",
+                    epoch_number,
+                    player.ledger_summary(None)?
+                );
+                // diagnostics only, a failure to write them must not stop the dispute
+                let epoch_directory = self.state_manager.epoch_directory(epoch_number)?;
+                let audit_log_path = epoch_directory.join("audit_log.json");
+                if let Err(e) = player.export_audit_log(&audit_log_path) {
+                    warn!(
+                        "failed to export audit log of epoch {epoch_number} to {}: {e}",
+                        audit_log_path.display()
+                    );
+                }
+                let bundle_path = epoch_directory.join("dispute_bundle.json");
+                if let Err(e) = player.export_bundle(&bundle_path) {
+                    warn!(
+                        "failed to export dispute bundle of epoch {epoch_number} to {}: {e}",
+                        bundle_path.display()
+                    );
+                }
+            }
+
             let inputs = self
                 .state_manager
                 .inputs(last_sealed_epoch.epoch_number)?
@@ -210,6 +283,14 @@ fn to_bytes_32_vec(proof: Proof) -> Vec<B256> {
     proof.inner().iter().map(B256::from).collect()
 }
 
+/// Whether the last sealed epoch still waits for the `machine-runner` to insert its settlement.
+fn awaiting_settlement(state_manager: &mut impl StateManager) -> Result<bool> {
+    match state_manager.last_sealed_epoch()? {
+        Some(epoch) => Ok(state_manager.settlement_info(epoch.epoch_number)?.is_none()),
+        None => Ok(false),
+    }
+}
+
 fn vec_u8_to_bytes_32(hash: Vec<u8>) -> B256 {
     B256::from_slice(&hash)
 }
@@ -6,8 +6,8 @@ pub mod error;
 use error::Result;
 use std::{ops::ControlFlow, time::Duration};
 
-use cartesi_machine::types::cmio::ManualReason;
-use rollups_state_manager::{InputId, StateManager, sync::Watch};
+use rollups_state_manager::{StateManager, processing, sync::Watch};
+
 pub struct MachineRunner<SM: StateManager> {
     state_manager: SM,
     sleep_duration: Duration,
@@ -33,61 +33,9 @@ impl<SM: StateManager + std::fmt::Debug> MachineRunner<SM> {
         }
     }
 
-    fn process_rollup(&mut self) -> Result<()> {
-        // process all inputs that are currently availalble
-        loop {
-            self.catch_up()?;
-
-            let current_machine_epoch = self.state_manager.next_input_id()?.epoch_number;
-            let latest_blockchain_epoch = self.state_manager.epoch_count()?;
-
-            if current_machine_epoch == latest_blockchain_epoch {
-                // all current inputs processed in current epoch, which is still open.
-                // sleep and come back later.
-                break Ok(());
-            } else {
-                // epoch is finished, all inputs processed
-                assert!(current_machine_epoch < latest_blockchain_epoch);
-                self.state_manager.roll_epoch()?;
-                log::info!("started new epoch {}", current_machine_epoch + 1);
-            }
-        }
-    }
-
-    fn catch_up(&mut self) -> Result<()> {
-        let mut rollups_machine = self.state_manager.latest_snapshot()?;
-
-        loop {
-            let next_input_index = rollups_machine.next_input_index_in_epoch();
-
-            let input_id = InputId {
-                epoch_number: rollups_machine.epoch(),
-                input_index_in_epoch: next_input_index,
-            };
-            let input = self.state_manager.input(&input_id)?;
-
-            match input {
-                Some(input) => {
-                    log::info!(
-                        "processing input {}:{}",
-                        input.id.epoch_number,
-                        input.id.input_index_in_epoch
-                    );
-                    let (state_hashes, reason) = rollups_machine.process_input(&input.data)?;
-
-                    match reason {
-                        ManualReason::RxAccepted { .. } => {
-                            self.state_manager
-                                .advance_accepted(&mut rollups_machine, &state_hashes)?;
-                        }
-                        _ => {
-                            self.state_manager
-                                .advance_reverted(&mut rollups_machine, &state_hashes)?;
-                        }
-                    }
-                }
-                None => break Ok(()),
-            }
-        }
+    /// Processes every input currently available, rolling over each sealed epoch.
+    pub fn process_rollup(&mut self) -> Result<()> {
+        processing::process_rollup(&mut self.state_manager)?;
+        Ok(())
     }
 }
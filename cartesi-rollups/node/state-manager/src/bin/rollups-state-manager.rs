@@ -0,0 +1,118 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Maintenance commands for the state directory of a rollups node. The node must not be running
+//! while they change its state; the ones that only read it can run alongside the node.
+
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use env_logger::Env;
+use log::info;
+use std::path::PathBuf;
+
+use rollups_state_manager::{
+    StateManager,
+    persistent_state_access::PersistentStateAccess,
+    retention::{RetentionPolicy, SnapshotRetention},
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "rollups-state-manager")]
+#[command(about = "Maintenance of a rollups node state directory")]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// verify snapshots and settlements, reporting any corruption
+    Check {
+        #[arg(long, env)]
+        state_dir: PathBuf,
+
+        /// re-run inputs from the nearest intact snapshot to fix any corruption found
+        #[arg(long)]
+        repair: bool,
+
+        /// epoch boundary snapshots to keep while repairing: `all`, `last:<n>` or `every:<k>`
+        #[arg(long, env, default_value_t = RetentionPolicy::default())]
+        snapshot_retention: RetentionPolicy,
+
+        /// archive evicted epoch boundary snapshots as compressed tarballs in this directory
+        #[arg(long, env)]
+        snapshot_archive_dir: Option<PathBuf>,
+    },
+
+    /// print the tournament clock deadlines observed in the latest dispute reaction, earliest
+    /// first
+    Deadlines {
+        #[arg(long, env)]
+        state_dir: PathBuf,
+    },
+}
+
+fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+
+    match Args::parse().command {
+        Command::Check {
+            state_dir,
+            repair,
+            snapshot_retention,
+            snapshot_archive_dir,
+        } => {
+            let mut state =
+                PersistentStateAccess::new(&state_dir)?.with_retention(SnapshotRetention {
+                    policy: snapshot_retention,
+                    archive_dir: snapshot_archive_dir,
+                });
+
+            let corruptions = state.check()?;
+            for corruption in &corruptions {
+                println!("{}", corruption);
+            }
+            if corruptions.is_empty() {
+                info!("state directory `{}` is intact", state_dir.display());
+                return Ok(());
+            }
+
+            if !repair {
+                anyhow::bail!(
+                    "found {} corruption(s) in `{}`",
+                    corruptions.len(),
+                    state_dir.display()
+                );
+            }
+
+            match state.repair(&corruptions)? {
+                Some(restart) => info!(
+                    "reprocessed inputs from {}:{}",
+                    restart.epoch_number, restart.input_index_in_epoch
+                ),
+                None => info!("no epoch uses the corrupted snapshots, nothing to reprocess"),
+            }
+
+            let remaining = state.check()?;
+            for corruption in &remaining {
+                println!("{}", corruption);
+            }
+            if !remaining.is_empty() {
+                anyhow::bail!("{} corruption(s) left after repair", remaining.len());
+            }
+            Ok(())
+        }
+        Command::Deadlines { state_dir } => {
+            let mut state = PersistentStateAccess::new(&state_dir)?;
+
+            let deadlines = state.deadlines()?;
+            for deadline in &deadlines {
+                println!("{}", deadline);
+            }
+            if deadlines.is_empty() {
+                info!("no clock is ticking in the disputed epoch");
+            }
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,115 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Integrity checks of a state directory: snapshots must exist and hold the machine their state
+//! hash names, and settled computation hashes must match the stored state hashes.
+
+use std::{fmt, path::PathBuf};
+
+use cartesi_dave_merkle::Digest;
+use cartesi_machine::types::Hash;
+
+use crate::InputId;
+
+#[derive(Clone, Debug)]
+pub enum Corruption {
+    /// snapshot directory is gone
+    MissingSnapshot {
+        state_hash: Hash,
+        path: PathBuf,
+        first_use: Option<InputId>,
+    },
+    /// snapshot directory doesn't hold a loadable machine
+    UnreadableSnapshot {
+        state_hash: Hash,
+        path: PathBuf,
+        first_use: Option<InputId>,
+        error: String,
+    },
+    /// machine on disk doesn't have the state hash it is stored under
+    SnapshotHashMismatch {
+        state_hash: Hash,
+        path: PathBuf,
+        first_use: Option<InputId>,
+        actual: Hash,
+    },
+    /// settled computation hash isn't the commitment of the stored state hashes
+    ComputationHashMismatch {
+        epoch_number: u64,
+        stored: Digest,
+        computed: Digest,
+    },
+}
+
+impl Corruption {
+    /// State hash of the corrupted snapshot, if this is a snapshot corruption.
+    pub fn snapshot_hash(&self) -> Option<&Hash> {
+        match self {
+            Corruption::MissingSnapshot { state_hash, .. }
+            | Corruption::UnreadableSnapshot { state_hash, .. }
+            | Corruption::SnapshotHashMismatch { state_hash, .. } => Some(state_hash),
+            Corruption::ComputationHashMismatch { .. } => None,
+        }
+    }
+
+    /// Earliest epoch and input whose rollup data is affected, if any.
+    pub fn first_affected(&self) -> Option<InputId> {
+        match self {
+            Corruption::MissingSnapshot { first_use, .. }
+            | Corruption::UnreadableSnapshot { first_use, .. }
+            | Corruption::SnapshotHashMismatch { first_use, .. } => first_use.clone(),
+            Corruption::ComputationHashMismatch { epoch_number, .. } => Some(InputId {
+                epoch_number: *epoch_number,
+                input_index_in_epoch: 0,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Corruption::MissingSnapshot {
+                state_hash, path, ..
+            } => write!(
+                f,
+                "snapshot 0x{} is missing from `{}`",
+                hex::encode(state_hash),
+                path.display()
+            ),
+            Corruption::UnreadableSnapshot {
+                state_hash,
+                path,
+                error,
+                ..
+            } => write!(
+                f,
+                "snapshot 0x{} at `{}` can't be loaded: {}",
+                hex::encode(state_hash),
+                path.display(),
+                error
+            ),
+            Corruption::SnapshotHashMismatch {
+                state_hash,
+                path,
+                actual,
+                ..
+            } => write!(
+                f,
+                "snapshot 0x{} at `{}` holds machine 0x{}",
+                hex::encode(state_hash),
+                path.display(),
+                hex::encode(actual)
+            ),
+            Corruption::ComputationHashMismatch {
+                epoch_number,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "computation hash of epoch {} is {}, but its state hashes commit to {}",
+                epoch_number, stored, computed
+            ),
+        }
+    }
+}
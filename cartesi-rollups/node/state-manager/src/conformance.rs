@@ -0,0 +1,286 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Behaviour every [StateManager] implementation must share, run by the tests of each of them.
+
+use std::path::{Path, PathBuf};
+
+use alloy::primitives::Address;
+use cartesi_machine::{
+    Machine,
+    config::{
+        machine::{MachineConfig, RAMConfig},
+        runtime::RuntimeConfig,
+    },
+};
+use cartesi_prt_core::strategy::scheduler::Deadline;
+
+use crate::{
+    CommitmentLeaf, Epoch, Input, InputExecution, InputId, InputProgress, StateAccessError,
+    StateManager, persistent_state_access::build_commitment_from_hashes, rollups_machine,
+    state_manager::Result,
+};
+
+/// Stores a bare machine in `dir`, to be used as the template machine.
+pub fn create_machine(dir: &Path) -> PathBuf {
+    let machine_path = dir.join("_my_machine_image");
+    let mut machine = Machine::create(
+        &MachineConfig::new_with_ram(RAMConfig {
+            length: 134217728,
+            image_filename: "../../../test/programs/linux.bin".into(),
+        }),
+        &RuntimeConfig::default(),
+    )
+    .unwrap();
+    machine.store(&machine_path).unwrap();
+
+    machine_path
+}
+
+/// Runs the whole suite, each part against a fresh state created by `setup` at genesis block zero.
+/// The first element returned by `setup` is kept alive until the part is done.
+pub fn run<H, SM: StateManager>(mut setup: impl FnMut() -> (H, SM)) -> Result<()> {
+    let (_handle, mut state_manager) = setup();
+    consensus_data(&mut state_manager)?;
+
+    let (_handle, mut state_manager) = setup();
+    rollup_data(&mut state_manager)?;
+
+    let (_handle, mut state_manager) = setup();
+    input_progress(&mut state_manager)?;
+
+    let (_handle, mut state_manager) = setup();
+    deadlines(&mut state_manager)?;
+
+    Ok(())
+}
+
+fn input(epoch_number: u64, input_index_in_epoch: u64) -> Input {
+    Input {
+        id: InputId {
+            epoch_number,
+            input_index_in_epoch,
+        },
+        data: vec![epoch_number as u8, input_index_in_epoch as u8],
+        metadata: None,
+    }
+}
+
+fn epoch(epoch_number: u64, input_index_boundary: u64) -> Epoch {
+    Epoch {
+        epoch_number,
+        input_index_boundary,
+        root_tournament: Address::ZERO,
+        block_created_number: epoch_number * 10,
+    }
+}
+
+fn consensus_data(state_manager: &mut impl StateManager) -> Result<()> {
+    assert_eq!(state_manager.latest_processed_block()?, 0);
+    assert_eq!(state_manager.epoch_count()?, 0);
+    assert!(state_manager.last_input()?.is_none());
+    assert!(state_manager.last_sealed_epoch()?.is_none());
+
+    state_manager.insert_consensus_data(
+        20,
+        [&input(0, 0), &input(0, 1)].into_iter(),
+        [&epoch(0, 2)].into_iter(),
+    )?;
+
+    assert!(matches!(
+        state_manager.insert_consensus_data(20, [].into_iter(), [].into_iter()),
+        Err(StateAccessError::InconsistentLastProcessed {
+            last: 20,
+            provided: 20
+        })
+    ));
+    assert!(matches!(
+        state_manager.insert_consensus_data(21, [&input(0, 1)].into_iter(), [].into_iter()),
+        Err(StateAccessError::InconsistentInput { .. })
+    ));
+    assert!(matches!(
+        state_manager.insert_consensus_data(21, [&input(0, 3)].into_iter(), [].into_iter()),
+        Err(StateAccessError::InconsistentInput { .. })
+    ));
+    assert!(matches!(
+        state_manager.insert_consensus_data(
+            21,
+            [&input(1, 0)].into_iter(),
+            [&epoch(2, 3)].into_iter()
+        ),
+        Err(StateAccessError::InconsistentEpoch {
+            expected: 1,
+            provided: 2
+        })
+    ));
+
+    // failed insertions leave no trace
+    assert_eq!(state_manager.latest_processed_block()?, 20);
+    assert_eq!(state_manager.input_count(0)?, 2);
+    assert_eq!(state_manager.input_count(1)?, 0);
+    assert_eq!(state_manager.epoch_count()?, 1);
+
+    state_manager.insert_consensus_data(
+        21,
+        [&input(1, 0)].into_iter(),
+        [&epoch(1, 3)].into_iter(),
+    )?;
+
+    assert_eq!(state_manager.latest_processed_block()?, 21);
+    assert_eq!(state_manager.epoch_count()?, 2);
+    assert_eq!(state_manager.epoch(0)?.unwrap().input_index_boundary, 2);
+    assert!(state_manager.epoch(2)?.is_none());
+    assert_eq!(state_manager.last_sealed_epoch()?.unwrap().epoch_number, 1);
+    assert_eq!(
+        state_manager.inputs(0)?,
+        vec![input(0, 0).data, input(0, 1).data]
+    );
+    assert_eq!(
+        state_manager.input(&input(1, 0).id)?.unwrap().data,
+        input(1, 0).data
+    );
+    assert!(state_manager.input(&input(1, 1).id)?.is_none());
+
+    let last = state_manager.last_input()?.unwrap();
+    assert_eq!((last.epoch_number, last.input_index_in_epoch), (1, 0));
+
+    Ok(())
+}
+
+fn rollup_data(state_manager: &mut impl StateManager) -> Result<()> {
+    let leaf_1 = CommitmentLeaf {
+        hash: [1; 32],
+        repetitions: 1,
+    };
+    let leaf_2 = CommitmentLeaf {
+        hash: [2; 32],
+        repetitions: 5,
+    };
+
+    let next = state_manager.next_input_id()?;
+    assert_eq!((next.epoch_number, next.input_index_in_epoch), (0, 0));
+    assert!(state_manager.epoch_state_hashes(0)?.is_empty());
+
+    let mut machine = state_manager.latest_snapshot()?;
+    assert_eq!(machine.epoch(), 0);
+
+    machine.increment_input();
+    state_manager.advance_accepted(&mut machine, std::slice::from_ref(&leaf_1))?;
+    assert_eq!(
+        state_manager.epoch_state_hashes(0)?,
+        vec![CommitmentLeaf {
+            hash: leaf_1.hash,
+            repetitions: rollups_machine::STRIDE_COUNT_IN_EPOCH,
+        }]
+    );
+    let next = state_manager.next_input_id()?;
+    assert_eq!((next.epoch_number, next.input_index_in_epoch), (0, 1));
+
+    let execution = InputExecution {
+        start_mcycle: 0,
+        end_mcycle: 100,
+        stride_count: 1,
+        reason: Some(0),
+        wall_time: std::time::Duration::from_millis(3),
+        output_count: 1,
+    };
+    let id = input(0, 0).id;
+    assert!(state_manager.input_execution(&id)?.is_none());
+    state_manager.insert_input_execution(&id, &execution)?;
+    assert_eq!(state_manager.input_execution(&id)?, Some(execution.clone()));
+    assert_eq!(state_manager.input_executions(0)?, vec![execution]);
+    assert!(state_manager.input_executions(1)?.is_empty());
+
+    machine.increment_input();
+    state_manager.advance_reverted(&mut machine, std::slice::from_ref(&leaf_2))?;
+    assert_eq!(machine.next_input_index_in_epoch(), 2);
+    assert_eq!(state_manager.epoch_state_hashes(0)?.len(), 2);
+    let next = state_manager.next_input_id()?;
+    assert_eq!((next.epoch_number, next.input_index_in_epoch), (0, 2));
+
+    // intermediate snapshots are dropped, the boundary one is kept
+    assert!(state_manager.snapshot_dir(0, 1)?.is_none());
+    assert!(state_manager.snapshot_dir(0, 0)?.unwrap().is_dir());
+
+    assert!(state_manager.settlement_info(0)?.is_none());
+    let (output_merkle, output_proof) = machine.outputs_proof()?;
+    state_manager.roll_epoch()?;
+
+    let settlement = state_manager.settlement_info(0)?.unwrap();
+    assert_eq!(
+        settlement.computation_hash,
+        build_commitment_from_hashes(&[leaf_1, leaf_2])
+    );
+    assert_eq!(settlement.output_merkle, output_merkle);
+    assert_eq!(settlement.output_proof, output_proof);
+
+    assert_eq!(state_manager.latest_snapshot()?.epoch(), 1);
+    let next = state_manager.next_input_id()?;
+    assert_eq!((next.epoch_number, next.input_index_in_epoch), (1, 0));
+    assert!(state_manager.snapshot_dir(1, 0)?.unwrap().is_dir());
+    assert!(state_manager.epoch_directory(1)?.is_dir());
+
+    Ok(())
+}
+
+fn input_progress(state_manager: &mut impl StateManager) -> Result<()> {
+    let leaf = |i| CommitmentLeaf {
+        hash: [i; 32],
+        repetitions: 1,
+    };
+
+    assert!(state_manager.input_progress()?.is_none());
+
+    let mut machine = state_manager.latest_snapshot()?;
+    let mut progress = InputProgress {
+        checkpoint_hash: machine.state_hash()?,
+        leafs: vec![leaf(1), leaf(2)],
+        start_mcycle: 0,
+        output_count: 1,
+    };
+    state_manager.save_input_progress(&mut machine, &progress)?;
+
+    progress.leafs.push(leaf(3));
+    progress.output_count = 2;
+    state_manager.save_input_progress(&mut machine, &progress)?;
+
+    let (mut resumed, saved) = state_manager.input_progress()?.unwrap();
+    assert_eq!(saved, progress);
+    assert_eq!(resumed.state_hash()?, machine.state_hash()?);
+    assert_eq!(
+        (resumed.epoch(), resumed.next_input_index_in_epoch()),
+        (0, 0)
+    );
+
+    // advancing the input drops its progress
+    machine.increment_input();
+    state_manager.advance_accepted(&mut machine, &[leaf(4)])?;
+    assert!(state_manager.input_progress()?.is_none());
+
+    Ok(())
+}
+
+fn deadlines(state_manager: &mut impl StateManager) -> Result<()> {
+    assert!(state_manager.deadlines()?.is_empty());
+
+    let deadline = |own, block| Deadline {
+        tournament: Address::repeat_byte(1),
+        commitment: [2; 32].into(),
+        own,
+        block,
+        block_number: 10,
+    };
+    state_manager.set_deadlines(&[deadline(false, 30), deadline(true, 20)])?;
+    assert_eq!(
+        state_manager.deadlines()?,
+        vec![deadline(true, 20), deadline(false, 30)]
+    );
+
+    // a reaction replaces every deadline observed before it
+    state_manager.set_deadlines(&[deadline(false, 40)])?;
+    assert_eq!(state_manager.deadlines()?, vec![deadline(false, 40)]);
+    state_manager.set_deadlines(&[])?;
+    assert!(state_manager.deadlines()?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,330 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Capture of the application console into a log file per input. The machine writes its console
+//! straight to the standard output of the thread running it, so each input runs on a thread of its
+//! own, with a file descriptor table of its own where standard output is the log. The rest of the
+//! process, and any other capture, keeps its own standard output. Whether the console is captured
+//! has no effect on state hashes.
+//!
+//! Only Linux lets a thread have a file descriptor table of its own. Elsewhere the console isn't
+//! captured, as if there was no [ConsoleCapture].
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{Mutex, mpsc},
+    thread,
+};
+
+use log::warn;
+
+const CONSOLE_DIR: &str = "console";
+
+/// Whether the console can be captured on this platform.
+pub const SUPPORTED: bool = cfg!(target_os = "linux");
+
+/// Write ends of the pipes of the logs being written. The table a runner gets has a copy of each,
+/// which it closes, as the log they belong to would otherwise only be finished once the runner is.
+static WRITERS: Mutex<Vec<RawFd>> = Mutex::new(Vec::new());
+
+/// Where and how much of the application console is kept.
+#[derive(Clone, Debug)]
+pub struct ConsoleCapture {
+    dir: PathBuf,
+    /// bytes kept per input, the rest is dropped
+    pub max_file_size: u64,
+    /// logs kept, the ones of the oldest inputs are removed first
+    pub max_files: usize,
+}
+
+impl ConsoleCapture {
+    /// Captures into the `console` directory of `state_dir`.
+    pub fn new(state_dir: &Path, max_file_size: u64, max_files: usize) -> Self {
+        Self {
+            dir: state_dir.join(CONSOLE_DIR),
+            max_file_size,
+            max_files,
+        }
+    }
+
+    pub fn log_path(&self, epoch_number: u64, input_number: u64) -> PathBuf {
+        self.dir.join(format!("{epoch_number}-{input_number}.log"))
+    }
+
+    /// Starts the log of `input_number` of `epoch_number`, which gets the console of whatever
+    /// [ConsoleLog::run] runs until it is finished. A log left by an earlier run of the same input,
+    /// which was interrupted, is appended to.
+    pub fn start(&self, epoch_number: u64, input_number: u64) -> io::Result<ConsoleLog> {
+        fs::create_dir_all(&self.dir)?;
+        let mut log = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(self.log_path(epoch_number, input_number))?;
+
+        // what the interrupted run logged counts towards the size kept
+        let left = self.max_file_size.saturating_sub(log.metadata()?.len());
+
+        let (mut reader, writer) = {
+            let mut writers = WRITERS.lock().unwrap();
+            let (reader, writer) = pipe()?;
+            writers.push(writer.as_raw_fd());
+            (reader, writer)
+        };
+        let copier = thread::spawn(move || -> io::Result<()> {
+            io::copy(&mut (&mut reader).take(left), &mut log)?;
+            // keep draining, so the machine never blocks on a full pipe
+            io::copy(&mut reader, &mut io::sink())?;
+            Ok(())
+        });
+
+        let (jobs, pending) = mpsc::channel::<Job>();
+        let fd = writer.as_raw_fd();
+        let runner = thread::spawn(move || {
+            if let Err(e) = redirect_stdout(fd) {
+                warn!("could not capture console of input {epoch_number}:{input_number}: {e}");
+            }
+            for job in pending {
+                job();
+            }
+        });
+
+        Ok(ConsoleLog {
+            capture: self.clone(),
+            epoch_number,
+            input_number,
+            jobs,
+            runner,
+            writer,
+            copier,
+        })
+    }
+}
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Console log of an input being processed.
+#[derive(Debug)]
+pub struct ConsoleLog {
+    capture: ConsoleCapture,
+    epoch_number: u64,
+    input_number: u64,
+    /// jobs for the thread whose standard output goes to the log
+    jobs: mpsc::Sender<Job>,
+    runner: thread::JoinHandle<()>,
+    writer: OwnedFd,
+    copier: thread::JoinHandle<io::Result<()>>,
+}
+
+impl ConsoleLog {
+    /// Runs `f` on the thread whose standard output goes to the log, waiting for it. If it
+    /// couldn't be redirected, `f` runs anyway and the failure was only warned about.
+    pub fn run<T: Send>(&self, f: impl FnOnce() -> T + Send) -> T {
+        let (result, done) = mpsc::sync_channel(1);
+        let job: Box<dyn FnOnce() + Send + '_> = Box::new(move || {
+            let _ = result.send(panic::catch_unwind(AssertUnwindSafe(f)));
+        });
+        // SAFETY: only the lifetime is erased. The job is waited for below, before anything it
+        // borrows can go away, and it always sends its result, catching any panic.
+        let job: Job = unsafe { std::mem::transmute(job) };
+
+        self.jobs
+            .send(job)
+            .expect("console runner should outlive its log");
+        match done.recv().expect("console runner should finish every job") {
+            Ok(result) => result,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+
+    /// Waits for the log to be written, then removes the logs of the oldest inputs past
+    /// `max_files`. Failures are only warned about.
+    pub fn finish(self) {
+        let Self {
+            capture,
+            epoch_number,
+            input_number,
+            jobs,
+            runner,
+            writer,
+            copier,
+        } = self;
+
+        // the copy of the write end of the pipe in the table of the runner goes with it, then the
+        // one of the process, the last one left
+        drop(jobs);
+        if runner.join().is_err() {
+            warn!("console runner of input {epoch_number}:{input_number} panicked");
+        }
+        {
+            let mut writers = WRITERS.lock().unwrap();
+            writers.retain(|&fd| fd != writer.as_raw_fd());
+            drop(writer);
+        }
+        match copier.join() {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                warn!("could not finish console log of input {epoch_number}:{input_number}: {e}")
+            }
+            Err(_) => warn!("console copier of input {epoch_number}:{input_number} panicked"),
+        }
+        if let Err(e) = rotate(&capture.dir, capture.max_files) {
+            warn!(
+                "could not rotate console logs in `{}`: {e}",
+                capture.dir.display()
+            );
+        }
+    }
+}
+
+/// Points the standard output of the calling thread, and only of it, at `writer`. The thread
+/// gets a copy of the file descriptor table of the process, closed when it exits, which leaves
+/// the table of the process and its locks alone.
+#[cfg(target_os = "linux")]
+fn redirect_stdout(writer: RawFd) -> io::Result<()> {
+    // no log starts or finishes while the table is copied
+    let writers = WRITERS.lock().unwrap();
+    cvt(unsafe { libc::unshare(libc::CLONE_FILES) })?;
+    for &other in writers.iter().filter(|&&other| other != writer) {
+        unsafe { libc::close(other) };
+    }
+    cvt(unsafe { libc::dup2(writer, libc::STDOUT_FILENO) })?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn redirect_stdout(_writer: RawFd) -> io::Result<()> {
+    Err(unsupported())
+}
+
+/// Removes the logs of the oldest inputs in `dir`, keeping `max_files` of them.
+pub(crate) fn rotate(dir: &Path, max_files: usize) -> io::Result<()> {
+    let mut logs: Vec<((u64, u64), PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let (epoch, input) = path.file_stem()?.to_str()?.split_once('-')?;
+            Some(((epoch.parse().ok()?, input.parse().ok()?), path))
+        })
+        .collect();
+
+    if logs.len() <= max_files {
+        return Ok(());
+    }
+
+    logs.sort();
+    for (_, path) in &logs[..logs.len() - max_files] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn pipe() -> io::Result<(File, OwnedFd)> {
+    use std::os::fd::FromRawFd;
+
+    let mut fds = [0; 2];
+    cvt(unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) })?;
+    unsafe { Ok((File::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))) }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn pipe() -> io::Result<(File, OwnedFd)> {
+    Err(unsupported())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn unsupported() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        "console capture is only supported on Linux",
+    )
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn cvt(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret == -1 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotate_keeps_latest_inputs() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let capture = ConsoleCapture::new(dir.path(), 1024, 3);
+        fs::create_dir_all(&capture.dir)?;
+        for (epoch, input) in [(0, 9), (1, 0), (0, 10), (2, 1), (1, 2)] {
+            fs::write(capture.log_path(epoch, input), b"hello")?;
+        }
+
+        rotate(&capture.dir, capture.max_files)?;
+
+        assert!(!capture.log_path(0, 9).exists());
+        assert!(!capture.log_path(0, 10).exists());
+        assert!(capture.log_path(1, 0).exists());
+        assert!(capture.log_path(1, 2).exists());
+        assert!(capture.log_path(2, 1).exists());
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    fn write_stdout(data: &[u8]) {
+        let written = unsafe { libc::write(libc::STDOUT_FILENO, data.as_ptr().cast(), data.len()) };
+        assert_eq!(written, data.len() as isize);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_concurrent_captures() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let capture = ConsoleCapture::new(dir.path(), 1024, 3);
+        let short_capture = ConsoleCapture::new(dir.path(), 4, 3);
+
+        let first = capture.start(0, 1)?;
+        let second = short_capture.start(0, 2)?;
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..100 {
+                    first.run(|| write_stdout(b"first "));
+                }
+            });
+            s.spawn(|| {
+                for _ in 0..100 {
+                    second.run(|| write_stdout(b"second "));
+                }
+            });
+        });
+        first.finish();
+        second.finish();
+
+        // each log only has what its own runs printed, the second one cut short
+        assert_eq!(fs::read(capture.log_path(0, 1))?, b"first ".repeat(100));
+        assert_eq!(fs::read(capture.log_path(0, 2))?, b"seco");
+        Ok(())
+    }
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_restarted_input_appends_to_log() -> io::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let capture = ConsoleCapture::new(dir.path(), 1024, 3);
+
+        // the node goes down midway through the input, then runs it again
+        for run in [&b"interrupted "[..], b"resumed"] {
+            let log = capture.start(0, 1)?;
+            log.run(|| write_stdout(run));
+            log.run(|| write_stdout(b" "));
+            log.finish();
+        }
+
+        assert_eq!(fs::read(capture.log_path(0, 1))?, b"interrupted  resumed ");
+        Ok(())
+    }
+}
@@ -0,0 +1,620 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    CommitmentLeaf, Epoch, Input, InputExecution, InputId, InputProgress, Settlement, StateManager,
+    open_epoch::OpenEpoch,
+    retention::{EpochSnapshots, SnapshotRetention},
+    rollups_machine::{self, RollupsMachine},
+    sql::{
+        create_directory_structure, create_epoch_dir, input_progress_machine_path,
+        input_progress_path, prune_input_progress, snapshots_path,
+    },
+    state_manager::{Result, StateAccessError},
+};
+
+use anyhow::Context;
+use cartesi_machine::types::Hash;
+use cartesi_prt_core::strategy::scheduler::Deadline;
+use tempfile::TempDir;
+
+/// [StateManager] that keeps its data in memory instead of SQLite, for tests and ephemeral nodes.
+/// Machine snapshots still live on disk, in a temporary directory removed on drop. Only the
+/// boundary snapshots of the two most recent epochs are kept.
+#[derive(Debug)]
+pub struct InMemoryStateAccess {
+    // consensus data
+    last_processed_block: u64,
+    epochs: BTreeMap<u64, Epoch>,
+    inputs: BTreeMap<(u64, u64), Input>,
+
+    // rollup data
+    state_hashes: BTreeMap<(u64, u64), Vec<CommitmentLeaf>>,
+    settlements: BTreeMap<u64, Settlement>,
+    input_executions: BTreeMap<(u64, u64), InputExecution>,
+    deadlines: Vec<Deadline>,
+    snapshots: BTreeMap<Hash, PathBuf>,
+    epoch_snapshots: BTreeMap<(u64, u64), Hash>,
+    template_hash: Hash,
+    halted: Option<InputId>,
+    input_progress: Option<(InputId, PathBuf, InputProgress)>,
+    open_epoch: Option<OpenEpoch>,
+
+    state_dir: TempDir,
+}
+
+impl InMemoryStateAccess {
+    pub fn new(initial_machine_path: &Path, genesis_block_number: u64) -> Result<Self> {
+        let state_dir = tempfile::tempdir().context("creating temporary state directory")?;
+        create_directory_structure(state_dir.path())?;
+
+        let mut machine = RollupsMachine::new(initial_machine_path, 0, 0)?;
+        let (dest_dir, state_hash) = machine
+            .store_if_needed(&snapshots_path(state_dir.path()))
+            .map_err(anyhow::Error::from)?;
+
+        Ok(Self {
+            last_processed_block: genesis_block_number,
+            epochs: BTreeMap::new(),
+            inputs: BTreeMap::new(),
+            state_hashes: BTreeMap::new(),
+            settlements: BTreeMap::new(),
+            input_executions: BTreeMap::new(),
+            deadlines: Vec::new(),
+            snapshots: BTreeMap::from([(state_hash, dest_dir)]),
+            epoch_snapshots: BTreeMap::from([((0, 0), state_hash)]),
+            template_hash: state_hash,
+            halted: None,
+            input_progress: None,
+            open_epoch: None,
+            state_dir,
+        })
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        self.state_dir.path()
+    }
+}
+
+impl InMemoryStateAccess {
+    fn insert_state_hashes_for_input(
+        &mut self,
+        epoch_number: u64,
+        input_number: u64,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        if self
+            .state_hashes
+            .contains_key(&(epoch_number, input_number))
+        {
+            return Err(StateAccessError::DuplicateEntry {
+                description: format!("state hashes of input {epoch_number}:{input_number}"),
+            });
+        }
+
+        self.state_hashes
+            .insert((epoch_number, input_number), leafs.to_vec());
+        Ok(())
+    }
+
+    fn insert_snapshot(
+        &mut self,
+        epoch_number: u64,
+        input_number: u64,
+        state_hash: Hash,
+        dest_dir: PathBuf,
+    ) {
+        self.snapshots.entry(state_hash).or_insert(dest_dir);
+        self.epoch_snapshots
+            .entry((epoch_number, input_number))
+            .or_insert(state_hash);
+    }
+
+    fn latest_snapshot_path(&self) -> (PathBuf, u64, u64) {
+        let (&(epoch_number, input_number), state_hash) = self
+            .epoch_snapshots
+            .last_key_value()
+            .expect("there should at least be a single machine");
+
+        (
+            self.snapshots[state_hash].clone(),
+            epoch_number,
+            input_number,
+        )
+    }
+
+    /// Takes the open epoch with every input before `next_input_index` advanced, rebuilding it
+    /// from the stored state hashes unless it was kept. Whoever advances it puts it back.
+    fn take_open_epoch(&mut self, epoch_number: u64, next_input_index: u64) -> OpenEpoch {
+        match self.open_epoch.take() {
+            Some(open_epoch) if open_epoch.is_at(epoch_number, next_input_index) => open_epoch,
+            _ => {
+                let leafs: Vec<CommitmentLeaf> = self
+                    .state_hashes
+                    .range((epoch_number, 0)..=(epoch_number, u64::MAX))
+                    .flat_map(|(_, leafs)| leafs.iter().cloned())
+                    .collect();
+                OpenEpoch::new(epoch_number, next_input_index, &leafs)
+            }
+        }
+    }
+
+    fn clear_input_progress(&mut self) -> Result<()> {
+        self.input_progress = None;
+        prune_input_progress(self.state_dir.path(), None)
+    }
+
+    fn gc_previous_advances(&mut self, epoch: u64, input_anchor: u64) -> Result<()> {
+        self.epoch_snapshots
+            .retain(|&(e, i), _| e != epoch || i == input_anchor || i == 0);
+        self.gc_orphan_snapshots()
+    }
+
+    /// Drops the snapshots of epochs settled before `current_epoch`.
+    fn gc_old_epochs(&mut self, current_epoch: u64) -> Result<()> {
+        SnapshotRetention::default().gc_old_epochs(self, current_epoch)
+    }
+
+    /// Snapshots no longer used by any epoch nor by the template machine.
+    fn orphan_hashes(&self) -> Vec<Hash> {
+        let used: HashSet<Hash> = self
+            .epoch_snapshots
+            .values()
+            .copied()
+            .chain([self.template_hash])
+            .collect();
+
+        self.snapshots
+            .keys()
+            .filter(|h| !used.contains(*h))
+            .copied()
+            .collect()
+    }
+}
+
+impl EpochSnapshots for InMemoryStateAccess {
+    fn snapshot_epochs_before(&mut self, epoch_number: u64) -> Result<Vec<u64>> {
+        let epochs: BTreeSet<u64> = self
+            .epoch_snapshots
+            .range(..(epoch_number, 0))
+            .map(|(&(e, _), _)| e)
+            .collect();
+        Ok(epochs.into_iter().collect())
+    }
+
+    fn boundary_snapshot_path(&mut self, epoch_number: u64) -> Result<Option<PathBuf>> {
+        Ok(self
+            .epoch_snapshots
+            .get(&(epoch_number, 0))
+            .map(|state_hash| self.snapshots[state_hash].clone()))
+    }
+
+    fn delete_epoch_snapshots(&mut self, epoch_number: u64, keep_boundary: bool) -> Result<()> {
+        self.epoch_snapshots
+            .retain(|&(e, i), _| e != epoch_number || (keep_boundary && i == 0));
+        Ok(())
+    }
+
+    fn orphan_snapshots(&mut self) -> Result<Vec<PathBuf>> {
+        Ok(self
+            .orphan_hashes()
+            .iter()
+            .map(|state_hash| self.snapshots[state_hash].clone())
+            .collect())
+    }
+
+    fn gc_orphan_snapshots(&mut self) -> Result<()> {
+        for state_hash in self.orphan_hashes() {
+            let path = self.snapshots.remove(&state_hash).unwrap();
+            match std::fs::remove_dir_all(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(anyhow::Error::from(e)
+                        .context(format!("deleting `{}`", path.display()))
+                        .into());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl StateManager for InMemoryStateAccess {
+    //
+    // Consensus Data
+    //
+
+    fn epoch(&mut self, epoch_number: u64) -> Result<Option<Epoch>> {
+        Ok(self.epochs.get(&epoch_number).cloned())
+    }
+
+    fn epoch_count(&mut self) -> Result<u64> {
+        Ok(self.epochs.keys().next_back().map_or(0, |e| e + 1))
+    }
+
+    fn last_sealed_epoch(&mut self) -> Result<Option<Epoch>> {
+        Ok(self.epochs.values().next_back().cloned())
+    }
+
+    fn input(&mut self, id: &InputId) -> Result<Option<Input>> {
+        Ok(self
+            .inputs
+            .get(&(id.epoch_number, id.input_index_in_epoch))
+            .cloned())
+    }
+
+    fn inputs(&mut self, epoch_number: u64) -> Result<Vec<Vec<u8>>> {
+        Ok(self
+            .inputs
+            .range((epoch_number, 0)..=(epoch_number, u64::MAX))
+            .map(|(_, input)| input.data.clone())
+            .collect())
+    }
+
+    fn input_count(&mut self, epoch_number: u64) -> Result<u64> {
+        Ok(self
+            .inputs
+            .range((epoch_number, 0)..=(epoch_number, u64::MAX))
+            .next_back()
+            .map_or(0, |((_, i), _)| i + 1))
+    }
+
+    fn last_input(&mut self) -> Result<Option<InputId>> {
+        Ok(self
+            .inputs
+            .keys()
+            .next_back()
+            .map(|&(epoch_number, input_index_in_epoch)| InputId {
+                epoch_number,
+                input_index_in_epoch,
+            }))
+    }
+
+    fn insert_consensus_data<'a>(
+        &mut self,
+        last_processed_block: u64,
+        inputs: impl Iterator<Item = &'a Input>,
+        epochs: impl Iterator<Item = &'a Epoch>,
+    ) -> Result<()> {
+        // validate everything before applying anything, like the SQLite transaction would
+        if self.last_processed_block >= last_processed_block {
+            return Err(StateAccessError::InconsistentLastProcessed {
+                last: self.last_processed_block,
+                provided: last_processed_block,
+            });
+        }
+
+        let mut current_input = self.last_input()?;
+        let inputs: Vec<&Input> = inputs.collect();
+        for input in &inputs {
+            let valid = match &current_input {
+                Some(i) => i.validate_next(&input.id),
+                None => input.id.input_index_in_epoch == 0,
+            };
+            if !valid {
+                return Err(StateAccessError::InconsistentInput {
+                    previous: current_input,
+                    provided: input.id.clone(),
+                });
+            }
+            current_input = Some(input.id.clone());
+        }
+
+        let mut next_epoch = self.epoch_count()?;
+        let epochs: Vec<&Epoch> = epochs.collect();
+        for epoch in &epochs {
+            if epoch.epoch_number != next_epoch {
+                return Err(StateAccessError::InconsistentEpoch {
+                    expected: next_epoch,
+                    provided: epoch.epoch_number,
+                });
+            }
+            next_epoch += 1;
+        }
+
+        self.last_processed_block = last_processed_block;
+        for input in inputs {
+            self.inputs.insert(
+                (input.id.epoch_number, input.id.input_index_in_epoch),
+                input.clone(),
+            );
+        }
+        for epoch in epochs {
+            self.epochs.insert(epoch.epoch_number, epoch.clone());
+        }
+
+        Ok(())
+    }
+
+    fn latest_processed_block(&mut self) -> Result<u64> {
+        Ok(self.last_processed_block)
+    }
+
+    //
+    // Rollup Data
+    //
+    fn advance_accepted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        assert!(!leafs.is_empty());
+        let epoch = machine.epoch();
+        let next_input_index = machine.next_input_index_in_epoch();
+        let processed_input_index = next_input_index - 1;
+        let mut open_epoch = self.take_open_epoch(epoch, processed_input_index);
+
+        self.insert_state_hashes_for_input(epoch, processed_input_index, leafs)?;
+
+        let (dest_dir, state_hash) = machine
+            .store_if_needed(&snapshots_path(self.state_dir.path()))
+            .map_err(anyhow::Error::from)?;
+
+        self.insert_snapshot(epoch, next_input_index, state_hash, dest_dir);
+        self.gc_previous_advances(epoch, next_input_index)?;
+        self.clear_input_progress()?;
+
+        open_epoch.advance(leafs);
+        open_epoch.set_outputs(machine.outputs_proof()?);
+        self.open_epoch = Some(open_epoch);
+        Ok(())
+    }
+
+    fn advance_reverted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        assert!(!leafs.is_empty());
+        let epoch = machine.epoch();
+        let next_input_index = machine.next_input_index_in_epoch();
+        let processed_input_index = next_input_index - 1;
+        let mut open_epoch = self.take_open_epoch(epoch, processed_input_index);
+
+        self.insert_state_hashes_for_input(epoch, processed_input_index, leafs)?;
+
+        let (snapshot_path, snapshot_epoch, snapshot_input) = self.latest_snapshot_path();
+
+        assert_eq!(snapshot_epoch, epoch);
+        assert_eq!(snapshot_input, processed_input_index);
+
+        // load rollups machine from previous successful (ACCEPT) snapshot
+        let mut reverted_machine = RollupsMachine::new(&snapshot_path, epoch, next_input_index)?;
+
+        let state_hash = reverted_machine.state_hash()?;
+        self.insert_snapshot(epoch, next_input_index, state_hash, snapshot_path);
+        self.gc_previous_advances(epoch, next_input_index)?;
+        self.clear_input_progress()?;
+
+        // a rejected input leaves the outputs as they were
+        open_epoch.advance(leafs);
+        self.open_epoch = Some(open_epoch);
+
+        // Update the passed machine to match the reverted state
+        *machine = reverted_machine;
+        Ok(())
+    }
+
+    fn insert_input_execution(&mut self, id: &InputId, execution: &InputExecution) -> Result<()> {
+        self.input_executions.insert(
+            (id.epoch_number, id.input_index_in_epoch),
+            execution.clone(),
+        );
+        Ok(())
+    }
+
+    fn input_execution(&mut self, id: &InputId) -> Result<Option<InputExecution>> {
+        Ok(self
+            .input_executions
+            .get(&(id.epoch_number, id.input_index_in_epoch))
+            .cloned())
+    }
+
+    fn input_executions(&mut self, epoch_number: u64) -> Result<Vec<InputExecution>> {
+        Ok(self
+            .input_executions
+            .range((epoch_number, 0)..=(epoch_number, u64::MAX))
+            .map(|(_, execution)| execution.clone())
+            .collect())
+    }
+
+    fn set_deadlines(&mut self, deadlines: &[Deadline]) -> Result<()> {
+        self.deadlines = deadlines.to_vec();
+        self.deadlines.sort_by_key(|d| d.block);
+        Ok(())
+    }
+
+    fn deadlines(&mut self) -> Result<Vec<Deadline>> {
+        Ok(self.deadlines.clone())
+    }
+
+    fn save_input_progress(
+        &mut self,
+        machine: &mut RollupsMachine,
+        progress: &InputProgress,
+    ) -> Result<()> {
+        let id = InputId {
+            epoch_number: machine.epoch(),
+            input_index_in_epoch: machine.next_input_index_in_epoch(),
+        };
+        let state_dir = self.state_dir.path();
+        let path = input_progress_machine_path(
+            state_dir,
+            id.epoch_number,
+            id.input_index_in_epoch,
+            progress.leafs.len(),
+        );
+
+        let saved = self
+            .input_progress
+            .as_ref()
+            .map(|(_, path, _)| path.as_path());
+        prune_input_progress(state_dir, saved)?;
+        std::fs::create_dir_all(input_progress_path(state_dir)).map_err(anyhow::Error::from)?;
+        machine.store(&path).map_err(anyhow::Error::from)?;
+
+        prune_input_progress(state_dir, Some(&path))?;
+        self.input_progress = Some((id, path, progress.clone()));
+        Ok(())
+    }
+
+    fn input_progress(&mut self) -> Result<Option<(RollupsMachine, InputProgress)>> {
+        let Some((id, path, progress)) = self.input_progress.clone() else {
+            return Ok(None);
+        };
+        // progress left behind by an input that was advanced since is of no use
+        if id != self.next_input_id()? {
+            return Ok(None);
+        }
+
+        let machine = RollupsMachine::new(&path, id.epoch_number, id.input_index_in_epoch)?;
+        Ok(Some((machine, progress)))
+    }
+
+    fn advance_halted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        self.advance_accepted(machine, leafs)?;
+
+        let processed_input_index = machine.next_input_index_in_epoch() - 1;
+        self.halted.get_or_insert(InputId {
+            epoch_number: machine.epoch(),
+            input_index_in_epoch: processed_input_index,
+        });
+        Ok(())
+    }
+
+    fn halted_at(&mut self) -> Result<Option<InputId>> {
+        Ok(self.halted.clone())
+    }
+
+    fn next_input_id(&mut self) -> Result<InputId> {
+        let (&(epoch_number, input_index_in_epoch), _) = self
+            .epoch_snapshots
+            .last_key_value()
+            .expect("there should at least be a single latest processed");
+
+        Ok(InputId {
+            epoch_number,
+            input_index_in_epoch,
+        })
+    }
+
+    fn epoch_state_hashes(&mut self, epoch_number: u64) -> Result<Vec<CommitmentLeaf>> {
+        let mut leafs: Vec<CommitmentLeaf> = self
+            .state_hashes
+            .range((epoch_number, 0)..=(epoch_number, u64::MAX))
+            .flat_map(|(_, leafs)| leafs.iter().cloned())
+            .collect();
+
+        let total_reps = leafs.iter().fold(0, |acc, leaf| acc + leaf.repetitions);
+        if let Some(last) = leafs.last_mut() {
+            last.repetitions += rollups_machine::STRIDE_COUNT_IN_EPOCH - total_reps
+        }
+
+        Ok(leafs)
+    }
+
+    fn settlement_info(&mut self, epoch_number: u64) -> Result<Option<Settlement>> {
+        Ok(self.settlements.get(&epoch_number).cloned())
+    }
+
+    fn roll_epoch(&mut self) -> Result<()> {
+        let mut machine = self.latest_snapshot()?;
+        let previous_epoch_number = machine.epoch();
+
+        if self.settlements.contains_key(&previous_epoch_number) {
+            return Err(StateAccessError::DuplicateEntry {
+                description: format!("settlement of epoch {previous_epoch_number}"),
+            });
+        }
+
+        let settlement = self
+            .take_open_epoch(previous_epoch_number, machine.next_input_index_in_epoch())
+            .settlement(&mut machine)?;
+
+        machine.finish_epoch();
+
+        let new_epoch_number = machine.epoch();
+        create_epoch_dir(self.state_dir.path(), new_epoch_number)?;
+
+        let (dest_dir, state_hash) = machine
+            .store_if_needed(&snapshots_path(self.state_dir.path()))
+            .map_err(anyhow::Error::from)?;
+
+        self.insert_snapshot(new_epoch_number, 0, state_hash, dest_dir);
+        self.settlements.insert(previous_epoch_number, settlement);
+        self.open_epoch = Some(OpenEpoch::new(new_epoch_number, 0, &[]));
+
+        self.gc_old_epochs(new_epoch_number)
+    }
+
+    fn snapshot(&mut self, epoch_number: u64, input_number: u64) -> Result<Option<RollupsMachine>> {
+        let ret = if let Some(path) = self.snapshot_dir(epoch_number, input_number)? {
+            Some(RollupsMachine::new(&path, epoch_number, input_number)?)
+        } else {
+            None
+        };
+
+        Ok(ret)
+    }
+
+    fn latest_snapshot(&mut self) -> Result<RollupsMachine> {
+        let (path, epoch_number, input_number) = self.latest_snapshot_path();
+        Ok(RollupsMachine::new(&path, epoch_number, input_number)?)
+    }
+
+    fn snapshot_dir(&mut self, epoch_number: u64, input_number: u64) -> Result<Option<PathBuf>> {
+        Ok(self
+            .epoch_snapshots
+            .get(&(epoch_number, input_number))
+            .map(|state_hash| self.snapshots[state_hash].clone()))
+    }
+
+    //
+    // Directory
+    //
+
+    fn epoch_directory(&mut self, epoch_number: u64) -> Result<PathBuf> {
+        create_epoch_dir(self.state_dir.path(), epoch_number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance;
+
+    fn setup() -> (TempDir, InMemoryStateAccess) {
+        let handle = tempfile::tempdir().unwrap();
+        let machine_path = conformance::create_machine(handle.path());
+        let access = InMemoryStateAccess::new(&machine_path, 0).unwrap();
+
+        (handle, access)
+    }
+
+    #[test]
+    fn test_conformance() -> Result<()> {
+        conformance::run(setup)
+    }
+
+    #[test]
+    fn test_snapshots_live_in_state_dir() -> Result<()> {
+        let (_handle, mut access) = setup();
+        let state_dir = access.state_dir().to_owned();
+        assert!(access.snapshot_dir(0, 0)?.unwrap().starts_with(&state_dir));
+
+        drop(access);
+        assert!(!state_dir.exists());
+
+        Ok(())
+    }
+}
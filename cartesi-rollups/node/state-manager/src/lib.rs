@@ -1,19 +1,32 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+pub mod check;
+pub mod console;
+pub mod in_memory_state_access;
 pub mod persistent_state_access;
+pub mod postgres_state_access;
+pub mod processing;
+pub mod retention;
 pub mod rollups_machine;
 pub mod state_manager;
 pub mod sync;
 
-use alloy::primitives::Address;
+use alloy::primitives::{Address, U256};
 pub use state_manager::StateAccessError;
 pub use state_manager::StateManager;
 
+pub(crate) mod open_epoch;
+pub(crate) mod pg;
 pub(crate) mod sql;
+pub(crate) mod stride_hashes;
+
+#[cfg(test)]
+pub(crate) mod conformance;
 
 use cartesi_dave_merkle::Digest;
 use cartesi_machine::types::Hash;
+use std::{path::PathBuf, time::Duration};
 
 pub type Blob = Vec<u8>;
 
@@ -69,7 +82,33 @@ pub struct Settlement {
     pub output_proof: Proof,
 }
 
-#[derive(Clone, Debug, Default)]
+/// How an input ran on the application machine.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputExecution {
+    pub start_mcycle: u64,
+    pub end_mcycle: u64,
+    /// strides run before the machine yielded or halted, out of
+    /// [rollups_machine::STRIDE_COUNT_IN_INPUT]
+    pub stride_count: u64,
+    /// manual yield reason the input ended with, `None` if the machine halted, on this input or
+    /// before it
+    pub reason: Option<u16>,
+    pub wall_time: Duration,
+    pub output_count: u64,
+}
+
+/// How far an input that is still running got, enough to resume it on the machine stored with it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputProgress {
+    /// state hash before the input, which a rejected input reverts to
+    pub checkpoint_hash: Hash,
+    /// leafs of the strides run so far, each repeated once
+    pub leafs: Vec<CommitmentLeaf>,
+    pub start_mcycle: u64,
+    pub output_count: u64,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct InputId {
     pub epoch_number: u64,
     pub input_index_in_epoch: u64,
@@ -116,6 +155,21 @@ impl InputId {
 pub struct Input {
     pub id: InputId,
     pub data: Blob,
+    /// none for inputs stored before their metadata was kept
+    pub metadata: Option<InputMetadata>,
+}
+
+/// Metadata of an input, decoded from its `EvmAdvance` encoding and the log that added it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InputMetadata {
+    pub sender: Address,
+    pub block_number: u64,
+    pub block_timestamp: u64,
+    pub prev_randao: U256,
+    /// index of the input among all inputs of the application
+    pub index: u64,
+    pub transaction_hash: Hash,
+    pub log_index: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -125,3 +179,17 @@ pub struct Epoch {
     pub root_tournament: Address,
     pub block_created_number: u64,
 }
+
+/// Point a fresh node starts syncing from instead of genesis: a snapshot of the machine at the start
+/// of a sealed epoch, and the consensus data of the chain up to the block that sealed it.
+#[derive(Clone, Debug)]
+pub struct TrustedCheckpoint {
+    /// machine snapshot at the start of `epoch`
+    pub machine_path: PathBuf,
+    /// initial machine state hash of `epoch`, as sealed on chain
+    pub state_hash: Hash,
+    /// the epoch the snapshot starts, whose creation block is the last one processed
+    pub epoch: Epoch,
+    /// inputs of `epoch`, and those of the next epoch added up to its creation block
+    pub inputs: Vec<Input>,
+}
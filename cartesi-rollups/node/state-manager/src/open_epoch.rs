@@ -0,0 +1,222 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use cartesi_dave_merkle::Digest;
+use cartesi_machine::{error::MachineResult, types::Hash};
+
+use crate::{
+    CommitmentLeaf, Proof, Settlement,
+    persistent_state_access::build_commitment_from_hashes,
+    rollups_machine::{self, RollupsMachine},
+};
+
+/// Settlement of the epoch still open, kept up to date as its inputs are advanced, so that
+/// sealing the epoch only has to finalize it.
+#[derive(Clone, Debug)]
+pub(crate) struct OpenEpoch {
+    epoch_number: u64,
+    next_input_index: u64,
+    frontier: Frontier,
+    last_hash: Option<Hash>,
+    outputs: Option<(Hash, Proof)>,
+}
+
+impl OpenEpoch {
+    /// Open epoch `epoch_number` with the `leafs` of every input before `next_input_index`.
+    pub fn new(epoch_number: u64, next_input_index: u64, leafs: &[CommitmentLeaf]) -> Self {
+        let mut open_epoch = Self {
+            epoch_number,
+            next_input_index,
+            frontier: Frontier::default(),
+            last_hash: None,
+            outputs: None,
+        };
+        open_epoch.append(leafs);
+        open_epoch
+    }
+
+    pub fn is_at(&self, epoch_number: u64, next_input_index: u64) -> bool {
+        self.epoch_number == epoch_number && self.next_input_index == next_input_index
+    }
+
+    /// Appends the `leafs` of the next input.
+    pub fn advance(&mut self, leafs: &[CommitmentLeaf]) {
+        self.append(leafs);
+        self.next_input_index += 1;
+    }
+
+    /// Keeps the outputs proof of the latest accepted input, so it isn't computed when sealing.
+    pub fn set_outputs(&mut self, outputs: (Hash, Proof)) {
+        self.outputs = Some(outputs);
+    }
+
+    /// Settlement of the epoch as if it was sealed now, with `machine` being its latest snapshot.
+    pub fn settlement(&self, machine: &mut RollupsMachine) -> MachineResult<Settlement> {
+        let computation_hash = match self.computation_hash() {
+            Some(computation_hash) => computation_hash,
+            None => {
+                assert_eq!(machine.next_input_index_in_epoch(), 0);
+                build_commitment_from_hashes(&[CommitmentLeaf {
+                    hash: machine.state_hash()?,
+                    repetitions: 1,
+                }])
+            }
+        };
+
+        let (output_merkle, output_proof) = match &self.outputs {
+            Some(outputs) => outputs.clone(),
+            None => machine.outputs_proof()?,
+        };
+
+        Ok(Settlement {
+            computation_hash,
+            output_merkle,
+            output_proof,
+        })
+    }
+
+    /// Computation hash of the inputs advanced so far, none if there were no inputs.
+    ///
+    /// Not cached: padding a copy of the frontier to the end of the epoch only hashes on the
+    /// order of the height of the tree squared, however many leafs were appended.
+    fn computation_hash(&self) -> Option<Digest> {
+        let last_hash = self.last_hash?;
+
+        // the last state hash repeats until the end of the epoch
+        let mut frontier = self.frontier.clone();
+        frontier.append_repeated(
+            Digest::new(last_hash),
+            rollups_machine::STRIDE_COUNT_IN_EPOCH - frontier.count,
+        );
+        Some(frontier.root())
+    }
+
+    fn append(&mut self, leafs: &[CommitmentLeaf]) {
+        for leaf in leafs {
+            self.frontier
+                .append_repeated(Digest::new(leaf.hash), leaf.repetitions);
+            self.last_hash = Some(leaf.hash);
+        }
+    }
+}
+
+/// Roots of the complete subtrees left of the next leaf of a Merkle tree, at most one per level,
+/// which is all that's needed to keep appending leafs and to finish the root.
+#[derive(Clone, Debug)]
+struct Frontier {
+    /// root of the subtree of height `i`, for every bit `i` set in `count`
+    subtrees: [Digest; 64],
+    count: u64,
+}
+
+impl Default for Frontier {
+    fn default() -> Self {
+        Self {
+            subtrees: [Digest::ZERO; 64],
+            count: 0,
+        }
+    }
+}
+
+impl Frontier {
+    /// Appends `leaf` `repetitions` times, in subtrees as large as the leafs already appended
+    /// align to, hashing on the order of the height of the tree squared.
+    fn append_repeated(&mut self, leaf: Digest, mut repetitions: u64) {
+        // root of `leaf` repeated 2^i times, at `i`
+        let mut repeated = vec![leaf];
+
+        while repetitions > 0 {
+            let aligned = match self.count {
+                0 => u64::BITS - 1,
+                count => count.trailing_zeros(),
+            };
+            let height = aligned.min(repetitions.ilog2());
+            while repeated.len() <= height as usize {
+                let last = repeated[repeated.len() - 1];
+                repeated.push(last.join(&last));
+            }
+
+            self.insert(repeated[height as usize], height);
+            repetitions -= 1 << height;
+        }
+    }
+
+    /// Inserts the root of a complete subtree of `height`, which the count is aligned to, joining
+    /// it with the subtrees left of it, as adding its leafs to the count carries.
+    fn insert(&mut self, mut subtree: Digest, height: u32) {
+        let mut level = height;
+        while (self.count >> level) & 1 == 1 {
+            subtree = self.subtrees[level as usize].join(&subtree);
+            level += 1;
+        }
+        self.subtrees[level as usize] = subtree;
+        self.count += 1 << height;
+    }
+
+    /// Root of the tree, which must be complete.
+    fn root(&self) -> Digest {
+        assert!(
+            self.count.is_power_of_two(),
+            "tree with `{}` leafs isn't complete",
+            self.count
+        );
+        self.subtrees[self.count.trailing_zeros() as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_incremental_matches_from_scratch() {
+        let leafs = [
+            CommitmentLeaf {
+                hash: [1; 32],
+                repetitions: 3,
+            },
+            CommitmentLeaf {
+                hash: [2; 32],
+                repetitions: 1,
+            },
+            CommitmentLeaf {
+                hash: [3; 32],
+                repetitions: 7,
+            },
+        ];
+
+        let mut open_epoch = OpenEpoch::new(4, 1, &leafs[..1]);
+        open_epoch.advance(&leafs[1..]);
+        assert!(open_epoch.is_at(4, 2));
+
+        assert_eq!(
+            open_epoch.computation_hash(),
+            Some(build_commitment_from_hashes(&leafs))
+        );
+        assert!(OpenEpoch::new(5, 0, &[]).computation_hash().is_none());
+    }
+
+    #[test]
+    fn test_frontier_matches_builder() {
+        // repetitions that straddle the alignment of the leafs before them, some spanning most of
+        // the epoch
+        let repetitions = [1, 2, 3, 1 << 20, 5, (1 << 40) + 7, 1, 12345];
+        let leafs: Vec<_> = repetitions
+            .iter()
+            .enumerate()
+            .map(|(i, &repetitions)| CommitmentLeaf {
+                hash: [i as u8 + 1; 32],
+                repetitions,
+            })
+            .collect();
+
+        let mut open_epoch = OpenEpoch::new(0, 0, &[]);
+        for (i, leaf) in leafs.iter().enumerate() {
+            open_epoch.advance(std::slice::from_ref(leaf));
+            assert_eq!(
+                open_epoch.computation_hash(),
+                Some(build_commitment_from_hashes(&leafs[..=i]))
+            );
+        }
+    }
+}
@@ -1,10 +1,19 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use std::path::{Path, PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    CommitmentLeaf, Epoch, Input, InputId, Settlement, StateManager,
+    CommitmentLeaf, Epoch, Input, InputExecution, InputId, InputProgress, Settlement,
+    StateAccessError, StateManager, TrustedCheckpoint,
+    check::Corruption,
+    console::ConsoleCapture,
+    open_epoch::OpenEpoch,
+    processing,
+    retention::SnapshotRetention,
     rollups_machine::{self, RollupsMachine},
     sql::*,
     state_manager::Result,
@@ -12,12 +21,16 @@ use crate::{
 
 use alloy::primitives::U256;
 use cartesi_dave_merkle::{Digest, MerkleBuilder};
+use cartesi_prt_core::strategy::scheduler::Deadline;
 use rusqlite::Connection;
 
 #[derive(Debug)]
 pub struct PersistentStateAccess {
     connection: Connection,
     state_dir: PathBuf,
+    retention: SnapshotRetention,
+    console: Option<ConsoleCapture>,
+    open_epoch: Option<OpenEpoch>,
 }
 
 impl PersistentStateAccess {
@@ -33,19 +46,56 @@ impl PersistentStateAccess {
         Ok(Self {
             connection,
             state_dir,
+            retention: SnapshotRetention::default(),
+            console: None,
+            open_epoch: None,
+        })
+    }
+
+    /// Like [Self::migrate], but a fresh state starts from a trusted `checkpoint` instead of
+    /// replaying every input since genesis.
+    pub fn migrate_from_checkpoint(
+        state_dir: &Path,
+        checkpoint: &TrustedCheckpoint,
+    ) -> Result<Self> {
+        create_empty_state_dir_if_needed(state_dir)?;
+        let state_dir = state_dir.canonicalize().map_err(anyhow::Error::from)?;
+        let connection = migrate_from_checkpoint(&state_dir, checkpoint)?;
+
+        Ok(Self {
+            connection,
+            state_dir,
+            retention: SnapshotRetention::default(),
+            console: None,
+            open_epoch: None,
         })
     }
 
+    /// Opens the state in `state_dir`, failing if it wasn't created by [Self::migrate] before.
     pub fn new(state_dir: &Path) -> Result<Self> {
         let state_dir = state_dir.canonicalize().map_err(anyhow::Error::from)?;
-        let connection = create_connection(&state_dir)?;
+        let connection = open_connection(&state_dir)?;
 
         Ok(Self {
             connection,
             state_dir,
+            retention: SnapshotRetention::default(),
+            console: None,
+            open_epoch: None,
         })
     }
 
+    pub fn with_retention(mut self, retention: SnapshotRetention) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Machines handed out for processing inputs capture their console into `console`.
+    pub fn with_console(mut self, console: ConsoleCapture) -> Self {
+        self.console = Some(console);
+        self
+    }
+
     pub fn db_path(&self) -> PathBuf {
         db_path(&self.state_dir)
     }
@@ -55,6 +105,181 @@ impl PersistentStateAccess {
     }
 }
 
+impl PersistentStateAccess {
+    /// Takes the open epoch with every input before `next_input_index` advanced, rebuilding it
+    /// from the stored state hashes unless it was kept. Whoever advances it puts it back.
+    fn take_open_epoch(&mut self, epoch_number: u64, next_input_index: u64) -> Result<OpenEpoch> {
+        match self.open_epoch.take() {
+            Some(open_epoch) if open_epoch.is_at(epoch_number, next_input_index) => Ok(open_epoch),
+            _ => {
+                let leafs = rollup_data::get_all_commitments(&self.connection, epoch_number)?;
+                Ok(OpenEpoch::new(epoch_number, next_input_index, &leafs))
+            }
+        }
+    }
+
+    /// Records the input `machine` just accepted, or halted on, along with its snapshot, in a
+    /// single transaction that also drops the progress of the input.
+    fn advance_stored(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+        halted: bool,
+    ) -> Result<()> {
+        assert!(!leafs.is_empty());
+        let epoch = machine.epoch();
+        let next_input_index = machine.next_input_index_in_epoch();
+        let processed_input_index = next_input_index - 1;
+        let mut open_epoch = self.take_open_epoch(epoch, processed_input_index)?;
+
+        let (dest_dir, state_hash) = {
+            let snapshots_path = snapshots_path(&self.state_dir);
+            machine
+                .store_if_needed(&snapshots_path)
+                .map_err(anyhow::Error::from)?
+        };
+
+        let tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::insert_state_hashes_for_input(&tx, epoch, processed_input_index, leafs)?;
+        rollup_data::insert_snapshot(&tx, epoch, next_input_index, &state_hash, &dest_dir)?;
+        rollup_data::gc_previous_advances(&tx, epoch, next_input_index)?;
+        if halted {
+            rollup_data::insert_halted(&tx, epoch, processed_input_index)?;
+        }
+        // the progress of the input goes with the advance, or a restart would resume it again
+        rollup_data::delete_input_progress(&tx)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+        prune_input_progress(&self.state_dir, None)?;
+
+        open_epoch.advance(leafs);
+        open_epoch.set_outputs(machine.outputs_proof()?);
+        self.open_epoch = Some(open_epoch);
+
+        Ok(())
+    }
+
+    /// Drops the snapshots of epochs settled before `current_epoch` that the retention policy
+    /// doesn't keep, archiving evicted boundary snapshots first if configured to.
+    fn gc_old_epochs(&mut self, current_epoch: u64) -> Result<()> {
+        let mut tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        self.retention.gc_old_epochs(&mut tx, current_epoch)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+
+        Ok(())
+    }
+
+    /// Verifies that every snapshot exists and holds the machine of its state hash, and that the
+    /// computation hash of every settled epoch commits to its stored state hashes.
+    pub fn check(&mut self) -> Result<Vec<Corruption>> {
+        let mut corruptions = Vec::new();
+
+        for (state_hash, path, first_use) in rollup_data::all_snapshots(&self.connection)? {
+            if !path.is_dir() {
+                corruptions.push(Corruption::MissingSnapshot {
+                    state_hash,
+                    path,
+                    first_use,
+                });
+                continue;
+            }
+
+            match RollupsMachine::new(&path, 0, 0).and_then(|mut m| m.state_hash()) {
+                Ok(actual) if actual == state_hash => {}
+                Ok(actual) => corruptions.push(Corruption::SnapshotHashMismatch {
+                    state_hash,
+                    path,
+                    first_use,
+                    actual,
+                }),
+                Err(e) => corruptions.push(Corruption::UnreadableSnapshot {
+                    state_hash,
+                    path,
+                    first_use,
+                    error: e.to_string(),
+                }),
+            }
+        }
+
+        let positions = rollup_data::snapshot_positions(&self.connection)?;
+        for epoch_number in rollup_data::settled_epochs(&self.connection)? {
+            let Some(settlement) = rollup_data::settlement_info(&self.connection, epoch_number)?
+            else {
+                continue;
+            };
+
+            let mut leafs = rollup_data::get_all_commitments(&self.connection, epoch_number)?;
+            if leafs.is_empty() {
+                // an epoch without inputs commits to its initial state, if that is still around
+                let Some((_, hash)) = positions.iter().find(|(id, _)| {
+                    id.epoch_number == epoch_number && id.input_index_in_epoch == 0
+                }) else {
+                    continue;
+                };
+                leafs.push(CommitmentLeaf {
+                    hash: *hash,
+                    repetitions: 1,
+                });
+            }
+
+            let computed = build_commitment_from_hashes(&leafs);
+            if computed != settlement.computation_hash {
+                corruptions.push(Corruption::ComputationHashMismatch {
+                    epoch_number,
+                    stored: settlement.computation_hash,
+                    computed,
+                });
+            }
+        }
+
+        Ok(corruptions)
+    }
+
+    /// Rewinds the rollup data to the latest intact snapshot preceding every one of `corruptions`,
+    /// and processes the inputs from there again. Returns where processing restarted, or `None` if
+    /// nothing needed to be reprocessed. Corrupted snapshots no epoch uses, like a template machine
+    /// that was already garbage collected from epoch zero, are left as is.
+    pub fn repair(&mut self, corruptions: &[Corruption]) -> Result<Option<InputId>> {
+        let Some(first_affected) = corruptions
+            .iter()
+            .filter_map(Corruption::first_affected)
+            .min_by_key(|id| (id.epoch_number, id.input_index_in_epoch))
+        else {
+            return Ok(None);
+        };
+
+        let bad_hashes: Vec<_> = corruptions
+            .iter()
+            .filter_map(Corruption::snapshot_hash)
+            .collect();
+        let (restart, _) = rollup_data::snapshot_positions(&self.connection)?
+            .into_iter()
+            .find(|(id, hash)| {
+                (id.epoch_number, id.input_index_in_epoch)
+                    <= (
+                        first_affected.epoch_number,
+                        first_affected.input_index_in_epoch,
+                    )
+                    && !bad_hashes.contains(&hash)
+            })
+            .ok_or_else(|| StateAccessError::DataNotFound {
+                description: format!(
+                    "intact snapshot preceding input {}:{}",
+                    first_affected.epoch_number, first_affected.input_index_in_epoch
+                ),
+            })?;
+
+        let tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::truncate_after(&tx, restart.epoch_number, restart.input_index_in_epoch)?;
+        rollup_data::gc_orphan_snapshots(&tx)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+        self.open_epoch = None;
+
+        processing::process_rollup(self)?;
+
+        Ok(Some(restart))
+    }
+}
+
 impl StateManager for PersistentStateAccess {
     //
     // Consensus Data
@@ -115,35 +340,7 @@ impl StateManager for PersistentStateAccess {
         machine: &mut RollupsMachine,
         leafs: &[CommitmentLeaf],
     ) -> Result<()> {
-        assert!(!leafs.is_empty());
-        let epoch = machine.epoch();
-        let next_input_index = machine.next_input_index_in_epoch();
-        let processed_input_index = next_input_index - 1;
-
-        rollup_data::insert_state_hashes_for_input(
-            &self.connection,
-            epoch,
-            processed_input_index,
-            leafs,
-        )?;
-
-        let (dest_dir, state_hash) = {
-            let snapshots_path = snapshots_path(&self.state_dir);
-            machine
-                .store_if_needed(&snapshots_path)
-                .map_err(anyhow::Error::from)?
-        };
-
-        rollup_data::insert_snapshot(
-            &self.connection,
-            epoch,
-            next_input_index,
-            &state_hash,
-            &dest_dir,
-        )?;
-        rollup_data::gc_previous_advances(&self.connection, epoch, next_input_index)?;
-
-        Ok(())
+        self.advance_stored(machine, leafs, false)
     }
 
     fn advance_reverted(
@@ -155,37 +352,128 @@ impl StateManager for PersistentStateAccess {
         let epoch = machine.epoch();
         let next_input_index = machine.next_input_index_in_epoch();
         let processed_input_index = next_input_index - 1;
+        let mut open_epoch = self.take_open_epoch(epoch, processed_input_index)?;
 
-        rollup_data::insert_state_hashes_for_input(
-            &self.connection,
-            epoch,
-            processed_input_index,
-            leafs,
-        )?;
+        let tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::insert_state_hashes_for_input(&tx, epoch, processed_input_index, leafs)?;
 
         let (snapshot_path, snapshot_epoch, snapshot_input) =
-            rollup_data::latest_snapshot_path(&self.connection)?;
+            rollup_data::latest_snapshot_path(&tx)?;
 
         assert_eq!(snapshot_epoch, epoch);
         assert_eq!(snapshot_input, processed_input_index);
 
         // load rollups machine from previous successful (ACCEPT) snapshot
-        let mut reverted_machine = RollupsMachine::new(&snapshot_path, epoch, next_input_index)?;
+        let mut reverted_machine = RollupsMachine::load(
+            &snapshot_path,
+            epoch,
+            next_input_index,
+            self.console.clone(),
+        )?;
 
         rollup_data::insert_snapshot(
-            &self.connection,
+            &tx,
             epoch,
             next_input_index,
             &reverted_machine.state_hash()?,
             &snapshot_path,
         )?;
-        rollup_data::gc_previous_advances(&self.connection, epoch, next_input_index)?;
+        rollup_data::gc_previous_advances(&tx, epoch, next_input_index)?;
+        // the progress of the input goes with the advance, or a restart would resume it again
+        rollup_data::delete_input_progress(&tx)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+        prune_input_progress(&self.state_dir, None)?;
+
+        // a rejected input leaves the outputs as they were
+        open_epoch.advance(leafs);
+        self.open_epoch = Some(open_epoch);
 
         // Update the passed machine to match the reverted state
         *machine = reverted_machine;
         Ok(())
     }
 
+    fn insert_input_execution(&mut self, id: &InputId, execution: &InputExecution) -> Result<()> {
+        rollup_data::insert_input_execution(
+            &self.connection,
+            id.epoch_number,
+            id.input_index_in_epoch,
+            execution,
+        )
+    }
+
+    fn input_execution(&mut self, id: &InputId) -> Result<Option<InputExecution>> {
+        rollup_data::input_execution(&self.connection, id.epoch_number, id.input_index_in_epoch)
+    }
+
+    fn input_executions(&mut self, epoch_number: u64) -> Result<Vec<InputExecution>> {
+        rollup_data::input_executions(&self.connection, epoch_number)
+    }
+
+    fn set_deadlines(&mut self, deadlines: &[Deadline]) -> Result<()> {
+        let tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::replace_deadlines(&tx, deadlines)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+        Ok(())
+    }
+
+    fn deadlines(&mut self) -> Result<Vec<Deadline>> {
+        rollup_data::deadlines(&self.connection)
+    }
+
+    fn save_input_progress(
+        &mut self,
+        machine: &mut RollupsMachine,
+        progress: &InputProgress,
+    ) -> Result<()> {
+        let epoch = machine.epoch();
+        let input_number = machine.next_input_index_in_epoch();
+        let path =
+            input_progress_machine_path(&self.state_dir, epoch, input_number, progress.leafs.len());
+
+        // the progress saved before stays usable until the new one is committed
+        let saved = rollup_data::input_progress(&self.connection)?.map(|(_, path, _)| path);
+        prune_input_progress(&self.state_dir, saved.as_deref())?;
+        fs::create_dir_all(input_progress_path(&self.state_dir)).map_err(anyhow::Error::from)?;
+        machine.store(&path).map_err(anyhow::Error::from)?;
+
+        let tx = self.connection.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::insert_input_progress(&tx, epoch, input_number, &path, progress)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+
+        prune_input_progress(&self.state_dir, Some(&path))
+    }
+
+    fn input_progress(&mut self) -> Result<Option<(RollupsMachine, InputProgress)>> {
+        let Some((id, path, progress)) = rollup_data::input_progress(&self.connection)? else {
+            return Ok(None);
+        };
+        // progress left behind by an input that was advanced since is of no use
+        if id != self.next_input_id()? {
+            return Ok(None);
+        }
+
+        let machine = RollupsMachine::load(
+            &path,
+            id.epoch_number,
+            id.input_index_in_epoch,
+            self.console.clone(),
+        )?;
+        Ok(Some((machine, progress)))
+    }
+
+    fn advance_halted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()> {
+        self.advance_stored(machine, leafs, true)
+    }
+
+    fn halted_at(&mut self) -> Result<Option<InputId>> {
+        rollup_data::halted_at(&self.connection)
+    }
+
     fn next_input_id(&mut self) -> Result<InputId> {
         rollup_data::next_input_to_be_processed(&self.connection)
     }
@@ -209,27 +497,9 @@ impl StateManager for PersistentStateAccess {
         let mut machine = self.latest_snapshot()?;
         let previous_epoch_number = machine.epoch();
 
-        let settlement = {
-            let leafs = rollup_data::get_all_commitments(&self.connection, previous_epoch_number)?;
-
-            let computation_hash = if !leafs.is_empty() {
-                build_commitment_from_hashes(&leafs)
-            } else {
-                assert_eq!(machine.next_input_index_in_epoch(), 0);
-                build_commitment_from_hashes(&[CommitmentLeaf {
-                    hash: machine.state_hash()?,
-                    repetitions: 1,
-                }])
-            };
-
-            let (output_merkle, output_proof) = machine.outputs_proof()?;
-
-            Settlement {
-                computation_hash,
-                output_merkle,
-                output_proof,
-            }
-        };
+        let settlement = self
+            .take_open_epoch(previous_epoch_number, machine.next_input_index_in_epoch())?
+            .settlement(&mut machine)?;
 
         machine.finish_epoch();
 
@@ -247,10 +517,9 @@ impl StateManager for PersistentStateAccess {
         rollup_data::insert_snapshot(&tx, new_epoch_number, 0, &state_hash, &dest_dir)?;
         rollup_data::insert_settlement_info(&tx, &settlement, previous_epoch_number)?;
         tx.commit().map_err(anyhow::Error::from)?;
+        self.open_epoch = Some(OpenEpoch::new(new_epoch_number, 0, &[]));
 
-        if previous_epoch_number >= 1 {
-            rollup_data::gc_old_epochs(&self.connection, previous_epoch_number - 1)?;
-        }
+        self.gc_old_epochs(new_epoch_number)?;
 
         Ok(())
     }
@@ -270,7 +539,12 @@ impl StateManager for PersistentStateAccess {
     fn latest_snapshot(&mut self) -> Result<crate::rollups_machine::RollupsMachine> {
         let (path, epoch_number, input_number) =
             rollup_data::latest_snapshot_path(&self.connection)?;
-        Ok(RollupsMachine::new(&path, epoch_number, input_number)?)
+        Ok(RollupsMachine::load(
+            &path,
+            epoch_number,
+            input_number,
+            self.console.clone(),
+        )?)
     }
 
     fn snapshot_dir(&mut self, epoch_number: u64, input_number: u64) -> Result<Option<PathBuf>> {
@@ -286,7 +560,7 @@ impl StateManager for PersistentStateAccess {
     }
 }
 
-fn build_commitment_from_hashes(state_hashes: &[CommitmentLeaf]) -> Digest {
+pub(crate) fn build_commitment_from_hashes(state_hashes: &[CommitmentLeaf]) -> Digest {
     let mut builder = MerkleBuilder::default();
 
     assert!(!state_hashes.is_empty());
@@ -311,6 +585,7 @@ fn build_commitment_from_hashes(state_hashes: &[CommitmentLeaf]) -> Digest {
 
 #[cfg(test)]
 mod tests {
+    use crate::{StateAccessError, retention::RetentionPolicy};
     use alloy::primitives::Address;
     use cartesi_machine::{
         Machine,
@@ -318,6 +593,7 @@ mod tests {
             machine::{MachineConfig, RAMConfig},
             runtime::RuntimeConfig,
         },
+        types::Hash,
     };
 
     use super::*;
@@ -342,6 +618,217 @@ mod tests {
         (state_dir_, acc)
     }
 
+    fn checkpoint(machine_path: &Path, state_hash: Hash) -> TrustedCheckpoint {
+        let input = |epoch_number, input_index_in_epoch| Input {
+            id: InputId {
+                epoch_number,
+                input_index_in_epoch,
+            },
+            data: vec![epoch_number as u8, input_index_in_epoch as u8],
+            metadata: None,
+        };
+
+        TrustedCheckpoint {
+            machine_path: machine_path.to_owned(),
+            state_hash,
+            epoch: Epoch {
+                epoch_number: 3,
+                input_index_boundary: 12,
+                root_tournament: Address::ZERO,
+                block_created_number: 40,
+            },
+            inputs: vec![input(3, 0), input(3, 1), input(4, 0)],
+        }
+    }
+
+    #[test]
+    fn test_migrate_from_checkpoint() -> super::Result<()> {
+        let (handle, mut genesis) = setup();
+        let state_hash = genesis.latest_snapshot()?.state_hash()?;
+        let snapshot_path = genesis.snapshot_dir(0, 0)?.unwrap();
+
+        let state_dir = handle.path().join("checkpoint");
+        let mismatch = PersistentStateAccess::migrate_from_checkpoint(
+            &state_dir,
+            &checkpoint(&snapshot_path, [0; 32]),
+        );
+        assert!(matches!(
+            mismatch,
+            Err(StateAccessError::CheckpointMismatch {
+                epoch_number: 3,
+                ..
+            })
+        ));
+
+        let checkpoint = checkpoint(&snapshot_path, state_hash);
+        let mut access = PersistentStateAccess::migrate_from_checkpoint(&state_dir, &checkpoint)?;
+        let next = access.next_input_id()?;
+        assert_eq!((next.epoch_number, next.input_index_in_epoch), (3, 0));
+        assert_eq!(access.latest_snapshot()?.epoch(), 3);
+        assert_eq!(access.latest_processed_block()?, 40);
+        assert_eq!(access.last_sealed_epoch()?.unwrap().epoch_number, 3);
+        assert_eq!(access.input_count(3)?, 2);
+        assert_eq!(access.input_count(4)?, 1);
+        assert!(access.snapshot(0, 0)?.is_none());
+
+        // an already synced state is not seeded again
+        drop(access);
+        let mut access = PersistentStateAccess::migrate_from_checkpoint(&state_dir, &checkpoint)?;
+        assert_eq!(access.input_count(3)?, 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_input_progress_is_ignored() -> super::Result<()> {
+        let (_handle, mut access) = setup();
+        let mut machine = access.latest_snapshot()?;
+        let progress = InputProgress {
+            checkpoint_hash: machine.state_hash()?,
+            leafs: vec![CommitmentLeaf {
+                hash: [1; 32],
+                repetitions: 1,
+            }],
+            start_mcycle: 0,
+            output_count: 0,
+        };
+        let path = input_progress_machine_path(&access.state_dir, 0, 0, 1);
+
+        machine.increment_input();
+        access.advance_accepted(&mut machine, &progress.leafs)?;
+
+        // as left behind by an advance that didn't drop it
+        rollup_data::insert_input_progress(&access.connection, 0, 0, &path, &progress)?;
+        assert!(access.input_progress()?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_conformance() -> super::Result<()> {
+        crate::conformance::run(setup)
+    }
+
+    #[test]
+    fn test_check() -> super::Result<()> {
+        let (_handle, mut access) = setup();
+        assert!(access.check()?.is_empty());
+
+        let mut machine = access.latest_snapshot()?;
+        machine.increment_input();
+        access.advance_accepted(
+            &mut machine,
+            &[CommitmentLeaf {
+                hash: [1; 32],
+                repetitions: 1,
+            }],
+        )?;
+        access.roll_epoch()?;
+        assert!(access.check()?.is_empty());
+
+        access
+            .connection
+            .execute(
+                "UPDATE settlement_info SET computation_hash = ?1 WHERE epoch_number = 0",
+                [[0u8; 32]],
+            )
+            .unwrap();
+        let corruptions = access.check()?;
+        assert_eq!(corruptions.len(), 1);
+        assert!(matches!(
+            corruptions[0],
+            Corruption::ComputationHashMismatch {
+                epoch_number: 0,
+                ..
+            }
+        ));
+
+        let snapshot_path = access.snapshot_dir(1, 0)?.unwrap();
+        std::fs::remove_dir_all(&snapshot_path).unwrap();
+        let corruptions = access.check()?;
+        assert!(corruptions.iter().any(|c| matches!(
+            c,
+            Corruption::MissingSnapshot {
+                path,
+                first_use: Some(_),
+                ..
+            } if *path == snapshot_path
+        )));
+        assert_eq!(
+            corruptions
+                .iter()
+                .filter_map(Corruption::first_affected)
+                .map(|id| id.epoch_number)
+                .min(),
+            Some(0)
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_repair() -> super::Result<()> {
+        let handle = tempfile::tempdir().unwrap();
+        let mut access = PersistentStateAccess::migrate(
+            handle.path(),
+            Path::new("../../../test/programs/echo/machine-image"),
+            0,
+        )?
+        .with_retention(SnapshotRetention {
+            policy: RetentionPolicy::KeepAll,
+            archive_dir: None,
+        });
+
+        let inputs: Vec<_> = [(0, 0), (0, 1), (1, 0), (1, 1), (1, 2), (2, 0)]
+            .into_iter()
+            .map(|(epoch_number, input_index_in_epoch)| Input {
+                id: InputId {
+                    epoch_number,
+                    input_index_in_epoch,
+                },
+                data: vec![epoch_number as u8, input_index_in_epoch as u8],
+                metadata: None,
+            })
+            .collect();
+        let epochs = [(0, 2), (1, 5)].map(|(epoch_number, input_index_boundary)| Epoch {
+            epoch_number,
+            input_index_boundary,
+            root_tournament: Address::ZERO,
+            block_created_number: epoch_number,
+        });
+        access.insert_consensus_data(10, inputs.iter(), epochs.iter())?;
+        processing::process_rollup(&mut access)?;
+
+        let settlements = |access: &mut PersistentStateAccess| -> super::Result<Vec<_>> {
+            (0..2).map(|e| access.settlement_info(e)).collect()
+        };
+        let settled = settlements(&mut access)?;
+        assert!(settled.iter().all(Option::is_some));
+        let open_leafs = access.epoch_state_hashes(2)?;
+        let state_hash = access.latest_snapshot()?.state_hash()?;
+        assert!(access.check()?.is_empty());
+
+        // the snapshot the last epoch starts from no longer holds a machine
+        let snapshot_path = access.snapshot_dir(2, 0)?.unwrap();
+        fs::remove_dir_all(&snapshot_path).unwrap();
+        fs::create_dir(&snapshot_path).unwrap();
+        fs::write(snapshot_path.join("config.json"), "corrupted").unwrap();
+        let corruptions = access.check()?;
+        assert!(matches!(
+            corruptions[..],
+            [Corruption::UnreadableSnapshot { .. }]
+        ));
+
+        let restart = access.repair(&corruptions)?.unwrap();
+        assert!((restart.epoch_number, restart.input_index_in_epoch) < (2, 0));
+        assert!(access.check()?.is_empty());
+        assert_eq!(settlements(&mut access)?, settled);
+        assert_eq!(access.epoch_state_hashes(2)?, open_leafs);
+        assert_eq!(access.latest_snapshot()?.state_hash()?, state_hash);
+
+        Ok(())
+    }
+
     #[test]
     fn test_state_access() -> super::Result<()> {
         let input_0_bytes = b"hello";
@@ -361,6 +848,7 @@ mod tests {
                         input_index_in_epoch: 0,
                     },
                     data: input_0_bytes.to_vec(),
+                    metadata: None,
                 },
                 &Input {
                     id: InputId {
@@ -368,6 +856,7 @@ mod tests {
                         input_index_in_epoch: 1,
                     },
                     data: input_1_bytes.to_vec(),
+                    metadata: None,
                 },
             ]
             .into_iter(),
@@ -420,6 +909,7 @@ mod tests {
                             input_index_in_epoch: 1,
                         },
                         data: input_0_bytes.to_vec(),
+                        metadata: None,
                     }]
                     .into_iter(),
                     [].into_iter(),
@@ -437,6 +927,7 @@ mod tests {
                             input_index_in_epoch: 3,
                         },
                         data: input_0_bytes.to_vec(),
+                        metadata: None,
                     }]
                     .into_iter(),
                     [].into_iter(),
@@ -454,6 +945,7 @@ mod tests {
                             input_index_in_epoch: 2,
                         },
                         data: input_1_bytes.to_vec(),
+                        metadata: None,
                     }]
                     .into_iter(),
                     [].into_iter(),
@@ -0,0 +1,302 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use super::{from_i64, to_i64};
+use crate::{
+    Epoch, Input, InputId, InputMetadata,
+    state_manager::{Result, StateAccessError},
+};
+
+use alloy::{
+    hex::{FromHex, ToHexExt},
+    primitives::{Address, U256},
+};
+use postgres::{GenericClient, Row};
+
+fn convert_row_to_epoch(row: &Row) -> Epoch {
+    let tournament_str: String = row.get(2);
+    Epoch {
+        epoch_number: from_i64(row.get(0)),
+        input_index_boundary: from_i64(row.get(1)),
+        root_tournament: Address::from_hex(tournament_str).unwrap(),
+        block_created_number: from_i64(row.get(3)),
+    }
+}
+
+fn convert_row_to_input_metadata(row: &Row) -> Option<InputMetadata> {
+    let sender: String = row.get::<_, Option<_>>(1)?;
+
+    let prev_randao: Vec<u8> = row.get(4);
+    let transaction_hash: Vec<u8> = row.get(6);
+    Some(InputMetadata {
+        sender: Address::from_hex(sender).unwrap(),
+        block_number: from_i64(row.get(2)),
+        block_timestamp: from_i64(row.get(3)),
+        prev_randao: U256::from_be_slice(&prev_randao),
+        index: from_i64(row.get(5)),
+        transaction_hash: transaction_hash.try_into().unwrap(),
+        log_index: from_i64(row.get(7)),
+    })
+}
+
+//
+// Last Processed
+//
+
+pub fn update_last_processed_block(conn: &mut impl GenericClient, block: u64) -> Result<()> {
+    let previous = last_processed_block(conn)?;
+
+    if previous >= block {
+        return Err(StateAccessError::InconsistentLastProcessed {
+            last: previous,
+            provided: block,
+        });
+    }
+
+    conn.execute(
+        "UPDATE latest_processed SET block = $1 WHERE id = 1",
+        &[&to_i64(block)],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn last_processed_block(conn: &mut impl GenericClient) -> Result<u64> {
+    let row = conn
+        .query_one("SELECT block FROM latest_processed WHERE id = 1", &[])
+        .map_err(anyhow::Error::from)?;
+
+    Ok(from_i64(row.get(0)))
+}
+
+//
+// Inputs
+//
+
+fn validate_insert(current: &Option<InputId>, next: &InputId) -> bool {
+    match &current {
+        Some(i) if !i.validate_next(next) => false,
+        None if next.input_index_in_epoch != 0 => false,
+        _ => true,
+    }
+}
+
+pub fn insert_inputs<'a>(
+    conn: &mut impl GenericClient,
+    inputs: impl Iterator<Item = &'a Input>,
+) -> Result<()> {
+    let mut inputs = inputs.peekable();
+    if inputs.peek().is_none() {
+        return Ok(());
+    }
+
+    let mut current_input = last_input(conn)?;
+
+    let stmt = conn
+        .prepare(
+            r#"
+            INSERT INTO inputs (
+                epoch_number, input_index_in_epoch, input, sender, block_number, block_timestamp,
+                prev_randao, app_input_index, transaction_hash, log_index
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+    for input in inputs {
+        if !validate_insert(&current_input, &input.id) {
+            return Err(StateAccessError::InconsistentInput {
+                previous: current_input,
+                provided: input.id.clone(),
+            });
+        }
+
+        let metadata = input.metadata.as_ref();
+        conn.execute(
+            &stmt,
+            &[
+                &to_i64(input.id.epoch_number),
+                &to_i64(input.id.input_index_in_epoch),
+                &input.data,
+                &metadata.map(|m| m.sender.encode_hex()),
+                &metadata.map(|m| to_i64(m.block_number)),
+                &metadata.map(|m| to_i64(m.block_timestamp)),
+                &metadata.map(|m| m.prev_randao.to_be_bytes_vec()),
+                &metadata.map(|m| to_i64(m.index)),
+                &metadata.map(|m| m.transaction_hash.to_vec()),
+                &metadata.map(|m| to_i64(m.log_index)),
+            ],
+        )
+        .map_err(anyhow::Error::from)?;
+
+        current_input = Some(input.id.clone());
+    }
+
+    Ok(())
+}
+
+pub fn last_input(conn: &mut impl GenericClient) -> Result<Option<InputId>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT epoch_number, input_index_in_epoch FROM inputs
+            ORDER BY epoch_number DESC, input_index_in_epoch DESC
+            LIMIT 1
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.map(|row| InputId {
+        epoch_number: from_i64(row.get(0)),
+        input_index_in_epoch: from_i64(row.get(1)),
+    }))
+}
+
+pub fn input(conn: &mut impl GenericClient, id: &InputId) -> Result<Option<Input>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT input, sender, block_number, block_timestamp, prev_randao, app_input_index,
+                transaction_hash, log_index
+            FROM inputs
+            WHERE epoch_number = $1 AND input_index_in_epoch = $2
+            "#,
+            &[&to_i64(id.epoch_number), &to_i64(id.input_index_in_epoch)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.map(|row| Input {
+        id: id.clone(),
+        data: row.get(0),
+        metadata: convert_row_to_input_metadata(&row),
+    }))
+}
+
+pub fn inputs(conn: &mut impl GenericClient, epoch_number: u64) -> Result<Vec<Vec<u8>>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT input FROM inputs
+            WHERE epoch_number = $1
+            ORDER BY input_index_in_epoch ASC
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows.iter().map(|row| row.get(0)).collect())
+}
+
+pub fn input_count(conn: &mut impl GenericClient, epoch_number: u64) -> Result<u64> {
+    let row = conn
+        .query_one(
+            "SELECT MAX(input_index_in_epoch) FROM inputs WHERE epoch_number = $1",
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let x: Option<i64> = row.get(0);
+    Ok(x.map(|x| from_i64(x) + 1).unwrap_or(0))
+}
+
+//
+// Epochs
+//
+
+pub fn insert_epochs<'a>(
+    conn: &mut impl GenericClient,
+    epochs: impl Iterator<Item = &'a Epoch>,
+) -> Result<()> {
+    let mut epochs = epochs.peekable();
+    if epochs.peek().is_none() {
+        return Ok(());
+    }
+
+    let mut next_epoch = epoch_count(conn)?;
+
+    for epoch in epochs {
+        if epoch.epoch_number != next_epoch {
+            return Err(StateAccessError::InconsistentEpoch {
+                expected: next_epoch,
+                provided: epoch.epoch_number,
+            });
+        }
+
+        insert_epoch(conn, epoch)?;
+        next_epoch += 1;
+    }
+    Ok(())
+}
+
+/// Inserts the first epoch a node knows of, which is not epoch zero when it syncs from a
+/// checkpoint.
+pub fn insert_first_epoch(conn: &mut impl GenericClient, epoch: &Epoch) -> Result<()> {
+    let count = epoch_count(conn)?;
+    if count != 0 {
+        return Err(StateAccessError::InconsistentEpoch {
+            expected: count,
+            provided: epoch.epoch_number,
+        });
+    }
+
+    insert_epoch(conn, epoch)
+}
+
+fn insert_epoch(conn: &mut impl GenericClient, epoch: &Epoch) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO epochs (epoch_number, input_index_boundary, root_tournament, block_created_number)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        &[
+            &to_i64(epoch.epoch_number),
+            &to_i64(epoch.input_index_boundary),
+            &epoch.root_tournament.encode_hex(),
+            &to_i64(epoch.block_created_number),
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn last_sealed_epoch(conn: &mut impl GenericClient) -> Result<Option<Epoch>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT epoch_number, input_index_boundary, root_tournament, block_created_number
+            FROM epochs
+            ORDER BY epoch_number DESC
+            LIMIT 1
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.as_ref().map(convert_row_to_epoch))
+}
+
+pub fn epoch(conn: &mut impl GenericClient, epoch_number: u64) -> Result<Option<Epoch>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT epoch_number, input_index_boundary, root_tournament, block_created_number
+            FROM epochs
+            WHERE epoch_number = $1
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.as_ref().map(convert_row_to_epoch))
+}
+
+pub fn epoch_count(conn: &mut impl GenericClient) -> Result<u64> {
+    let row = conn
+        .query_one("SELECT MAX(epoch_number) FROM epochs", &[])
+        .map_err(anyhow::Error::from)?;
+
+    let x: Option<i64> = row.get(0);
+    Ok(x.map(|x| from_i64(x) + 1).unwrap_or(0))
+}
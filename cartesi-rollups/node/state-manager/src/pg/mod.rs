@@ -0,0 +1,230 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! PostgreSQL counterpart of [crate::sql], with the same schema. Snapshot directories stay on the
+//! local filesystem, under the state directory.
+//!
+//! The blocking [Client] drives a runtime of its own, which panics on a thread that already runs
+//! one, like the tasks of the node. Every use of a client goes through [PgClient], on the one
+//! thread that owns it.
+
+pub mod consensus_data;
+pub mod rollup_data;
+
+use crate::{
+    TrustedCheckpoint,
+    rollups_machine::RollupsMachine,
+    sql::snapshots_path,
+    state_manager::{Result, StateAccessError},
+};
+use postgres::{Client, Config, NoTls};
+use std::{
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    sync::mpsc,
+    thread,
+};
+
+const SCHEMA: &str = include_str!("schema.sql");
+
+// Postgres has no unsigned integers; every number the node stores fits in a `BIGINT`
+pub(crate) fn to_i64(x: u64) -> i64 {
+    i64::try_from(x).expect("value should fit in a BIGINT")
+}
+
+pub(crate) fn from_i64(x: i64) -> u64 {
+    u64::try_from(x).expect("stored value should be non-negative")
+}
+
+fn set_genesis(client: &mut Client, block_number: u64) -> Result<()> {
+    let last_processed = consensus_data::last_processed_block(client)?;
+
+    if block_number > last_processed {
+        consensus_data::update_last_processed_block(client, block_number)?;
+    }
+    Ok(())
+}
+
+type Job = Box<dyn FnOnce(&mut Client) + Send>;
+
+/// A [Client] owned by a worker thread of its own, which runs every use of it.
+pub struct PgClient {
+    jobs: Option<mpsc::Sender<Job>>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl PgClient {
+    pub fn connect(database: &Config) -> Result<Self> {
+        let database = database.clone();
+        let (connected, connection) = mpsc::sync_channel(1);
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let worker = thread::spawn(move || {
+            let mut client = match database.connect(NoTls) {
+                Ok(client) => {
+                    let _ = connected.send(Ok(()));
+                    client
+                }
+                Err(e) => {
+                    let _ = connected.send(Err(e));
+                    return;
+                }
+            };
+            // the connection is closed here too, since that blocks on the client runtime as well
+            for job in queue {
+                job(&mut client);
+            }
+        });
+
+        let connection = connection
+            .recv()
+            .expect("postgres worker should report its connection");
+        if let Err(e) = connection {
+            let _ = worker.join();
+            return Err(anyhow::Error::from(e).into());
+        }
+
+        Ok(Self {
+            jobs: Some(jobs),
+            worker: Some(worker),
+        })
+    }
+
+    /// Runs `f` with the client on its worker thread, and waits for it.
+    pub fn run<T: Send>(&mut self, f: impl FnOnce(&mut Client) -> T + Send) -> T {
+        let (result, done) = mpsc::sync_channel(1);
+        let job: Box<dyn FnOnce(&mut Client) + Send + '_> = Box::new(move |client| {
+            let _ = result.send(panic::catch_unwind(AssertUnwindSafe(|| f(client))));
+        });
+        // SAFETY: only the lifetime is erased. The job is waited for below, before anything it
+        // borrows can go away, and it always sends its result, catching any panic.
+        let job: Job = unsafe { std::mem::transmute(job) };
+
+        self.jobs
+            .as_ref()
+            .expect("jobs are only taken on drop")
+            .send(job)
+            .expect("postgres worker should outlive its client");
+        match done
+            .recv()
+            .expect("postgres worker should finish every job")
+        {
+            Ok(result) => result,
+            Err(panic) => panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for PgClient {
+    fn drop(&mut self) {
+        drop(self.jobs.take());
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+fn set_initial_machine(
+    client: &mut PgClient,
+    state_dir: &Path,
+    source_machine_path: &Path,
+) -> Result<()> {
+    assert!(
+        source_machine_path.is_dir(),
+        "machine path `{}` must be an existing directory",
+        source_machine_path.display()
+    );
+
+    let mut machine = RollupsMachine::new(source_machine_path, 0, 0)?;
+
+    let (dest_machine_path, state_hash) = {
+        let snapshots_path = snapshots_path(state_dir);
+        machine
+            .store_if_needed(&snapshots_path)
+            .map_err(anyhow::Error::from)?
+    };
+
+    client.run(|c| {
+        let mut tx = c.transaction().map_err(anyhow::Error::from)?;
+        rollup_data::insert_snapshot(&mut tx, 0, 0, &state_hash, &dest_machine_path)?;
+        rollup_data::insert_template_machine(&mut tx, &state_hash)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+
+        Ok(())
+    })
+}
+
+fn set_checkpoint(
+    client: &mut PgClient,
+    state_dir: &Path,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<()> {
+    let epoch_number = checkpoint.epoch.epoch_number;
+    let mut machine = RollupsMachine::new(&checkpoint.machine_path, epoch_number, 0)?;
+
+    let state_hash = machine.state_hash()?;
+    if state_hash != checkpoint.state_hash {
+        return Err(StateAccessError::CheckpointMismatch {
+            epoch_number,
+            expected: hex::encode(checkpoint.state_hash),
+            provided: hex::encode(state_hash),
+        });
+    }
+
+    let (dest_machine_path, _) = {
+        let snapshots_path = snapshots_path(state_dir);
+        machine
+            .store_if_needed(&snapshots_path)
+            .map_err(anyhow::Error::from)?
+    };
+
+    client.run(|c| {
+        let mut tx = c.transaction().map_err(anyhow::Error::from)?;
+        consensus_data::update_last_processed_block(
+            &mut tx,
+            checkpoint.epoch.block_created_number,
+        )?;
+        consensus_data::insert_first_epoch(&mut tx, &checkpoint.epoch)?;
+        consensus_data::insert_inputs(&mut tx, checkpoint.inputs.iter())?;
+        rollup_data::insert_snapshot(&mut tx, epoch_number, 0, &state_hash, &dest_machine_path)?;
+        tx.commit().map_err(anyhow::Error::from)?;
+
+        Ok(())
+    })
+}
+
+fn create_migrated_client(database: &Config) -> Result<PgClient> {
+    let mut client = PgClient::connect(database)?;
+    client
+        .run(|c| c.batch_execute(SCHEMA))
+        .map_err(anyhow::Error::from)?;
+
+    Ok(client)
+}
+
+/// Like [crate::sql::migrate], with `state_dir` holding only the snapshots.
+pub fn migrate(
+    database: &Config,
+    state_dir: &Path,
+    initial_machine_path: &Path,
+    genesis_block_number: u64,
+) -> Result<PgClient> {
+    let mut client = create_migrated_client(database)?;
+    client.run(|c| set_genesis(c, genesis_block_number))?;
+    set_initial_machine(&mut client, state_dir, initial_machine_path)?;
+
+    Ok(client)
+}
+
+/// Like [crate::sql::migrate_from_checkpoint], with `state_dir` holding only the snapshots.
+pub fn migrate_from_checkpoint(
+    database: &Config,
+    state_dir: &Path,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<PgClient> {
+    let mut client = create_migrated_client(database)?;
+    if !client.run(rollup_data::has_snapshots)? {
+        set_checkpoint(&mut client, state_dir, checkpoint)?;
+    }
+
+    Ok(client)
+}
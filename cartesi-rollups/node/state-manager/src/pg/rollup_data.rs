@@ -0,0 +1,717 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{from_i64, to_i64};
+use crate::{
+    CommitmentLeaf, InputExecution, InputId, InputProgress, Proof, Settlement,
+    retention::EpochSnapshots, state_manager::Result,
+};
+
+use alloy::primitives::Address;
+use anyhow::Context;
+use cartesi_machine::types::Hash;
+use cartesi_prt_core::strategy::scheduler::Deadline;
+use postgres::{GenericClient, Row, Transaction};
+
+fn convert_row_to_commitment_leaf(row: &Row) -> CommitmentLeaf {
+    let hash: Vec<u8> = row.get(0);
+    CommitmentLeaf {
+        hash: hash
+            .try_into()
+            .expect("machine_state_hash should have 32 bytes"),
+        repetitions: from_i64(row.get(1)),
+    }
+}
+
+fn convert_row_to_settlement(row: &Row) -> Settlement {
+    let ch_blob: Vec<u8> = row.get(0);
+    let ch_arr: [u8; 32] = ch_blob
+        .try_into()
+        .expect("computation_hash must be 32 bytes");
+
+    let om_blob: Vec<u8> = row.get(1);
+
+    Settlement {
+        computation_hash: ch_arr.into(),
+        output_merkle: om_blob.try_into().expect("output_merkle must be 32 bytes"),
+        output_proof: Proof::from_flattened(row.get(2)),
+    }
+}
+
+pub fn get_all_commitments(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+) -> Result<Vec<CommitmentLeaf>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT machine_state_hash, repetitions
+            FROM machine_state_hashes
+            WHERE epoch_number = $1
+            ORDER BY
+                input_number ASC,
+                hash_index   ASC
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows.iter().map(convert_row_to_commitment_leaf).collect())
+}
+
+pub fn insert_state_hashes_for_input(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+    leafs: &[CommitmentLeaf],
+) -> Result<()> {
+    let stmt = conn
+        .prepare(
+            r#"
+            INSERT INTO machine_state_hashes
+            (epoch_number, input_number, hash_index, repetitions, machine_state_hash)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    for (i, leaf) in leafs.iter().enumerate() {
+        let count = conn
+            .execute(
+                &stmt,
+                &[
+                    &to_i64(epoch_number),
+                    &to_i64(input_number),
+                    &(i as i64),
+                    &to_i64(leaf.repetitions),
+                    &leaf.hash.as_slice(),
+                ],
+            )
+            .map_err(anyhow::Error::from)?;
+
+        assert_eq!(
+            count, 1,
+            "expected exactly one row to be inserted into machine_state_hashes"
+        );
+    }
+
+    Ok(())
+}
+
+pub fn settlement_info(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+) -> Result<Option<Settlement>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT computation_hash, output_merkle, output_proof
+            FROM settlement_info
+            WHERE epoch_number = $1
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.as_ref().map(convert_row_to_settlement))
+}
+
+pub fn insert_settlement_info(
+    conn: &mut impl GenericClient,
+    settlement: &Settlement,
+    epoch_number: u64,
+) -> Result<()> {
+    let count = conn
+        .execute(
+            r#"
+            INSERT INTO settlement_info
+            (epoch_number, computation_hash, output_merkle, output_proof)
+            VALUES ($1, $2, $3, $4)
+            "#,
+            &[
+                &to_i64(epoch_number),
+                &settlement.computation_hash.data().as_slice(),
+                &settlement.output_merkle.as_slice(),
+                &settlement.output_proof.flatten(),
+            ],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    assert_eq!(count, 1, "expected exactly one row inserted");
+    Ok(())
+}
+
+pub fn insert_template_machine(conn: &mut impl GenericClient, state_hash: &Hash) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO template_machine (id, state_hash)
+        VALUES (1, $1)
+        ON CONFLICT DO NOTHING
+        "#,
+        &[&state_hash.as_slice()],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn insert_snapshot(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+    state_hash: &Hash,
+    dest_dir: &Path,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO machine_state_snapshots (state_hash, file_path)
+        VALUES ($1, $2)
+        ON CONFLICT (state_hash) DO NOTHING
+        "#,
+        &[
+            &state_hash.as_slice(),
+            &dest_dir.to_string_lossy().into_owned(),
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    conn.execute(
+        r#"
+        INSERT INTO epoch_snapshot_info (epoch_number, input_number, state_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (epoch_number, input_number) DO NOTHING
+        "#,
+        &[
+            &to_i64(epoch_number),
+            &to_i64(input_number),
+            &state_hash.as_slice(),
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+/// Epochs older than `epoch_number` that still have snapshots.
+pub fn snapshot_epochs_before(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+) -> Result<Vec<u64>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT DISTINCT epoch_number
+            FROM epoch_snapshot_info
+            WHERE epoch_number < $1
+            ORDER BY epoch_number ASC
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows.iter().map(|row| from_i64(row.get(0))).collect())
+}
+
+/// Forgets the snapshots of `epoch_number`, except for its boundary snapshot if `keep_boundary`.
+/// The snapshot files themselves are only removed by [gc_orphan_snapshots].
+pub fn delete_epoch_snapshots(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    keep_boundary: bool,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        DELETE FROM epoch_snapshot_info
+        WHERE epoch_number = $1 AND (NOT $2 OR input_number != 0)
+        "#,
+        &[&to_i64(epoch_number), &keep_boundary],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+/// Snapshots no longer referenced by any epoch nor by the template machine.
+pub fn orphan_snapshots(conn: &mut impl GenericClient) -> Result<Vec<PathBuf>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT file_path
+            FROM machine_state_snapshots
+            WHERE state_hash NOT IN (
+                SELECT state_hash FROM epoch_snapshot_info
+                UNION
+                SELECT state_hash FROM template_machine
+            )
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows
+        .iter()
+        .map(|row| PathBuf::from(row.get::<_, String>(0)))
+        .collect())
+}
+
+/// Deletes the [orphan_snapshots], along with their files.
+pub fn gc_orphan_snapshots(conn: &mut impl GenericClient) -> Result<()> {
+    let rows = conn
+        .query(
+            r#"
+            DELETE FROM machine_state_snapshots
+            WHERE state_hash NOT IN (
+                SELECT state_hash FROM epoch_snapshot_info
+                UNION
+                SELECT state_hash FROM template_machine
+            )
+            RETURNING file_path
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    for row in rows {
+        let path: String = row.get(0);
+        match std::fs::remove_dir_all(&path) {
+            // a snapshot that went missing is as good as deleted
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(anyhow::Error::from(e)
+                    .context(format!("deleting `{path}`"))
+                    .into());
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+pub fn gc_previous_advances(
+    conn: &mut impl GenericClient,
+    epoch: u64,
+    input_anchor: u64,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        DELETE FROM epoch_snapshot_info
+        WHERE epoch_number = $1 AND (input_number != $2 AND input_number != 0)
+        "#,
+        &[&to_i64(epoch), &to_i64(input_anchor)],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    gc_orphan_snapshots(conn)
+}
+
+fn convert_row_to_input_execution(row: &Row) -> InputExecution {
+    InputExecution {
+        start_mcycle: from_i64(row.get(0)),
+        end_mcycle: from_i64(row.get(1)),
+        stride_count: from_i64(row.get(2)),
+        reason: row.get::<_, Option<i32>>(3).map(|r| r as u16),
+        output_count: from_i64(row.get(4)),
+        wall_time: Duration::from_micros(from_i64(row.get(5))),
+    }
+}
+
+pub fn insert_input_execution(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+    execution: &InputExecution,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO input_executions
+        (epoch_number, input_number, start_mcycle, end_mcycle, stride_count, reason, wall_time_us,
+         output_count)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        ON CONFLICT (epoch_number, input_number) DO UPDATE SET
+            start_mcycle = EXCLUDED.start_mcycle,
+            end_mcycle = EXCLUDED.end_mcycle,
+            stride_count = EXCLUDED.stride_count,
+            reason = EXCLUDED.reason,
+            wall_time_us = EXCLUDED.wall_time_us,
+            output_count = EXCLUDED.output_count
+        "#,
+        &[
+            &to_i64(epoch_number),
+            &to_i64(input_number),
+            &to_i64(execution.start_mcycle),
+            &to_i64(execution.end_mcycle),
+            &to_i64(execution.stride_count),
+            &execution.reason.map(i32::from),
+            &to_i64(execution.wall_time.as_micros() as u64),
+            &to_i64(execution.output_count),
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn input_execution(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+) -> Result<Option<InputExecution>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT start_mcycle, end_mcycle, stride_count, reason, output_count, wall_time_us
+            FROM input_executions
+            WHERE epoch_number = $1 AND input_number = $2
+            "#,
+            &[&to_i64(epoch_number), &to_i64(input_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.as_ref().map(convert_row_to_input_execution))
+}
+
+pub fn input_executions(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+) -> Result<Vec<InputExecution>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT start_mcycle, end_mcycle, stride_count, reason, output_count, wall_time_us
+            FROM input_executions
+            WHERE epoch_number = $1
+            ORDER BY input_number ASC
+            "#,
+            &[&to_i64(epoch_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows.iter().map(convert_row_to_input_execution).collect())
+}
+
+/// Records the application as halted on `input_number` of `epoch_number`, unless it already was.
+pub fn insert_halted(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO halted (id, epoch_number, input_number)
+        VALUES (1, $1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        &[&to_i64(epoch_number), &to_i64(input_number)],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn halted_at(conn: &mut impl GenericClient) -> Result<Option<InputId>> {
+    let row = conn
+        .query_opt(
+            "SELECT epoch_number, input_number FROM halted WHERE id = 1",
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.map(|row| InputId {
+        epoch_number: from_i64(row.get(0)),
+        input_index_in_epoch: from_i64(row.get(1)),
+    }))
+}
+
+fn convert_row_to_deadline(row: &Row) -> Deadline {
+    let tournament: Vec<u8> = row.get(0);
+    let commitment: Vec<u8> = row.get(1);
+    let commitment: [u8; 32] = commitment.try_into().expect("commitment must be 32 bytes");
+    Deadline {
+        tournament: Address::from_slice(&tournament),
+        commitment: commitment.into(),
+        own: row.get(2),
+        block: from_i64(row.get(3)),
+        block_number: from_i64(row.get(4)),
+    }
+}
+
+/// Replaces the recorded deadlines with `deadlines`.
+pub fn replace_deadlines(conn: &mut impl GenericClient, deadlines: &[Deadline]) -> Result<()> {
+    conn.execute("DELETE FROM deadlines", &[])
+        .map_err(anyhow::Error::from)?;
+
+    for deadline in deadlines {
+        conn.execute(
+            r#"
+            INSERT INTO deadlines (tournament, commitment, own, block, block_number)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            &[
+                &deadline.tournament.as_slice(),
+                &deadline.commitment.slice(),
+                &deadline.own,
+                &to_i64(deadline.block),
+                &to_i64(deadline.block_number),
+            ],
+        )
+        .map_err(anyhow::Error::from)?;
+    }
+
+    Ok(())
+}
+
+pub fn deadlines(conn: &mut impl GenericClient) -> Result<Vec<Deadline>> {
+    let rows = conn
+        .query(
+            r#"
+            SELECT tournament, commitment, own, block, block_number
+            FROM deadlines
+            ORDER BY block ASC
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(rows.iter().map(convert_row_to_deadline).collect())
+}
+
+/// Records `progress` of `input_number` of `epoch_number`, on the machine stored at
+/// `machine_path`, replacing the progress of any other input. Hashes already recorded for the same
+/// input aren't inserted again.
+pub fn insert_input_progress(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+    machine_path: &Path,
+    progress: &InputProgress,
+) -> Result<()> {
+    let same_input: bool = conn
+        .query_one(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM input_progress WHERE epoch_number = $1 AND input_number = $2
+            )
+            "#,
+            &[&to_i64(epoch_number), &to_i64(input_number)],
+        )
+        .map_err(anyhow::Error::from)?
+        .get(0);
+
+    if same_input {
+        conn.execute(
+            "DELETE FROM input_progress_hashes WHERE hash_index >= $1",
+            &[&(progress.leafs.len() as i64)],
+        )
+        .map_err(anyhow::Error::from)?;
+    } else {
+        conn.execute("DELETE FROM input_progress_hashes", &[])
+            .map_err(anyhow::Error::from)?;
+    }
+
+    conn.execute(
+        r#"
+        INSERT INTO input_progress
+        (id, epoch_number, input_number, machine_path, checkpoint_hash, start_mcycle, output_count)
+        VALUES (1, $1, $2, $3, $4, $5, $6)
+        ON CONFLICT (id) DO UPDATE SET
+            epoch_number = EXCLUDED.epoch_number,
+            input_number = EXCLUDED.input_number,
+            machine_path = EXCLUDED.machine_path,
+            checkpoint_hash = EXCLUDED.checkpoint_hash,
+            start_mcycle = EXCLUDED.start_mcycle,
+            output_count = EXCLUDED.output_count
+        "#,
+        &[
+            &to_i64(epoch_number),
+            &to_i64(input_number),
+            &machine_path.to_string_lossy().as_ref(),
+            &progress.checkpoint_hash.as_slice(),
+            &to_i64(progress.start_mcycle),
+            &to_i64(progress.output_count),
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    let recorded: i64 = conn
+        .query_one("SELECT COUNT(*) FROM input_progress_hashes", &[])
+        .map_err(anyhow::Error::from)?
+        .get(0);
+
+    let stmt = conn
+        .prepare(
+            r#"
+            INSERT INTO input_progress_hashes (hash_index, machine_state_hash)
+            VALUES ($1, $2)
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    for (i, leaf) in progress.leafs.iter().enumerate().skip(recorded as usize) {
+        assert_eq!(
+            leaf.repetitions, 1,
+            "strides of a running input are never repeated"
+        );
+        conn.execute(&stmt, &[&(i as i64), &leaf.hash.as_slice()])
+            .map_err(anyhow::Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Input recorded by [insert_input_progress], with the path of its machine and its progress.
+pub fn input_progress(
+    conn: &mut impl GenericClient,
+) -> Result<Option<(InputId, PathBuf, InputProgress)>> {
+    let Some(row) = conn
+        .query_opt(
+            r#"
+            SELECT epoch_number, input_number, machine_path, checkpoint_hash, start_mcycle,
+                   output_count
+            FROM input_progress
+            WHERE id = 1
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?
+    else {
+        return Ok(None);
+    };
+
+    let leafs = conn
+        .query(
+            r#"
+            SELECT machine_state_hash, 1::BIGINT
+            FROM input_progress_hashes
+            ORDER BY hash_index ASC
+            "#,
+            &[],
+        )
+        .map_err(anyhow::Error::from)?
+        .iter()
+        .map(convert_row_to_commitment_leaf)
+        .collect();
+
+    let checkpoint_hash: Vec<u8> = row.get(3);
+    Ok(Some((
+        InputId {
+            epoch_number: from_i64(row.get(0)),
+            input_index_in_epoch: from_i64(row.get(1)),
+        },
+        PathBuf::from(row.get::<_, String>(2)),
+        InputProgress {
+            checkpoint_hash: checkpoint_hash
+                .try_into()
+                .expect("checkpoint_hash should have 32 bytes"),
+            leafs,
+            start_mcycle: from_i64(row.get(4)),
+            output_count: from_i64(row.get(5)),
+        },
+    )))
+}
+
+pub fn delete_input_progress(conn: &mut impl GenericClient) -> Result<()> {
+    conn.execute("DELETE FROM input_progress", &[])
+        .map_err(anyhow::Error::from)?;
+    conn.execute("DELETE FROM input_progress_hashes", &[])
+        .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn has_snapshots(conn: &mut impl GenericClient) -> Result<bool> {
+    let row = conn
+        .query_one("SELECT EXISTS (SELECT 1 FROM epoch_snapshot_info)", &[])
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.get(0))
+}
+
+pub fn next_input_to_be_processed(conn: &mut impl GenericClient) -> Result<InputId> {
+    let row = conn
+        .query_one(
+            r#"
+            SELECT epoch_number, input_number
+            FROM epoch_snapshot_info
+            ORDER BY
+                epoch_number DESC,
+                input_number DESC
+            LIMIT 1
+            "#,
+            &[],
+        )
+        .context("there should at least be a single latest processed")?;
+
+    Ok(InputId {
+        epoch_number: from_i64(row.get(0)),
+        input_index_in_epoch: from_i64(row.get(1)),
+    })
+}
+
+pub fn latest_snapshot_path(conn: &mut impl GenericClient) -> Result<(PathBuf, u64, u64)> {
+    let row = conn
+        .query_one(
+            r#"
+            SELECT s.file_path, e.epoch_number, e.input_number
+            FROM epoch_snapshot_info AS e
+            JOIN machine_state_snapshots AS s
+            ON s.state_hash = e.state_hash
+            ORDER BY
+                e.epoch_number DESC,
+                e.input_number DESC
+            LIMIT 1
+            "#,
+            &[],
+        )
+        .context("there should at least be a single machine")?;
+
+    let path: String = row.get(0);
+    Ok((path.into(), from_i64(row.get(1)), from_i64(row.get(2))))
+}
+
+pub fn snapshot_path_for_epoch(
+    conn: &mut impl GenericClient,
+    epoch_number: u64,
+    input_number: u64,
+) -> Result<Option<PathBuf>> {
+    let row = conn
+        .query_opt(
+            r#"
+            SELECT s.file_path
+            FROM epoch_snapshot_info AS e
+            JOIN machine_state_snapshots AS s
+            ON s.state_hash = e.state_hash
+            WHERE e.epoch_number = $1 AND e.input_number = $2
+            "#,
+            &[&to_i64(epoch_number), &to_i64(input_number)],
+        )
+        .map_err(anyhow::Error::from)?;
+
+    Ok(row.map(|row| PathBuf::from(row.get::<_, String>(0))))
+}
+
+impl EpochSnapshots for Transaction<'_> {
+    fn snapshot_epochs_before(&mut self, epoch_number: u64) -> Result<Vec<u64>> {
+        snapshot_epochs_before(self, epoch_number)
+    }
+
+    fn boundary_snapshot_path(&mut self, epoch_number: u64) -> Result<Option<PathBuf>> {
+        snapshot_path_for_epoch(self, epoch_number, 0)
+    }
+
+    fn delete_epoch_snapshots(&mut self, epoch_number: u64, keep_boundary: bool) -> Result<()> {
+        delete_epoch_snapshots(self, epoch_number, keep_boundary)
+    }
+
+    fn orphan_snapshots(&mut self) -> Result<Vec<PathBuf>> {
+        orphan_snapshots(self)
+    }
+
+    fn gc_orphan_snapshots(&mut self) -> Result<()> {
+        gc_orphan_snapshots(self)
+    }
+}
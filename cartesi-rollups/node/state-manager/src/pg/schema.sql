@@ -0,0 +1,122 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+-- mirrors `sql/migrations.sql`; snapshot files are deleted by the node instead of a trigger
+
+CREATE TABLE IF NOT EXISTS settlement_info (
+    epoch_number BIGINT NOT NULL PRIMARY KEY CHECK (epoch_number >= 0),
+    computation_hash BYTEA NOT NULL,
+    output_merkle BYTEA NOT NULL,
+    output_proof BYTEA NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS epochs (
+    epoch_number BIGINT NOT NULL PRIMARY KEY CHECK (epoch_number >= 0),
+    input_index_boundary BIGINT NOT NULL,
+    root_tournament TEXT NOT NULL,
+    block_created_number BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS inputs (
+    epoch_number BIGINT NOT NULL CHECK (epoch_number >= 0),
+    input_index_in_epoch BIGINT NOT NULL,
+    input BYTEA NOT NULL,
+    PRIMARY KEY (epoch_number, input_index_in_epoch)
+);
+
+CREATE TABLE IF NOT EXISTS latest_processed (
+    id INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    block BIGINT NOT NULL CHECK (block >= 0)
+);
+INSERT INTO latest_processed (id, block)
+    VALUES (1, 0)
+    ON CONFLICT DO NOTHING;
+
+CREATE TABLE IF NOT EXISTS machine_state_hashes (
+    epoch_number BIGINT NOT NULL,
+    input_number BIGINT NOT NULL,
+    hash_index BIGINT NOT NULL,
+    repetitions BIGINT NOT NULL CHECK (repetitions > 0),
+    machine_state_hash BYTEA NOT NULL,
+    PRIMARY KEY (epoch_number, input_number, hash_index)
+);
+
+CREATE TABLE IF NOT EXISTS machine_state_snapshots (
+    state_hash  BYTEA NOT NULL PRIMARY KEY,
+    file_path   TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS template_machine (
+    id         INTEGER PRIMARY KEY CHECK (id = 1),
+    state_hash BYTEA NOT NULL
+        UNIQUE
+        REFERENCES machine_state_snapshots (state_hash)
+        ON DELETE RESTRICT
+);
+
+CREATE TABLE IF NOT EXISTS epoch_snapshot_info (
+    epoch_number  BIGINT NOT NULL CHECK (epoch_number >= 0),
+    input_number  BIGINT NOT NULL CHECK (input_number >= 0),
+    state_hash    BYTEA NOT NULL,
+
+    PRIMARY KEY (epoch_number, input_number),
+
+    FOREIGN KEY (state_hash)
+        REFERENCES machine_state_snapshots (state_hash)
+        ON UPDATE CASCADE
+        ON DELETE RESTRICT
+);
+
+CREATE TABLE IF NOT EXISTS halted (
+    id            INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    epoch_number  BIGINT NOT NULL CHECK (epoch_number >= 0),
+    input_number  BIGINT NOT NULL CHECK (input_number >= 0)
+);
+
+CREATE TABLE IF NOT EXISTS input_executions (
+    epoch_number  BIGINT NOT NULL CHECK (epoch_number >= 0),
+    input_number  BIGINT NOT NULL CHECK (input_number >= 0),
+    start_mcycle  BIGINT NOT NULL,
+    end_mcycle    BIGINT NOT NULL,
+    stride_count  BIGINT NOT NULL,
+    reason        INTEGER,
+    wall_time_us  BIGINT NOT NULL,
+    output_count  BIGINT NOT NULL,
+    PRIMARY KEY (epoch_number, input_number)
+);
+
+CREATE TABLE IF NOT EXISTS input_progress (
+    id               INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    epoch_number     BIGINT NOT NULL CHECK (epoch_number >= 0),
+    input_number     BIGINT NOT NULL CHECK (input_number >= 0),
+    machine_path     TEXT NOT NULL,
+    checkpoint_hash  BYTEA NOT NULL,
+    start_mcycle     BIGINT NOT NULL,
+    output_count     BIGINT NOT NULL
+);
+
+-- every stride of an input that is still running is repeated once
+CREATE TABLE IF NOT EXISTS input_progress_hashes (
+    hash_index          BIGINT NOT NULL PRIMARY KEY CHECK (hash_index >= 0),
+    machine_state_hash  BYTEA NOT NULL
+);
+
+-- decoded from the `EvmAdvance` encoding of the input and the log that added it; null for inputs
+-- stored before they were kept
+ALTER TABLE inputs
+    ADD COLUMN IF NOT EXISTS sender TEXT,
+    ADD COLUMN IF NOT EXISTS block_number BIGINT,
+    ADD COLUMN IF NOT EXISTS block_timestamp BIGINT,
+    ADD COLUMN IF NOT EXISTS prev_randao BYTEA,
+    ADD COLUMN IF NOT EXISTS app_input_index BIGINT,
+    ADD COLUMN IF NOT EXISTS transaction_hash BYTEA,
+    ADD COLUMN IF NOT EXISTS log_index BIGINT;
+
+-- tournament clock deadlines observed in the latest dispute reaction
+CREATE TABLE IF NOT EXISTS deadlines (
+    tournament    BYTEA NOT NULL,
+    commitment    BYTEA NOT NULL,
+    own           BOOLEAN NOT NULL,
+    block         BIGINT NOT NULL,
+    block_number  BIGINT NOT NULL
+);
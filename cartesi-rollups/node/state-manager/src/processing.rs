@@ -0,0 +1,129 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Processing of the inputs in the state, shared by the machine runner and by the commands that
+//! reprocess the inputs of a state they rewound or rebuilt.
+
+use cartesi_machine::types::cmio::ManualReason;
+
+use crate::{
+    InputId, StateManager,
+    rollups_machine::{InputOutcome, STRIDE_COUNT_IN_INPUT},
+    state_manager::Result,
+};
+
+/// Strides between checkpoints of an input that is still running, which a restarted node resumes
+/// the input from.
+pub const INPUT_CHECKPOINT_INTERVAL: u64 = 1 << 12;
+
+/// Processes every input currently available, rolling over each sealed epoch.
+pub fn process_rollup(state_manager: &mut impl StateManager) -> Result<()> {
+    loop {
+        catch_up(state_manager)?;
+
+        let current_machine_epoch = state_manager.next_input_id()?.epoch_number;
+        let latest_blockchain_epoch = state_manager.epoch_count()?;
+
+        if current_machine_epoch == latest_blockchain_epoch {
+            // all current inputs processed in current epoch, which is still open
+            break Ok(());
+        } else {
+            // epoch is finished, all inputs processed
+            assert!(current_machine_epoch < latest_blockchain_epoch);
+            state_manager.roll_epoch()?;
+            log::info!("started new epoch {}", current_machine_epoch + 1);
+        }
+    }
+}
+
+/// Processes the inputs available in the epoch of the machine, resuming the input left running.
+fn catch_up(state_manager: &mut impl StateManager) -> Result<()> {
+    let (mut rollups_machine, mut resume) = match state_manager.input_progress()? {
+        Some((machine, progress)) => {
+            log::info!(
+                "resuming input {}:{} after {} strides",
+                machine.epoch(),
+                machine.next_input_index_in_epoch(),
+                progress.leafs.len()
+            );
+            (machine, Some(progress))
+        }
+        None => (state_manager.latest_snapshot()?, None),
+    };
+
+    loop {
+        let input_id = InputId {
+            epoch_number: rollups_machine.epoch(),
+            input_index_in_epoch: rollups_machine.next_input_index_in_epoch(),
+        };
+        let Some(input) = state_manager.input(&input_id)? else {
+            break Ok(());
+        };
+
+        log::info!(
+            "processing input {}:{}",
+            input.id.epoch_number,
+            input.id.input_index_in_epoch
+        );
+        let (state_hashes, outcome, execution) = rollups_machine.process_input_checkpointed(
+            &input.data,
+            resume.take(),
+            INPUT_CHECKPOINT_INTERVAL,
+            |machine, progress| {
+                log::debug!(
+                    "checkpointing input {}:{} after {} strides",
+                    machine.epoch(),
+                    machine.next_input_index_in_epoch(),
+                    progress.leafs.len()
+                );
+                state_manager.save_input_progress(machine, progress)
+            },
+        )?;
+        log::debug!(
+            "input {}:{} ran {} mcycles in {} of {} strides, emitting {} outputs in {:?}",
+            input.id.epoch_number,
+            input.id.input_index_in_epoch,
+            execution.end_mcycle - execution.start_mcycle,
+            execution.stride_count,
+            STRIDE_COUNT_IN_INPUT,
+            execution.output_count,
+            execution.wall_time
+        );
+        if execution.stride_count > STRIDE_COUNT_IN_INPUT / 2 {
+            log::warn!(
+                "input {}:{} used {} of the {} strides an input may run for",
+                input.id.epoch_number,
+                input.id.input_index_in_epoch,
+                execution.stride_count,
+                STRIDE_COUNT_IN_INPUT
+            );
+        }
+        state_manager.insert_input_execution(&input.id, &execution)?;
+
+        match outcome {
+            InputOutcome::Yielded(ManualReason::RxAccepted { .. }) => {
+                state_manager.advance_accepted(&mut rollups_machine, &state_hashes)?;
+            }
+            InputOutcome::Halted => {
+                log::warn!(
+                    "application halted on input {}:{}",
+                    input.id.epoch_number,
+                    input.id.input_index_in_epoch
+                );
+                state_manager.advance_halted(&mut rollups_machine, &state_hashes)?;
+            }
+            InputOutcome::Skipped => {
+                log::warn!(
+                    "skipped input {}:{}, sent after the application halted",
+                    input.id.epoch_number,
+                    input.id.input_index_in_epoch
+                );
+                // the machine stays where it halted, as if it rejected the input
+                state_manager.advance_reverted(&mut rollups_machine, &state_hashes)?;
+            }
+            InputOutcome::Yielded(_) => {
+                state_manager.advance_reverted(&mut rollups_machine, &state_hashes)?;
+            }
+        }
+    }
+}
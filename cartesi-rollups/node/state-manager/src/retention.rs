@@ -0,0 +1,251 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Which epoch boundary snapshots survive once their epoch is settled, and what happens to the ones
+//! that don't.
+
+use std::{
+    fmt,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{Context, anyhow};
+use flate2::{Compression, write::GzEncoder};
+
+use crate::state_manager::Result;
+
+/// Retention of epoch boundary snapshots. The snapshots of the two most recent epochs are always
+/// kept, since the node still runs and disputes them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// keep the boundary snapshots of the last `n` epochs
+    KeepLast(u64),
+    /// keep the boundary snapshot of every epoch that is a multiple of `k`
+    KeepEvery(u64),
+    /// keep every boundary snapshot
+    KeepAll,
+}
+
+impl RetentionPolicy {
+    /// Whether the boundary snapshot of `epoch_number` is kept once `current_epoch` is open.
+    pub fn retains(&self, epoch_number: u64, current_epoch: u64) -> bool {
+        if epoch_number + 2 > current_epoch {
+            return true;
+        }
+
+        match self {
+            RetentionPolicy::KeepLast(n) => epoch_number.saturating_add(*n) > current_epoch,
+            RetentionPolicy::KeepEvery(k) => epoch_number.is_multiple_of(*k),
+            RetentionPolicy::KeepAll => true,
+        }
+    }
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy::KeepLast(2)
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RetentionPolicy::KeepLast(n) => write!(f, "last:{n}"),
+            RetentionPolicy::KeepEvery(k) => write!(f, "every:{k}"),
+            RetentionPolicy::KeepAll => write!(f, "all"),
+        }
+    }
+}
+
+/// Parses `all`, `last:<n>` or `every:<k>`.
+impl FromStr for RetentionPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(RetentionPolicy::KeepAll);
+        }
+
+        let (kind, count) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow!("expected `all`, `last:<n>` or `every:<k>`, got `{s}`"))?;
+        let count: u64 = count
+            .parse()
+            .with_context(|| format!("invalid count in retention policy `{s}`"))?;
+        if count == 0 {
+            return Err(anyhow!("count of retention policy `{s}` must be positive"));
+        }
+
+        match kind {
+            "last" => Ok(RetentionPolicy::KeepLast(count)),
+            "every" => Ok(RetentionPolicy::KeepEvery(count)),
+            _ => Err(anyhow!("unknown retention policy `{kind}`")),
+        }
+    }
+}
+
+/// Snapshot records of a state, as far as collecting the snapshots of settled epochs needs them.
+pub(crate) trait EpochSnapshots {
+    /// Epochs older than `epoch_number` that still have snapshots.
+    fn snapshot_epochs_before(&mut self, epoch_number: u64) -> Result<Vec<u64>>;
+    /// Directory of the boundary snapshot of `epoch_number`, if it has one.
+    fn boundary_snapshot_path(&mut self, epoch_number: u64) -> Result<Option<PathBuf>>;
+    /// Forgets the snapshots of `epoch_number`, except for its boundary snapshot if
+    /// `keep_boundary`, leaving the snapshots themselves to [Self::gc_orphan_snapshots].
+    fn delete_epoch_snapshots(&mut self, epoch_number: u64, keep_boundary: bool) -> Result<()>;
+    /// Directories of the snapshots no longer used by any epoch nor by the template machine.
+    fn orphan_snapshots(&mut self) -> Result<Vec<PathBuf>>;
+    /// Deletes the [Self::orphan_snapshots].
+    fn gc_orphan_snapshots(&mut self) -> Result<()>;
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SnapshotRetention {
+    pub policy: RetentionPolicy,
+    /// cold-storage directory evicted boundary snapshots are archived to; they are deleted if unset
+    pub archive_dir: Option<PathBuf>,
+}
+
+impl SnapshotRetention {
+    /// Drops the snapshots of epochs settled before `current_epoch` that the policy doesn't keep,
+    /// archiving evicted boundary snapshots first if configured to.
+    pub(crate) fn gc_old_epochs(
+        &self,
+        snapshots: &mut impl EpochSnapshots,
+        current_epoch: u64,
+    ) -> Result<()> {
+        let mut evicted = Vec::new();
+        for epoch_number in snapshots.snapshot_epochs_before(current_epoch.saturating_sub(1))? {
+            let retained = self.policy.retains(epoch_number, current_epoch);
+            if !retained && let Some(path) = snapshots.boundary_snapshot_path(epoch_number)? {
+                evicted.push((epoch_number, path));
+            }
+            snapshots.delete_epoch_snapshots(epoch_number, retained)?;
+        }
+
+        // a boundary snapshot another epoch still uses stays where it is
+        let orphans = snapshots.orphan_snapshots()?;
+        for (epoch_number, path) in evicted {
+            if orphans.contains(&path) {
+                self.archive(epoch_number, &path)?;
+            }
+        }
+
+        snapshots.gc_orphan_snapshots()
+    }
+
+    /// Compresses the boundary snapshot of `epoch_number` at `snapshot_path` into
+    /// `epoch-<epoch_number>.tar.gz` in the archive directory, if there is one.
+    pub fn archive(&self, epoch_number: u64, snapshot_path: &Path) -> Result<()> {
+        let Some(archive_dir) = &self.archive_dir else {
+            return Ok(());
+        };
+
+        fs::create_dir_all(archive_dir)
+            .with_context(|| format!("creating `{}`", archive_dir.display()))?;
+
+        let name = format!("epoch-{epoch_number}");
+        let dest = archive_dir.join(format!("{name}.tar.gz"));
+        let partial = archive_dir.join(format!("{name}.tar.gz.partial"));
+
+        let file =
+            File::create(&partial).with_context(|| format!("creating `{}`", partial.display()))?;
+        let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        builder
+            .append_dir_all(&name, snapshot_path)
+            .with_context(|| format!("archiving `{}`", snapshot_path.display()))?;
+        builder
+            .into_inner()
+            .and_then(|encoder| encoder.finish())
+            .and_then(|file| file.sync_all())
+            .with_context(|| format!("writing `{}`", partial.display()))?;
+
+        // only a complete archive takes the final name
+        fs::rename(&partial, &dest).with_context(|| format!("creating `{}`", dest.display()))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::GzDecoder;
+
+    #[test]
+    fn test_retains() {
+        let last = RetentionPolicy::KeepLast(4);
+        assert!(!last.retains(5, 10));
+        assert!(!last.retains(6, 10));
+        assert!(last.retains(7, 10));
+        assert!(last.retains(9, 10));
+
+        let every = RetentionPolicy::KeepEvery(3);
+        assert!(every.retains(0, 10));
+        assert!(!every.retains(4, 10));
+        assert!(every.retains(6, 10));
+        // the two most recent epochs are always kept
+        assert!(every.retains(9, 10));
+        assert!(every.retains(10, 10));
+
+        assert!(RetentionPolicy::KeepAll.retains(0, 10));
+        assert!(!RetentionPolicy::default().retains(8, 10));
+    }
+
+    #[test]
+    fn test_parse() {
+        for policy in [
+            RetentionPolicy::KeepLast(2),
+            RetentionPolicy::KeepEvery(100),
+            RetentionPolicy::KeepAll,
+        ] {
+            assert_eq!(
+                policy.to_string().parse::<RetentionPolicy>().unwrap(),
+                policy
+            );
+        }
+
+        assert!("last:0".parse::<RetentionPolicy>().is_err());
+        assert!("every".parse::<RetentionPolicy>().is_err());
+        assert!("first:2".parse::<RetentionPolicy>().is_err());
+        assert!("last:x".parse::<RetentionPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let snapshot = dir.path().join("snapshot");
+        fs::create_dir_all(&snapshot).unwrap();
+        fs::write(snapshot.join("config.json"), b"{}").unwrap();
+
+        let retention = SnapshotRetention {
+            policy: RetentionPolicy::KeepAll,
+            archive_dir: Some(dir.path().join("cold")),
+        };
+        retention.archive(7, &snapshot).unwrap();
+
+        let archive = dir.path().join("cold").join("epoch-7.tar.gz");
+        assert!(
+            !dir.path()
+                .join("cold")
+                .join("epoch-7.tar.gz.partial")
+                .exists()
+        );
+
+        let mut entries: Vec<_> = tar::Archive::new(GzDecoder::new(File::open(archive).unwrap()))
+            .entries()
+            .unwrap()
+            .map(|e| e.unwrap().path().unwrap().into_owned())
+            .collect();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                PathBuf::from("epoch-7"),
+                PathBuf::from("epoch-7/config.json")
+            ]
+        );
+    }
+}
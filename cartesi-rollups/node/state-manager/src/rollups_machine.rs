@@ -1,13 +1,20 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use std::path::{Path, PathBuf};
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use cartesi_prt_core::machine::constants::{
     LOG2_BARCH_SPAN_TO_INPUT, LOG2_INPUT_SPAN_TO_EPOCH, LOG2_UARCH_SPAN_TO_BARCH,
 };
 
-use crate::{CommitmentLeaf, Proof};
+use crate::{
+    CommitmentLeaf, InputExecution, InputProgress, Proof,
+    console::{self, ConsoleCapture, ConsoleLog},
+    stride_hashes::{HASH_PIPELINE_DEPTH, StrideHashes},
+};
 use cartesi_machine::{
     config::runtime::{HTIFRuntimeConfig, RuntimeConfig},
     constants::{break_reason, pma::TX_START},
@@ -15,10 +22,11 @@ use cartesi_machine::{
     machine::Machine,
     types::{
         Hash,
-        cmio::{CmioRequest, CmioResponseReason, ManualReason},
+        cmio::{AutomaticReason, CmioRequest, CmioResponseReason, ManualReason},
     },
 };
 
+use log::warn;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -47,10 +55,24 @@ pub const STRIDE_COUNT_IN_EPOCH: u64 = 1
 
 pub const CHECKPOINT_ADDRESS: u64 = 0x7ffff000;
 
+/// How the application machine ended an input.
+#[derive(Clone, Debug)]
+pub enum InputOutcome {
+    /// machine yielded manually, accepting or rejecting the input
+    Yielded(ManualReason),
+    /// machine halted on this input, and can't be sent any other
+    Halted,
+    /// machine had halted before this input, which it wasn't sent, staying where it halted
+    Skipped,
+}
+
 pub struct RollupsMachine {
     machine: Machine,
     epoch_number: u64,
     next_input_index_in_epoch: u64,
+    console: Option<ConsoleCapture>,
+    console_log: Option<ConsoleLog>,
+    hash_pipeline_depth: usize,
 }
 
 impl RollupsMachine {
@@ -59,9 +81,21 @@ impl RollupsMachine {
         epoch_number: u64,
         next_input_index_in_epoch: u64,
     ) -> MachineResult<Self> {
+        Self::load(path, epoch_number, next_input_index_in_epoch, None)
+    }
+
+    /// Like [Self::new], but the console of each processed input goes to `console`, if any.
+    pub fn load(
+        path: &Path,
+        epoch_number: u64,
+        next_input_index_in_epoch: u64,
+        console: Option<ConsoleCapture>,
+    ) -> MachineResult<Self> {
+        // elsewhere than on Linux the console is dropped, as if it wasn't captured
+        let console = console.filter(|_| console::SUPPORTED);
         let runtime_config = RuntimeConfig {
             htif: Some(HTIFRuntimeConfig {
-                no_console_putchar: Some(true),
+                no_console_putchar: Some(console.is_none()),
             }),
             ..Default::default()
         };
@@ -71,9 +105,20 @@ impl RollupsMachine {
             machine,
             epoch_number,
             next_input_index_in_epoch,
+            console,
+            console_log: None,
+            hash_pipeline_depth: HASH_PIPELINE_DEPTH,
         })
     }
 
+    /// Hashes up to `depth` strides in a row while running the ones after them, instead of
+    /// stopping to hash each of them. Zero hashes every stride in place. Leafs are the same
+    /// either way.
+    pub fn with_hash_pipeline_depth(mut self, depth: usize) -> Self {
+        self.hash_pipeline_depth = depth;
+        self
+    }
+
     pub fn epoch(&self) -> u64 {
         self.epoch_number
     }
@@ -100,37 +145,152 @@ impl RollupsMachine {
         self.machine.root_hash()
     }
 
+    pub fn is_halted(&mut self) -> MachineResult<bool> {
+        self.machine.iflags_h()
+    }
+
     pub fn process_input(
         &mut self,
         data: &[u8],
-    ) -> MachineResult<(Vec<CommitmentLeaf>, ManualReason)> {
-        assert!(self.machine.iflags_y()?);
-        assert!(matches!(
-            self.machine.receive_cmio_request()?,
-            CmioRequest::Manual(ManualReason::RxAccepted { .. })
-        ));
+    ) -> MachineResult<(Vec<CommitmentLeaf>, InputOutcome, InputExecution)> {
+        self.process_input_checkpointed(data, None, u64::MAX, |_, _| Ok(()))
+    }
+
+    /// Like [Self::process_input], but calls `checkpoint` with the machine and the progress of the
+    /// input every `interval` strides. Passing that progress back as `resume`, on the machine
+    /// stored at that point, carries on with the input instead of starting it over.
+    pub fn process_input_checkpointed<E: From<MachineError>>(
+        &mut self,
+        data: &[u8],
+        resume: Option<InputProgress>,
+        interval: u64,
+        checkpoint: impl FnMut(&mut Self, &InputProgress) -> Result<(), E>,
+    ) -> Result<(Vec<CommitmentLeaf>, InputOutcome, InputExecution), E> {
+        if let Some(console) = &self.console {
+            match console.start(self.epoch_number, self.next_input_index_in_epoch) {
+                Ok(log) => self.console_log = Some(log),
+                Err(e) => warn!(
+                    "could not capture console of input {}:{}: {e}",
+                    self.epoch_number, self.next_input_index_in_epoch
+                ),
+            }
+        }
 
-        let checkpoint_hash = self.machine.root_hash()?;
-        self.feed_input(data, &checkpoint_hash)?;
-        self.run_machine(BIG_STEPS_IN_STRIDE)?;
+        let result = self.run_input(data, resume, interval, checkpoint);
+        if let Some(log) = self.console_log.take() {
+            log.finish();
+        }
+        result
+    }
+
+    fn run_input<E: From<MachineError>>(
+        &mut self,
+        data: &[u8],
+        resume: Option<InputProgress>,
+        interval: u64,
+        mut checkpoint: impl FnMut(&mut Self, &InputProgress) -> Result<(), E>,
+    ) -> Result<(Vec<CommitmentLeaf>, InputOutcome, InputExecution), E> {
+        let started = Instant::now();
+
+        let mut progress = match resume {
+            // the stored machine stopped right after the last stride of `progress`
+            Some(progress) => progress,
+            None => {
+                let start_mcycle = self.machine.mcycle()?;
+
+                // `CartesiStateTransition.transitionState` sends every input there is, and sending
+                // one to a machine that halted fails, in the emulator and in the step library
+                // generated from it alike: no state follows an input after a halt. It isn't sent,
+                // and the state the machine halted at is committed to for the whole input.
+                if self.machine.iflags_h()? {
+                    self.next_input_index_in_epoch += 1;
+                    let execution = InputExecution {
+                        start_mcycle,
+                        end_mcycle: start_mcycle,
+                        stride_count: 0,
+                        reason: None,
+                        wall_time: started.elapsed(),
+                        output_count: 0,
+                    };
+                    let halted = CommitmentLeaf {
+                        hash: self.machine.root_hash()?,
+                        repetitions: STRIDE_COUNT_IN_INPUT,
+                    };
+                    return Ok((vec![halted], InputOutcome::Skipped, execution));
+                }
+
+                if self.machine.iflags_y()? {
+                    assert!(matches!(
+                        self.machine.receive_cmio_request()?,
+                        CmioRequest::Manual(ManualReason::RxAccepted { .. })
+                    ));
+                }
+
+                let checkpoint_hash = self.machine.root_hash()?;
+                self.feed_input(data, &checkpoint_hash)?;
+
+                InputProgress {
+                    checkpoint_hash,
+                    leafs: Vec::with_capacity(1 << 20),
+                    start_mcycle,
+                    output_count: 0,
+                }
+            }
+        };
+
+        progress.output_count += self.run_machine(BIG_STEPS_IN_STRIDE)?;
+
+        // the next stride runs while the ones before it are still being hashed
+        let mut hashes = StrideHashes::new(self.hash_pipeline_depth);
+        while !self.machine.iflags_y()? && !self.machine.iflags_h()? {
+            hashes.push(&mut self.machine, &mut progress.leafs)?;
+
+            if (progress.leafs.len() + hashes.pending()) as u64 % interval == 0 {
+                hashes.finish(&mut progress.leafs)?;
+                checkpoint(self, &progress)?;
+            }
 
-        let mut state_hashes = Vec::with_capacity(1 << 20);
-        let mut i: u64 = 0;
+            progress.output_count += self.run_machine(BIG_STEPS_IN_STRIDE)?;
+        }
+        hashes.finish(&mut progress.leafs)?;
+
+        let InputProgress {
+            checkpoint_hash,
+            leafs: mut state_hashes,
+            start_mcycle,
+            output_count,
+        } = progress;
+        let i = state_hashes.len() as u64;
+
+        self.next_input_index_in_epoch += 1;
+        let mut execution = InputExecution {
+            start_mcycle,
+            end_mcycle: self.machine.mcycle()?,
+            stride_count: i + 1,
+            reason: None,
+            wall_time: Duration::ZERO,
+            output_count,
+        };
 
-        while !self.machine.iflags_y()? {
-            let hash = self.machine.root_hash()?;
+        // unlike a rejected input, halting isn't reverted: `CmioStateTransition.revertIfNeeded`
+        // only reverts a machine that yielded, and the micro architecture can't move a halted
+        // one, so its state is the fixed point every remaining stride of the input ends at
+        if self.machine.iflags_h()? {
+            let halted_hash = self.machine.root_hash()?;
             state_hashes.push(CommitmentLeaf {
-                hash,
-                repetitions: 1,
+                hash: halted_hash,
+                repetitions: STRIDE_COUNT_IN_INPUT - i,
             });
-            i += 1;
 
-            self.run_machine(BIG_STEPS_IN_STRIDE)?;
+            execution.wall_time = started.elapsed();
+            return Ok((state_hashes, InputOutcome::Halted, execution));
         }
 
-        self.next_input_index_in_epoch += 1;
+        let request = self.machine.receive_cmio_request()?;
+        execution.reason = Some(request.cmd_and_reason().1);
+        execution.wall_time = started.elapsed();
 
-        match self.machine.receive_cmio_request()? {
+        match request {
             CmioRequest::Manual(reason @ ManualReason::RxAccepted { .. }) => {
                 let fixed_point_hash = self.machine.root_hash()?;
                 state_hashes.push(CommitmentLeaf {
@@ -138,7 +298,7 @@ impl RollupsMachine {
                     repetitions: STRIDE_COUNT_IN_INPUT - i,
                 });
 
-                Ok((state_hashes, reason))
+                Ok((state_hashes, InputOutcome::Yielded(reason), execution))
             }
 
             CmioRequest::Manual(reason) => {
@@ -147,7 +307,7 @@ impl RollupsMachine {
                     repetitions: STRIDE_COUNT_IN_INPUT - i,
                 });
 
-                Ok((state_hashes, reason))
+                Ok((state_hashes, InputOutcome::Yielded(reason), execution))
             }
             _ => {
                 unreachable!("machine should be manually yielded");
@@ -162,20 +322,17 @@ impl RollupsMachine {
             .send_cmio_response(CmioResponseReason::Advance, input)
     }
 
-    fn run_machine(&mut self, cycles: u64) -> MachineResult<()> {
-        let mcycle = self.machine.mcycle()?;
-
-        loop {
-            let reason = self.machine.run(mcycle + cycles)?;
-            match reason {
-                break_reason::YIELDED_AUTOMATICALLY | break_reason::YIELDED_SOFTLY => continue,
-
-                break_reason::YIELDED_MANUALLY | break_reason::REACHED_TARGET_MCYCLE => {
-                    break Ok(());
-                }
-
-                _ => panic!("machine returned invalid `break_reason` {reason}"),
+    /// Runs for up to `cycles`, returning how many outputs the machine emitted meanwhile.
+    ///
+    /// With a console log, the machine runs on the thread of the input whose standard output goes
+    /// to that log, while checkpoints and everything else stay on the calling thread.
+    fn run_machine(&mut self, cycles: u64) -> MachineResult<u64> {
+        match &self.console_log {
+            Some(log) => {
+                let machine = SendMachine(&mut self.machine);
+                log.run(move || machine.run(cycles))
             }
+            None => run_machine(&mut self.machine, cycles),
         }
     }
 
@@ -191,28 +348,285 @@ impl RollupsMachine {
         let dest_machine_path = machine_store_path(snapshots_path, &state_hash);
 
         if !dest_machine_path.exists() {
-            let machine_status = self.machine.store(&dest_machine_path);
-
-            if let Err(machine_err) = machine_status {
-                // cleanup partial store before returning error.
-                let fs_status = std::fs::remove_dir_all(&dest_machine_path);
-
-                // combine errors
-                if let Err(fs_err) = fs_status {
-                    return Err(StoreError::CleanupError {
-                        machine_err,
-                        fs_err,
-                    });
-                } else {
-                    return Err(machine_err.into());
-                }
-            }
+            self.store(&dest_machine_path)?;
         }
 
         Ok((dest_machine_path, state_hash))
     }
+
+    /// Stores the machine at `path`, which must not exist yet.
+    pub fn store(&mut self, path: &Path) -> Result<(), StoreError> {
+        if let Err(machine_err) = self.machine.store(path) {
+            // cleanup partial store before returning error.
+            let fs_status = std::fs::remove_dir_all(path);
+
+            // combine errors
+            if let Err(fs_err) = fs_status {
+                return Err(StoreError::CleanupError {
+                    machine_err,
+                    fs_err,
+                });
+            } else {
+                return Err(machine_err.into());
+            }
+        }
+
+        Ok(())
+    }
 }
 
 fn machine_store_path(snapshots_path: &Path, state_hash: &cartesi_machine::types::Hash) -> PathBuf {
     snapshots_path.join(format!("0x{}", hex::encode(state_hash)))
 }
+
+/// Runs `machine` for up to `cycles`, returning how many outputs it emitted meanwhile.
+fn run_machine(machine: &mut Machine, cycles: u64) -> MachineResult<u64> {
+    let mcycle = machine.mcycle()?;
+    let mut output_count = 0;
+
+    loop {
+        let reason = machine.run(mcycle + cycles)?;
+        match reason {
+            break_reason::YIELDED_AUTOMATICALLY => {
+                if let CmioRequest::Automatic(AutomaticReason::TxOutput { .. }) =
+                    machine.receive_cmio_request()?
+                {
+                    output_count += 1;
+                }
+            }
+
+            break_reason::YIELDED_SOFTLY => continue,
+
+            break_reason::YIELDED_MANUALLY
+            | break_reason::REACHED_TARGET_MCYCLE
+            | break_reason::HALTED => {
+                break Ok(output_count);
+            }
+
+            _ => panic!("machine returned invalid `break_reason` {reason}"),
+        }
+    }
+}
+
+struct SendMachine<'a>(&'a mut Machine);
+
+// SAFETY: the emulator doesn't tie a machine to the thread that created it, and `ConsoleLog::run`
+// waits for the job it hands the machine to, so only one thread ever uses it at a time.
+unsafe impl Send for SendMachine<'_> {}
+
+impl SendMachine<'_> {
+    // takes `self` so that closures capture the whole wrapper instead of the machine inside it
+    fn run(self, cycles: u64) -> MachineResult<u64> {
+        run_machine(self.0, cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use alloy::primitives::U256;
+    use cartesi_dave_arithmetic::MetaCycle;
+    use cartesi_dave_merkle::Digest;
+    use cartesi_prt_core::{
+        db::dispute_state_access::{DisputeStateAccess, Input},
+        machine::{
+            MachineInstance, build_machine_commitment,
+            constants::{BARCH_SPAN_TO_INPUT, UARCH_SPAN_TO_BARCH},
+        },
+    };
+
+    /// Accepts its first input and halts on the second.
+    const HALT_MACHINE_PATH: &str = "../../../test/programs/halt/machine-image";
+
+    fn dispute_state(dir: &Path, inputs: &[&[u8]]) -> DisputeStateAccess {
+        let inputs = inputs.iter().map(|i| Input(i.to_vec())).collect();
+        DisputeStateAccess::new(inputs, Vec::new(), String::new(), dir.to_path_buf()).unwrap()
+    }
+
+    #[test]
+    fn test_halted_machine() {
+        let inputs: [&[u8]; 3] = [b"accepted", b"halting", b"after the halt"];
+        let mut machine = RollupsMachine::new(Path::new(HALT_MACHINE_PATH), 0, 0).unwrap();
+
+        let (_, outcome, _) = machine.process_input(inputs[0]).unwrap();
+        assert!(matches!(
+            outcome,
+            InputOutcome::Yielded(ManualReason::RxAccepted { .. })
+        ));
+        let (leafs, outcome, _) = machine.process_input(inputs[1]).unwrap();
+        assert!(matches!(outcome, InputOutcome::Halted));
+        let halted_hash = machine.state_hash().unwrap();
+        assert_eq!(leafs.last().unwrap().hash, halted_hash);
+        assert_eq!(
+            leafs.iter().map(|l| l.repetitions).sum::<u64>(),
+            STRIDE_COUNT_IN_INPUT
+        );
+
+        // a dispute over the input commits to the same leafs
+        let dir = tempfile::tempdir().unwrap();
+        let db = dispute_state(&dir.path().join("three inputs"), &inputs);
+        let input_start: U256 = MetaCycle::input_start(1).unwrap().into();
+        let mut instance =
+            MachineInstance::new_rollups_advanced_until(HALT_MACHINE_PATH, input_start, &db)
+                .unwrap();
+        let commitment = build_machine_commitment(
+            &mut instance,
+            input_start,
+            0,
+            LOG2_STRIDE,
+            STRIDE_COUNT_IN_INPUT.trailing_zeros() as u64,
+            &db,
+        )
+        .unwrap();
+        let dispute_leafs: Vec<(Hash, u64)> = commitment
+            .iter()
+            .map(|(tree, repetitions)| (tree.root_hash().into(), *repetitions))
+            .collect();
+        let node_leafs: Vec<(Hash, u64)> = leafs.iter().map(|l| (l.hash, l.repetitions)).collect();
+        assert_eq!(dispute_leafs, node_leafs);
+
+        // resetting the micro architecture at the end of the input leaves the machine halted where
+        // it was, and the proof of it replays as the chain would
+        let input_end: U256 = MetaCycle::new(1, BARCH_SPAN_TO_INPUT, UARCH_SPAN_TO_BARCH)
+            .unwrap()
+            .into();
+        let agree_hash =
+            MachineInstance::new_rollups_advanced_until(HALT_MACHINE_PATH, input_end, &db)
+                .unwrap()
+                .root_hash()
+                .unwrap();
+        let (_, next_hash) =
+            MachineInstance::get_logs(HALT_MACHINE_PATH, agree_hash, input_end, &db).unwrap();
+        assert_eq!(next_hash, Digest::new(halted_hash));
+
+        // past the inputs of the epoch the halted machine only steps
+        let next_input: U256 = MetaCycle::input_start(2).unwrap().into();
+        let two_inputs = dispute_state(&dir.path().join("two inputs"), &inputs[..2]);
+        MachineInstance::get_logs(
+            HALT_MACHINE_PATH,
+            Digest::new(halted_hash),
+            next_input,
+            &two_inputs,
+        )
+        .unwrap();
+
+        // but no state follows sending it another input
+        assert!(
+            MachineInstance::get_logs(
+                HALT_MACHINE_PATH,
+                Digest::new(halted_hash),
+                next_input,
+                &db,
+            )
+            .is_err()
+        );
+
+        // so the node doesn't send it, staying where the machine halted
+        let (leafs, outcome, execution) = machine.process_input(inputs[2]).unwrap();
+        assert!(matches!(outcome, InputOutcome::Skipped));
+        assert_eq!(
+            leafs,
+            vec![CommitmentLeaf {
+                hash: halted_hash,
+                repetitions: STRIDE_COUNT_IN_INPUT,
+            }]
+        );
+        assert_eq!(execution.stride_count, 0);
+        assert_eq!(machine.state_hash().unwrap(), halted_hash);
+        assert_eq!(machine.next_input_index_in_epoch(), 3);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_console_capture_keeps_execution() {
+        const ECHO_MACHINE_PATH: &str = "../../../test/programs/echo/machine-image";
+        let inputs: [&[u8]; 3] = [b"first", b"second", b"third"];
+        let dir = tempfile::tempdir().unwrap();
+        let console = ConsoleCapture::new(dir.path(), 1 << 20, 10);
+
+        let mut quiet = RollupsMachine::new(Path::new(ECHO_MACHINE_PATH), 0, 0).unwrap();
+        let mut captured =
+            RollupsMachine::load(Path::new(ECHO_MACHINE_PATH), 0, 0, Some(console.clone()))
+                .unwrap();
+
+        for (i, input) in inputs.into_iter().enumerate() {
+            let (quiet_leafs, quiet_outcome, _) = quiet.process_input(input).unwrap();
+            let (captured_leafs, captured_outcome, _) = captured.process_input(input).unwrap();
+
+            assert_eq!(quiet_leafs, captured_leafs);
+            assert_eq!(
+                format!("{quiet_outcome:?}"),
+                format!("{captured_outcome:?}")
+            );
+            assert_eq!(quiet.state_hash().unwrap(), captured.state_hash().unwrap());
+            assert!(console.log_path(0, i as u64).exists());
+        }
+    }
+
+    #[test]
+    fn test_pipelined_hashing_matches_in_place() {
+        const ECHO_MACHINE_PATH: &str = "../../../test/programs/echo/machine-image";
+        // echoing it back as outputs takes the machine several strides
+        let input = vec![0x5a; 1 << 20];
+
+        let mut in_place = RollupsMachine::new(Path::new(ECHO_MACHINE_PATH), 0, 0)
+            .unwrap()
+            .with_hash_pipeline_depth(0);
+        let mut pipelined = RollupsMachine::new(Path::new(ECHO_MACHINE_PATH), 0, 0)
+            .unwrap()
+            .with_hash_pipeline_depth(1);
+
+        let (leafs, outcome, execution) = in_place.process_input(&input).unwrap();
+        let (pipelined_leafs, pipelined_outcome, pipelined_execution) =
+            pipelined.process_input(&input).unwrap();
+
+        assert_eq!(pipelined_leafs, leafs);
+        assert_eq!(format!("{pipelined_outcome:?}"), format!("{outcome:?}"));
+        assert_eq!(pipelined_execution.output_count, execution.output_count);
+        assert_eq!(
+            pipelined.state_hash().unwrap(),
+            in_place.state_hash().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_resumed_input_matches_uninterrupted() {
+        const ECHO_MACHINE_PATH: &str = "../../../test/programs/echo/machine-image";
+        // echoing it back as outputs takes the machine several strides
+        let input = vec![0x5a; 1 << 20];
+        let dir = tempfile::tempdir().unwrap();
+        let stored = dir.path().join("checkpoint");
+
+        let mut uninterrupted = RollupsMachine::new(Path::new(ECHO_MACHINE_PATH), 0, 0).unwrap();
+        let (leafs, outcome, execution) = uninterrupted.process_input(&input).unwrap();
+
+        // the node goes down right after storing the first checkpoint
+        let mut saved = None;
+        let mut interrupted = RollupsMachine::new(Path::new(ECHO_MACHINE_PATH), 0, 0).unwrap();
+        let crashed =
+            interrupted.process_input_checkpointed(&input, None, 1, |machine, progress| {
+                machine.store(&stored)?;
+                saved = Some(progress.clone());
+                Err(anyhow::anyhow!("crashed"))
+            });
+        assert!(crashed.is_err());
+        let progress = saved.unwrap();
+        assert!(progress.leafs.len() < leafs.len());
+
+        let mut resumed = RollupsMachine::new(&stored, 0, 0).unwrap();
+        let (resumed_leafs, resumed_outcome, resumed_execution) = resumed
+            .process_input_checkpointed(&input, Some(progress), u64::MAX, |_, _| {
+                Ok::<_, MachineError>(())
+            })
+            .unwrap();
+
+        assert_eq!(resumed_leafs, leafs);
+        assert_eq!(format!("{resumed_outcome:?}"), format!("{outcome:?}"));
+        assert_eq!(resumed_execution.output_count, execution.output_count);
+        assert_eq!(
+            resumed.state_hash().unwrap(),
+            uninterrupted.state_hash().unwrap()
+        );
+    }
+}
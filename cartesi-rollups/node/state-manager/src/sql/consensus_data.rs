@@ -2,13 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use crate::{
-    Epoch, Input, InputId,
+    Epoch, Input, InputId, InputMetadata,
     state_manager::{Result, StateAccessError},
 };
 
 use alloy::{
     hex::{FromHex, ToHexExt},
-    primitives::Address,
+    primitives::{Address, U256},
 };
 use rusqlite::{OptionalExtension, params};
 
@@ -22,6 +22,25 @@ fn convert_row_to_epoch(row: &rusqlite::Row) -> rusqlite::Result<Epoch> {
     })
 }
 
+fn convert_row_to_input_metadata(row: &rusqlite::Row) -> rusqlite::Result<Option<InputMetadata>> {
+    let sender: Option<String> = row.get(1)?;
+    let Some(sender) = sender else {
+        return Ok(None);
+    };
+
+    let prev_randao: Vec<u8> = row.get(4)?;
+    let transaction_hash: Vec<u8> = row.get(6)?;
+    Ok(Some(InputMetadata {
+        sender: Address::from_hex(sender).unwrap(),
+        block_number: row.get(2)?,
+        block_timestamp: row.get(3)?,
+        prev_randao: U256::from_be_slice(&prev_randao),
+        index: row.get(5)?,
+        transaction_hash: transaction_hash.try_into().unwrap(),
+        log_index: row.get(7)?,
+    }))
+}
+
 //
 // Last Processed
 //
@@ -89,10 +108,18 @@ pub fn insert_inputs<'a>(
             });
         }
 
+        let metadata = input.metadata.as_ref();
         stmt.execute(params![
             input.id.epoch_number,
             input.id.input_index_in_epoch,
-            input.data
+            input.data,
+            metadata.map(|m| m.sender.encode_hex()),
+            metadata.map(|m| m.block_number),
+            metadata.map(|m| m.block_timestamp),
+            metadata.map(|m| m.prev_randao.to_be_bytes_vec()),
+            metadata.map(|m| m.index),
+            metadata.map(|m| m.transaction_hash.to_vec()),
+            metadata.map(|m| m.log_index),
         ])
         .map_err(anyhow::Error::from)?;
 
@@ -106,7 +133,10 @@ fn insert_input_statement(conn: &rusqlite::Connection) -> Result<rusqlite::State
     Ok(conn
         .prepare(
             "\
-        INSERT INTO inputs (epoch_number, input_index_in_epoch, input) VALUES (?1, ?2, ?3)
+        INSERT INTO inputs (
+            epoch_number, input_index_in_epoch, input, sender, block_number, block_timestamp,
+            prev_randao, app_input_index, transaction_hash, log_index
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
         ",
         )
         .map_err(anyhow::Error::from)?)
@@ -138,7 +168,9 @@ pub fn input(conn: &rusqlite::Connection, id: &InputId) -> Result<Option<Input>>
     let mut stmt = conn
         .prepare(
             "\
-        SELECT * FROM inputs
+        SELECT input, sender, block_number, block_timestamp, prev_randao, app_input_index,
+            transaction_hash, log_index
+        FROM inputs
         WHERE epoch_number = ?1 AND input_index_in_epoch = ?2
         ",
         )
@@ -148,7 +180,8 @@ pub fn input(conn: &rusqlite::Connection, id: &InputId) -> Result<Option<Input>>
         .query_row(params![id.epoch_number, id.input_index_in_epoch], |row| {
             Ok(Input {
                 id: id.clone(),
-                data: row.get(2)?,
+                data: row.get(0)?,
+                metadata: convert_row_to_input_metadata(row)?,
             })
         })
         .optional()
@@ -232,6 +265,29 @@ pub fn insert_epochs<'a>(
     Ok(())
 }
 
+/// Inserts the first epoch a node knows of, which is not epoch zero when it syncs from a
+/// checkpoint.
+pub fn insert_first_epoch(conn: &rusqlite::Connection, epoch: &Epoch) -> Result<()> {
+    let count = epoch_count(conn)?;
+    if count != 0 {
+        return Err(StateAccessError::InconsistentEpoch {
+            expected: count,
+            provided: epoch.epoch_number,
+        });
+    }
+
+    insert_epoch_statement(conn)?
+        .execute(params![
+            epoch.epoch_number,
+            epoch.input_index_boundary,
+            epoch.root_tournament.encode_hex(),
+            epoch.block_created_number
+        ])
+        .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
 fn insert_epoch_statement(conn: &rusqlite::Connection) -> Result<rusqlite::Statement<'_>> {
     Ok(conn.prepare(
         "\
@@ -363,6 +419,7 @@ mod inputs_tests {
                         input_index_in_epoch: 0
                     },
                     data: data.clone(),
+                    metadata: None,
                 }]
                 .into_iter(),
             ),
@@ -403,6 +460,7 @@ mod inputs_tests {
                             input_index_in_epoch: 1
                         },
                         data: data.clone(),
+                        metadata: None,
                     },
                     &Input {
                         id: InputId {
@@ -410,6 +468,7 @@ mod inputs_tests {
                             input_index_in_epoch: 2
                         },
                         data: data.clone(),
+                        metadata: None,
                     },
                     &Input {
                         id: InputId {
@@ -417,6 +476,7 @@ mod inputs_tests {
                             input_index_in_epoch: 0
                         },
                         data: data.clone(),
+                        metadata: None,
                     },
                     &Input {
                         id: InputId {
@@ -424,6 +484,7 @@ mod inputs_tests {
                             input_index_in_epoch: 0
                         },
                         data: data.clone(),
+                        metadata: None,
                     }
                 ]
                 .into_iter(),
@@ -469,6 +530,7 @@ mod inputs_tests {
                         input_index_in_epoch: 1
                     },
                     data: data.clone(),
+                    metadata: None,
                 }]
                 .into_iter(),
             )
@@ -483,6 +545,7 @@ mod inputs_tests {
                         input_index_in_epoch: 1
                     },
                     data: data.clone(),
+                    metadata: None,
                 }]
                 .into_iter(),
             )
@@ -511,6 +574,7 @@ mod inputs_tests {
                             input_index_in_epoch: 0
                         },
                         data: data.clone(),
+                        metadata: None,
                     },
                     &Input {
                         id: InputId {
@@ -518,6 +582,7 @@ mod inputs_tests {
                             input_index_in_epoch: 2
                         },
                         data: data.clone(),
+                        metadata: None,
                     },
                 ]
                 .into_iter(),
@@ -549,6 +614,67 @@ mod inputs_tests {
         ));
         assert!(matches!(input_count(&conn, 0,), Ok(1)));
     }
+
+    #[test]
+    fn test_metadata() {
+        let (_handle, conn) = test_helper::setup_db();
+        let metadata = InputMetadata {
+            sender: Address::repeat_byte(7),
+            block_number: 120,
+            block_timestamp: 1_700_000_000,
+            prev_randao: U256::MAX - U256::from(1),
+            index: 42,
+            transaction_hash: [9; 32],
+            log_index: 3,
+        };
+
+        insert_inputs(
+            &conn,
+            [
+                &Input {
+                    id: InputId {
+                        epoch_number: 0,
+                        input_index_in_epoch: 0,
+                    },
+                    data: vec![1],
+                    metadata: Some(metadata.clone()),
+                },
+                &Input {
+                    id: InputId {
+                        epoch_number: 0,
+                        input_index_in_epoch: 1,
+                    },
+                    data: vec![2],
+                    metadata: None,
+                },
+            ]
+            .into_iter(),
+        )
+        .unwrap();
+
+        let first = input(
+            &conn,
+            &InputId {
+                epoch_number: 0,
+                input_index_in_epoch: 0,
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(first.data, vec![1]);
+        assert_eq!(first.metadata, Some(metadata));
+
+        let second = input(
+            &conn,
+            &InputId {
+                epoch_number: 0,
+                input_index_in_epoch: 1,
+            },
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(second.metadata, None);
+    }
 }
 
 #[cfg(test)]
@@ -683,3 +809,37 @@ mod epochs_tests {
         ));
     }
 }
+
+#[cfg(test)]
+mod first_epoch_tests {
+    use super::*;
+    use crate::sql::test_helper;
+
+    fn epoch(epoch_number: u64) -> Epoch {
+        Epoch {
+            epoch_number,
+            input_index_boundary: 10,
+            root_tournament: Address::ZERO,
+            block_created_number: 40,
+        }
+    }
+
+    #[test]
+    fn test_first_epoch() {
+        let (_handle, conn) = test_helper::setup_db();
+
+        insert_first_epoch(&conn, &epoch(7)).unwrap();
+        assert!(matches!(epoch_count(&conn), Ok(8)));
+        assert!(matches!(
+            insert_first_epoch(&conn, &epoch(9)),
+            Err(StateAccessError::InconsistentEpoch {
+                expected: 8,
+                provided: 9
+            })
+        ));
+
+        // later epochs follow the first one
+        assert!(insert_epochs(&conn, [&epoch(8)].into_iter()).is_ok());
+        assert!(insert_epochs(&conn, [&epoch(10)].into_iter()).is_err());
+    }
+}
@@ -3,8 +3,14 @@ use rusqlite::Connection;
 use rusqlite_migration::{M, Migrations};
 
 lazy_static! {
-    pub static ref MIGRATIONS: Migrations<'static> =
-        Migrations::new(vec![M::up(include_str!("migrations.sql")),]);
+    pub static ref MIGRATIONS: Migrations<'static> = Migrations::new(vec![
+        M::up(include_str!("migrations.sql")),
+        M::up(include_str!("migrations_halted.sql")),
+        M::up(include_str!("migrations_input_executions.sql")),
+        M::up(include_str!("migrations_input_progress.sql")),
+        M::up(include_str!("migrations_input_metadata.sql")),
+        M::up(include_str!("migrations_deadlines.sql")),
+    ]);
 }
 
 pub fn migrate_to_latest(conn: &mut Connection) -> Result<(), rusqlite_migration::Error> {
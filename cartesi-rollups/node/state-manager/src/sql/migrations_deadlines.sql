@@ -0,0 +1,11 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+-- tournament clock deadlines observed in the latest dispute reaction
+CREATE TABLE IF NOT EXISTS deadlines (
+    tournament    BLOB    NOT NULL,
+    commitment    BLOB    NOT NULL,
+    own           INTEGER NOT NULL,
+    block         INTEGER NOT NULL,
+    block_number  INTEGER NOT NULL
+);
@@ -0,0 +1,8 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE IF NOT EXISTS halted (
+    id            INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    epoch_number  INTEGER NOT NULL CHECK (epoch_number >= 0),
+    input_number  INTEGER NOT NULL CHECK (input_number >= 0)
+);
@@ -0,0 +1,14 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE IF NOT EXISTS input_executions (
+    epoch_number  INTEGER NOT NULL CHECK (epoch_number >= 0),
+    input_number  INTEGER NOT NULL CHECK (input_number >= 0),
+    start_mcycle  INTEGER NOT NULL,
+    end_mcycle    INTEGER NOT NULL,
+    stride_count  INTEGER NOT NULL,
+    reason        INTEGER,
+    wall_time_us  INTEGER NOT NULL,
+    output_count  INTEGER NOT NULL,
+    PRIMARY KEY (epoch_number, input_number)
+);
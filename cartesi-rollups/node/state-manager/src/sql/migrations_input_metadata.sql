@@ -0,0 +1,12 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+-- decoded from the `EvmAdvance` encoding of the input and the log that added it; null for inputs
+-- stored before this migration
+ALTER TABLE inputs ADD COLUMN sender TEXT;
+ALTER TABLE inputs ADD COLUMN block_number INTEGER;
+ALTER TABLE inputs ADD COLUMN block_timestamp INTEGER;
+ALTER TABLE inputs ADD COLUMN prev_randao BLOB;
+ALTER TABLE inputs ADD COLUMN app_input_index INTEGER;
+ALTER TABLE inputs ADD COLUMN transaction_hash BLOB;
+ALTER TABLE inputs ADD COLUMN log_index INTEGER;
@@ -0,0 +1,18 @@
+-- (c) Cartesi and individual authors (see AUTHORS)
+-- SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+CREATE TABLE IF NOT EXISTS input_progress (
+    id               INTEGER NOT NULL PRIMARY KEY CHECK (id = 1),
+    epoch_number     INTEGER NOT NULL CHECK (epoch_number >= 0),
+    input_number     INTEGER NOT NULL CHECK (input_number >= 0),
+    machine_path     TEXT    NOT NULL,
+    checkpoint_hash  BLOB    NOT NULL,
+    start_mcycle     INTEGER NOT NULL,
+    output_count     INTEGER NOT NULL
+);
+
+-- every stride of an input that is still running is repeated once
+CREATE TABLE IF NOT EXISTS input_progress_hashes (
+    hash_index          INTEGER NOT NULL PRIMARY KEY CHECK (hash_index >= 0),
+    machine_state_hash  BLOB    NOT NULL
+);
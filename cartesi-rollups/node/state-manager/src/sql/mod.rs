@@ -8,9 +8,13 @@ pub mod rollup_data;
 #[cfg(test)]
 pub(crate) mod test_helper;
 
-use crate::{rollups_machine::RollupsMachine, state_manager::Result};
+use crate::{
+    TrustedCheckpoint,
+    rollups_machine::RollupsMachine,
+    state_manager::{Result, StateAccessError},
+};
 use anyhow::Context;
-use rusqlite::{Connection, functions::FunctionFlags};
+use rusqlite::{Connection, OpenFlags, functions::FunctionFlags};
 use std::{
     fs,
     path::{Path, PathBuf},
@@ -20,8 +24,13 @@ pub fn fs_delete_dir(
     ctx: &rusqlite::functions::Context,
 ) -> std::result::Result<rusqlite::types::Null, rusqlite::Error> {
     let path: String = ctx.get(0)?;
-    std::fs::remove_dir_all(&path).map_err(|e| rusqlite::Error::UserFunctionError(Box::new(e)))?;
-    Ok(rusqlite::types::Null)
+    match std::fs::remove_dir_all(&path) {
+        // a snapshot that went missing is as good as deleted
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(rusqlite::Error::UserFunctionError(Box::new(e)))
+        }
+        _ => Ok(rusqlite::types::Null),
+    }
 }
 
 fn set_genesis(connection: &Connection, block_number: u64) -> Result<()> {
@@ -66,6 +75,40 @@ fn set_initial_machine(
     Ok(())
 }
 
+fn set_checkpoint(
+    connection: &mut Connection,
+    state_dir: &Path,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<()> {
+    let epoch_number = checkpoint.epoch.epoch_number;
+    let mut machine = RollupsMachine::new(&checkpoint.machine_path, epoch_number, 0)?;
+
+    let state_hash = machine.state_hash()?;
+    if state_hash != checkpoint.state_hash {
+        return Err(StateAccessError::CheckpointMismatch {
+            epoch_number,
+            expected: hex::encode(checkpoint.state_hash),
+            provided: hex::encode(state_hash),
+        });
+    }
+
+    let (dest_machine_path, _) = {
+        let snapshots_path = snapshots_path(state_dir);
+        machine
+            .store_if_needed(&snapshots_path)
+            .map_err(anyhow::Error::from)?
+    };
+
+    let tx = connection.transaction().map_err(anyhow::Error::from)?;
+    consensus_data::update_last_processed_block(&tx, checkpoint.epoch.block_created_number)?;
+    consensus_data::insert_first_epoch(&tx, &checkpoint.epoch)?;
+    consensus_data::insert_inputs(&tx, checkpoint.inputs.iter())?;
+    rollup_data::insert_snapshot(&tx, epoch_number, 0, &state_hash, &dest_machine_path)?;
+    tx.commit().map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
 pub fn set_scalar_function(connection: &Connection) -> Result<()> {
     connection
         .create_scalar_function(
@@ -80,8 +123,23 @@ pub fn set_scalar_function(connection: &Connection) -> Result<()> {
 }
 
 pub fn create_connection(state_dir: &Path) -> Result<Connection> {
+    let connection = Connection::open(db_path(state_dir)).map_err(anyhow::Error::from)?;
+    configure_connection(connection)
+}
+
+/// Like [create_connection], but fails if there's no database yet instead of creating an empty
+/// one, which would hide that the state is missing.
+pub fn open_connection(state_dir: &Path) -> Result<Connection> {
     let db_path = db_path(state_dir);
-    let connection = Connection::open(db_path).map_err(anyhow::Error::from)?;
+    let connection = Connection::open_with_flags(
+        &db_path,
+        OpenFlags::default() & !OpenFlags::SQLITE_OPEN_CREATE,
+    )
+    .with_context(|| format!("opening `{}`", db_path.display()))?;
+    configure_connection(connection)
+}
+
+fn configure_connection(connection: Connection) -> Result<Connection> {
     connection
         .busy_timeout(std::time::Duration::from_secs(10))
         .map_err(anyhow::Error::from)?;
@@ -91,11 +149,7 @@ pub fn create_connection(state_dir: &Path) -> Result<Connection> {
     Ok(connection)
 }
 
-pub fn migrate(
-    state_dir: &Path,
-    initial_machine_path: &Path,
-    genesis_block_number: u64,
-) -> Result<Connection> {
+fn create_migrated_connection(state_dir: &Path) -> Result<Connection> {
     create_directory_structure(state_dir)?;
     let mut connection = create_connection(state_dir)?;
 
@@ -105,12 +159,36 @@ pub fn migrate(
         .map_err(anyhow::Error::from)?;
 
     migrations::migrate_to_latest(&mut connection).map_err(anyhow::Error::from)?;
+
+    Ok(connection)
+}
+
+pub fn migrate(
+    state_dir: &Path,
+    initial_machine_path: &Path,
+    genesis_block_number: u64,
+) -> Result<Connection> {
+    let mut connection = create_migrated_connection(state_dir)?;
     set_genesis(&connection, genesis_block_number)?;
     set_initial_machine(&mut connection, state_dir, initial_machine_path)?;
 
     Ok(connection)
 }
 
+/// Like [migrate], but a fresh state starts from `checkpoint` instead of genesis. A state that was
+/// already synced is left as is.
+pub fn migrate_from_checkpoint(
+    state_dir: &Path,
+    checkpoint: &TrustedCheckpoint,
+) -> Result<Connection> {
+    let mut connection = create_migrated_connection(state_dir)?;
+    if !rollup_data::has_snapshots(&connection)? {
+        set_checkpoint(&mut connection, state_dir, checkpoint)?;
+    }
+
+    Ok(connection)
+}
+
 //
 // Directory structure
 //
@@ -128,6 +206,41 @@ pub fn snapshots_path(state_dir: &Path) -> PathBuf {
     state_dir.to_owned().join("snapshots")
 }
 
+/// Where the machine of an input that is still running is stored, see
+/// [crate::StateManager::save_input_progress].
+pub fn input_progress_path(state_dir: &Path) -> PathBuf {
+    state_dir.to_owned().join("input_progress")
+}
+
+/// Path for the machine of `input_number` of `epoch_number` after `stride_count` strides.
+pub fn input_progress_machine_path(
+    state_dir: &Path,
+    epoch_number: u64,
+    input_number: u64,
+    stride_count: usize,
+) -> PathBuf {
+    input_progress_path(state_dir).join(format!("{epoch_number}-{input_number}-{stride_count}"))
+}
+
+/// Removes every machine stored for input progress but `keep`, including the ones left behind by
+/// a crash midway through a save.
+pub fn prune_input_progress(state_dir: &Path, keep: Option<&Path>) -> Result<()> {
+    let dir = input_progress_path(state_dir);
+    let entries = match fs::read_dir(&dir) {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        entries => entries.with_context(|| format!("reading `{}`", dir.display()))?,
+    };
+
+    for entry in entries {
+        let path = entry.map_err(anyhow::Error::from)?.path();
+        if Some(path.as_path()) != keep {
+            fs::remove_dir_all(&path).with_context(|| format!("removing `{}`", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn create_directory_structure(state_dir: &Path) -> Result<()> {
     create_empty_state_dir_if_needed(state_dir)?;
 
@@ -1,13 +1,21 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use crate::{CommitmentLeaf, InputId, Proof, Settlement, state_manager::Result};
+use crate::{
+    CommitmentLeaf, InputExecution, InputId, InputProgress, Proof, Settlement,
+    retention::EpochSnapshots, state_manager::Result,
+};
 
+use alloy::primitives::Address;
 use cartesi_machine::types::Hash;
+use cartesi_prt_core::strategy::scheduler::Deadline;
 
-use rusqlite::{Connection, OptionalExtension, params};
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
 
 fn convert_row_to_commitment_leaf(r: &rusqlite::Row) -> rusqlite::Result<CommitmentLeaf> {
     let hash: Hash = {
@@ -203,16 +211,75 @@ pub fn insert_snapshot(
     Ok(())
 }
 
-pub fn gc_old_epochs(conn: &Connection, max_epoch: u64) -> Result<()> {
+/// Epochs older than `epoch_number` that still have snapshots.
+pub fn snapshot_epochs_before(conn: &Connection, epoch_number: u64) -> Result<Vec<u64>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT DISTINCT epoch_number
+            FROM epoch_snapshot_info
+            WHERE epoch_number < ?1
+            ORDER BY epoch_number ASC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let epochs = stmt
+        .query_map([epoch_number], |row| row.get(0))
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<u64>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(epochs)
+}
+
+/// Forgets the snapshots of `epoch_number`, except for its boundary snapshot if `keep_boundary`.
+/// The snapshot files themselves are only removed by [gc_orphan_snapshots].
+pub fn delete_epoch_snapshots(
+    conn: &Connection,
+    epoch_number: u64,
+    keep_boundary: bool,
+) -> Result<()> {
     conn.execute(
         r#"
         DELETE FROM epoch_snapshot_info
-        WHERE epoch_number <= ?1
+        WHERE epoch_number = ?1 AND (?2 = 0 OR input_number != 0)
         "#,
-        [max_epoch],
+        params![epoch_number, keep_boundary],
     )
     .map_err(anyhow::Error::from)?;
 
+    Ok(())
+}
+
+/// Snapshots no longer referenced by any epoch nor by the template machine.
+pub fn orphan_snapshots(conn: &Connection) -> Result<Vec<PathBuf>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT file_path
+            FROM machine_state_snapshots
+            WHERE state_hash NOT IN (
+                SELECT state_hash FROM epoch_snapshot_info
+                UNION
+                SELECT state_hash FROM template_machine
+            )
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let paths = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(anyhow::Error::from)?
+        .map(|path| path.map(PathBuf::from))
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(paths)
+}
+
+/// Deletes the [orphan_snapshots], whose files are removed by `trg_delete_snapshot_files`.
+pub fn gc_orphan_snapshots(conn: &Connection) -> Result<()> {
     conn.execute_batch(
         r#"
         DELETE FROM machine_state_snapshots
@@ -238,21 +305,496 @@ pub fn gc_previous_advances(conn: &Connection, epoch: u64, input_anchor: u64) ->
     )
     .map_err(anyhow::Error::from)?;
 
-    conn.execute_batch(
+    gc_orphan_snapshots(conn)
+}
+
+/// Every stored snapshot, along with the first epoch and input it is the state of, if any.
+pub fn all_snapshots(conn: &Connection) -> Result<Vec<(Hash, PathBuf, Option<InputId>)>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT s.state_hash, s.file_path, e.epoch_number, e.input_number
+            FROM machine_state_snapshots AS s
+            LEFT JOIN epoch_snapshot_info AS e
+            ON e.state_hash = s.state_hash
+            ORDER BY
+                s.state_hash     ASC,
+                e.epoch_number   ASC,
+                e.input_number   ASC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let rows = stmt
+        .query_map([], |row| {
+            let hash: Vec<u8> = row.get(0)?;
+            let path: String = row.get(1)?;
+            let epoch_number: Option<u64> = row.get(2)?;
+            let input_number: Option<u64> = row.get(3)?;
+            Ok((hash, path, epoch_number.zip(input_number)))
+        })
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    let mut snapshots: Vec<(Hash, PathBuf, Option<InputId>)> = Vec::new();
+    for (hash, path, position) in rows {
+        let hash: Hash = hash.try_into().expect("state_hash should have 32 bytes");
+        // rows of a snapshot are sorted, so the first one is its earliest use
+        if snapshots.last().is_some_and(|(h, _, _)| *h == hash) {
+            continue;
+        }
+        let first_use = position.map(|(epoch_number, input_index_in_epoch)| InputId {
+            epoch_number,
+            input_index_in_epoch,
+        });
+        snapshots.push((hash, path.into(), first_use));
+    }
+
+    Ok(snapshots)
+}
+
+/// Every epoch and input that has a snapshot, along with its state hash, latest first.
+pub fn snapshot_positions(conn: &Connection) -> Result<Vec<(InputId, Hash)>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT epoch_number, input_number, state_hash
+            FROM epoch_snapshot_info
+            ORDER BY
+                epoch_number DESC,
+                input_number DESC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let positions = stmt
+        .query_map([], |row| {
+            let hash: Vec<u8> = row.get(2)?;
+            Ok((
+                InputId {
+                    epoch_number: row.get(0)?,
+                    input_index_in_epoch: row.get(1)?,
+                },
+                hash.try_into().expect("state_hash should have 32 bytes"),
+            ))
+        })
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(positions)
+}
+
+pub fn settled_epochs(conn: &Connection) -> Result<Vec<u64>> {
+    let mut stmt = conn
+        .prepare_cached("SELECT epoch_number FROM settlement_info ORDER BY epoch_number ASC")
+        .map_err(anyhow::Error::from)?;
+
+    let epochs = stmt
+        .query_map([], |row| row.get(0))
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<u64>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(epochs)
+}
+
+/// Forgets all rollup data computed after the snapshot of `epoch_number` and `input_number`, so
+/// that the inputs that follow it are processed again. The snapshot files themselves are only
+/// removed by [gc_orphan_snapshots].
+pub fn truncate_after(conn: &Connection, epoch_number: u64, input_number: u64) -> Result<()> {
+    conn.execute(
         r#"
-        DELETE FROM machine_state_snapshots
-        WHERE state_hash NOT IN (
-            SELECT state_hash FROM epoch_snapshot_info
-            UNION
-            SELECT state_hash FROM template_machine
-        );
+        DELETE FROM epoch_snapshot_info
+        WHERE epoch_number > ?1 OR (epoch_number = ?1 AND input_number > ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    // hashes of input `n` are computed from the snapshot of input `n`
+    conn.execute(
+        r#"
+        DELETE FROM machine_state_hashes
+        WHERE epoch_number > ?1 OR (epoch_number = ?1 AND input_number >= ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    conn.execute(
+        "DELETE FROM settlement_info WHERE epoch_number >= ?1",
+        [epoch_number],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    conn.execute(
+        r#"
+        DELETE FROM halted
+        WHERE epoch_number > ?1 OR (epoch_number = ?1 AND input_number >= ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+    conn.execute(
+        r#"
+        DELETE FROM input_executions
+        WHERE epoch_number > ?1 OR (epoch_number = ?1 AND input_number >= ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    conn.execute(
+        r#"
+        DELETE FROM input_progress
+        WHERE epoch_number > ?1 OR (epoch_number = ?1 AND input_number >= ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+    conn.execute(
+        "DELETE FROM input_progress_hashes WHERE NOT EXISTS (SELECT 1 FROM input_progress)",
+        [],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+fn convert_row_to_input_execution(row: &rusqlite::Row) -> rusqlite::Result<InputExecution> {
+    let wall_time_us: u64 = row.get(5)?;
+    Ok(InputExecution {
+        start_mcycle: row.get(0)?,
+        end_mcycle: row.get(1)?,
+        stride_count: row.get(2)?,
+        reason: row.get(3)?,
+        wall_time: Duration::from_micros(wall_time_us),
+        output_count: row.get(4)?,
+    })
+}
+
+pub fn insert_input_execution(
+    conn: &Connection,
+    epoch_number: u64,
+    input_number: u64,
+    execution: &InputExecution,
+) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT OR REPLACE INTO input_executions
+        (epoch_number, input_number, start_mcycle, end_mcycle, stride_count, reason, wall_time_us,
+         output_count)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
         "#,
+        params![
+            epoch_number,
+            input_number,
+            execution.start_mcycle,
+            execution.end_mcycle,
+            execution.stride_count,
+            execution.reason,
+            execution.wall_time.as_micros() as u64,
+            execution.output_count,
+        ],
     )
     .map_err(anyhow::Error::from)?;
 
     Ok(())
 }
 
+pub fn input_execution(
+    conn: &Connection,
+    epoch_number: u64,
+    input_number: u64,
+) -> Result<Option<InputExecution>> {
+    Ok(conn
+        .query_row(
+            r#"
+            SELECT start_mcycle, end_mcycle, stride_count, reason, output_count, wall_time_us
+            FROM input_executions
+            WHERE epoch_number = ?1 AND input_number = ?2
+            "#,
+            [epoch_number, input_number],
+            convert_row_to_input_execution,
+        )
+        .optional()
+        .map_err(anyhow::Error::from)?)
+}
+
+pub fn input_executions(conn: &Connection, epoch_number: u64) -> Result<Vec<InputExecution>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT start_mcycle, end_mcycle, stride_count, reason, output_count, wall_time_us
+            FROM input_executions
+            WHERE epoch_number = ?1
+            ORDER BY input_number ASC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let executions = stmt
+        .query_map([epoch_number], convert_row_to_input_execution)
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(executions)
+}
+
+/// Records the application as halted on `input_number` of `epoch_number`, unless it already was.
+pub fn insert_halted(conn: &Connection, epoch_number: u64, input_number: u64) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT OR IGNORE INTO halted (id, epoch_number, input_number)
+        VALUES (1, ?1, ?2)
+        "#,
+        [epoch_number, input_number],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn halted_at(conn: &Connection) -> Result<Option<InputId>> {
+    Ok(conn
+        .query_row(
+            "SELECT epoch_number, input_number FROM halted WHERE id = 1",
+            [],
+            |row| {
+                Ok(InputId {
+                    epoch_number: row.get(0)?,
+                    input_index_in_epoch: row.get(1)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(anyhow::Error::from)?)
+}
+
+fn convert_row_to_deadline(row: &rusqlite::Row) -> rusqlite::Result<Deadline> {
+    let tournament: Vec<u8> = row.get(0)?;
+    let commitment: [u8; 32] = row
+        .get::<_, Vec<u8>>(1)?
+        .try_into()
+        .expect("commitment must be 32 bytes");
+    Ok(Deadline {
+        tournament: Address::from_slice(&tournament),
+        commitment: commitment.into(),
+        own: row.get(2)?,
+        block: row.get(3)?,
+        block_number: row.get(4)?,
+    })
+}
+
+/// Replaces the recorded deadlines with `deadlines`.
+pub fn replace_deadlines(conn: &Connection, deadlines: &[Deadline]) -> Result<()> {
+    conn.execute("DELETE FROM deadlines", [])
+        .map_err(anyhow::Error::from)?;
+
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            INSERT INTO deadlines (tournament, commitment, own, block, block_number)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+    for deadline in deadlines {
+        stmt.execute(params![
+            deadline.tournament.as_slice(),
+            deadline.commitment.slice(),
+            deadline.own,
+            deadline.block,
+            deadline.block_number,
+        ])
+        .map_err(anyhow::Error::from)?;
+    }
+
+    Ok(())
+}
+
+pub fn deadlines(conn: &Connection) -> Result<Vec<Deadline>> {
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT tournament, commitment, own, block, block_number
+            FROM deadlines
+            ORDER BY block ASC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let deadlines = stmt
+        .query_map([], convert_row_to_deadline)
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(deadlines)
+}
+
+/// Records `progress` of `input_number` of `epoch_number`, on the machine stored at
+/// `machine_path`, replacing the progress of any other input. Hashes already recorded for the same
+/// input aren't inserted again.
+pub fn insert_input_progress(
+    conn: &Connection,
+    epoch_number: u64,
+    input_number: u64,
+    machine_path: &Path,
+    progress: &InputProgress,
+) -> Result<()> {
+    let same_input: bool = conn
+        .query_row(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM input_progress WHERE epoch_number = ?1 AND input_number = ?2
+            )
+            "#,
+            [epoch_number, input_number],
+            |row| row.get(0),
+        )
+        .map_err(anyhow::Error::from)?;
+
+    if same_input {
+        conn.execute(
+            "DELETE FROM input_progress_hashes WHERE hash_index >= ?1",
+            [progress.leafs.len()],
+        )
+        .map_err(anyhow::Error::from)?;
+    } else {
+        conn.execute("DELETE FROM input_progress_hashes", [])
+            .map_err(anyhow::Error::from)?;
+    }
+
+    conn.execute(
+        r#"
+        INSERT OR REPLACE INTO input_progress
+        (id, epoch_number, input_number, machine_path, checkpoint_hash, start_mcycle, output_count)
+        VALUES (1, ?1, ?2, ?3, ?4, ?5, ?6)
+        "#,
+        params![
+            epoch_number,
+            input_number,
+            machine_path.to_string_lossy(),
+            progress.checkpoint_hash.as_ref(),
+            progress.start_mcycle,
+            progress.output_count,
+        ],
+    )
+    .map_err(anyhow::Error::from)?;
+
+    let recorded: usize = conn
+        .query_row("SELECT COUNT(*) FROM input_progress_hashes", [], |row| {
+            row.get(0)
+        })
+        .map_err(anyhow::Error::from)?;
+
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            INSERT INTO input_progress_hashes (hash_index, machine_state_hash)
+            VALUES (?1, ?2)
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    for (i, leaf) in progress.leafs.iter().enumerate().skip(recorded) {
+        assert_eq!(
+            leaf.repetitions, 1,
+            "strides of a running input are never repeated"
+        );
+        stmt.execute(params![i, leaf.hash.as_ref()])
+            .map_err(anyhow::Error::from)?;
+    }
+
+    Ok(())
+}
+
+/// Input recorded by [insert_input_progress], with the path of its machine and its progress.
+pub fn input_progress(conn: &Connection) -> Result<Option<(InputId, PathBuf, InputProgress)>> {
+    let row = conn
+        .query_row(
+            r#"
+            SELECT epoch_number, input_number, machine_path, checkpoint_hash, start_mcycle,
+                   output_count
+            FROM input_progress
+            WHERE id = 1
+            "#,
+            [],
+            |row| {
+                let checkpoint_hash: Vec<u8> = row.get(3)?;
+                let machine_path: String = row.get(2)?;
+                Ok((
+                    InputId {
+                        epoch_number: row.get(0)?,
+                        input_index_in_epoch: row.get(1)?,
+                    },
+                    PathBuf::from(machine_path),
+                    checkpoint_hash,
+                    row.get::<_, u64>(4)?,
+                    row.get::<_, u64>(5)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(anyhow::Error::from)?;
+
+    let Some((id, machine_path, checkpoint_hash, start_mcycle, output_count)) = row else {
+        return Ok(None);
+    };
+
+    let mut stmt = conn
+        .prepare_cached(
+            r#"
+            SELECT machine_state_hash, 1
+            FROM input_progress_hashes
+            ORDER BY hash_index ASC
+            "#,
+        )
+        .map_err(anyhow::Error::from)?;
+
+    let leafs = stmt
+        .query_map([], convert_row_to_commitment_leaf)
+        .map_err(anyhow::Error::from)?
+        .collect::<rusqlite::Result<Vec<_>>>()
+        .map_err(anyhow::Error::from)?;
+
+    Ok(Some((
+        id,
+        machine_path,
+        InputProgress {
+            checkpoint_hash: checkpoint_hash
+                .try_into()
+                .expect("checkpoint_hash should have 32 bytes"),
+            leafs,
+            start_mcycle,
+            output_count,
+        },
+    )))
+}
+
+pub fn delete_input_progress(conn: &Connection) -> Result<()> {
+    conn.execute("DELETE FROM input_progress", [])
+        .map_err(anyhow::Error::from)?;
+    conn.execute("DELETE FROM input_progress_hashes", [])
+        .map_err(anyhow::Error::from)?;
+
+    Ok(())
+}
+
+pub fn has_snapshots(conn: &Connection) -> Result<bool> {
+    Ok(conn
+        .query_row(
+            "SELECT EXISTS (SELECT 1 FROM epoch_snapshot_info)",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(anyhow::Error::from)?)
+}
+
 pub fn next_input_to_be_processed(conn: &Connection) -> Result<InputId> {
     let mut stmt = conn
         .prepare_cached(
@@ -324,6 +866,28 @@ pub fn snapshot_path_for_epoch(
         .map_err(anyhow::Error::from)?)
 }
 
+impl EpochSnapshots for Transaction<'_> {
+    fn snapshot_epochs_before(&mut self, epoch_number: u64) -> Result<Vec<u64>> {
+        snapshot_epochs_before(self, epoch_number)
+    }
+
+    fn boundary_snapshot_path(&mut self, epoch_number: u64) -> Result<Option<PathBuf>> {
+        snapshot_path_for_epoch(self, epoch_number, 0)
+    }
+
+    fn delete_epoch_snapshots(&mut self, epoch_number: u64, keep_boundary: bool) -> Result<()> {
+        delete_epoch_snapshots(self, epoch_number, keep_boundary)
+    }
+
+    fn orphan_snapshots(&mut self) -> Result<Vec<PathBuf>> {
+        orphan_snapshots(self)
+    }
+
+    fn gc_orphan_snapshots(&mut self) -> Result<()> {
+        gc_orphan_snapshots(self)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -512,6 +1076,159 @@ mod tests {
         assert_eq!(remaining, 2);
     }
 
+    #[test]
+    fn delete_epoch_snapshots_keeps_boundary() {
+        let (_handle, conn) = setup_db();
+        let dirs: Vec<TempDir> = (0..4).map(|_| tmp_dir()).collect();
+
+        insert_snapshot(&conn, 1, 0, &[1; 32], dirs[0].path()).unwrap();
+        insert_snapshot(&conn, 1, 3, &[2; 32], dirs[1].path()).unwrap();
+        insert_snapshot(&conn, 2, 0, &[3; 32], dirs[2].path()).unwrap();
+        insert_snapshot(&conn, 3, 0, &[4; 32], dirs[3].path()).unwrap();
+        assert_eq!(snapshot_epochs_before(&conn, 3).unwrap(), vec![0, 1, 2]);
+
+        delete_epoch_snapshots(&conn, 1, true).unwrap();
+        delete_epoch_snapshots(&conn, 2, false).unwrap();
+
+        assert!(snapshot_path_for_epoch(&conn, 1, 0).unwrap().is_some());
+        assert!(snapshot_path_for_epoch(&conn, 1, 3).unwrap().is_none());
+        assert!(snapshot_path_for_epoch(&conn, 2, 0).unwrap().is_none());
+
+        let mut orphans = orphan_snapshots(&conn).unwrap();
+        orphans.sort();
+        let mut expected = vec![dirs[1].path().to_owned(), dirs[2].path().to_owned()];
+        expected.sort();
+        assert_eq!(orphans, expected);
+
+        gc_orphan_snapshots(&conn).unwrap();
+        assert!(orphan_snapshots(&conn).unwrap().is_empty());
+        assert!(!dirs[1].path().exists());
+        assert!(!dirs[2].path().exists());
+        assert!(dirs[0].path().exists());
+    }
+
+    #[test]
+    fn truncate_after_forgets_later_data() {
+        let (_handle, conn) = setup_db();
+        let dirs: Vec<TempDir> = (0..3).map(|_| tmp_dir()).collect();
+        let leaf = CommitmentLeaf {
+            hash: [9; 32],
+            repetitions: 1,
+        };
+        let settlement = Settlement {
+            computation_hash: [0x11; 32].into(),
+            output_merkle: [0x22; 32],
+            output_proof: Proof::new(vec![[0; 32]]),
+        };
+
+        insert_snapshot(&conn, 1, 0, &[1; 32], dirs[0].path()).unwrap();
+        insert_snapshot(&conn, 1, 1, &[2; 32], dirs[1].path()).unwrap();
+        insert_snapshot(&conn, 2, 0, &[3; 32], dirs[2].path()).unwrap();
+        insert_state_hashes_for_input(&conn, 1, 0, &[leaf.clone()]).unwrap();
+        insert_state_hashes_for_input(&conn, 1, 1, &[leaf.clone()]).unwrap();
+        insert_settlement_info(&conn, &settlement, 0).unwrap();
+        insert_settlement_info(&conn, &settlement, 1).unwrap();
+
+        truncate_after(&conn, 1, 1).unwrap();
+
+        let positions: Vec<_> = snapshot_positions(&conn)
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| (id.epoch_number, id.input_index_in_epoch))
+            .collect();
+        assert_eq!(positions[..2], [(1, 1), (1, 0)]);
+        assert_eq!(get_all_commitments(&conn, 1).unwrap(), vec![leaf]);
+        assert_eq!(settled_epochs(&conn).unwrap(), vec![0]);
+
+        let snapshots = all_snapshots(&conn).unwrap();
+        let orphan = snapshots.iter().find(|(h, _, _)| *h == [3; 32]).unwrap();
+        assert!(orphan.2.is_none());
+        let kept = snapshots.iter().find(|(h, _, _)| *h == [2; 32]).unwrap();
+        assert_eq!(kept.2.as_ref().unwrap().input_index_in_epoch, 1);
+    }
+
+    #[test]
+    fn halted_keeps_first_input() {
+        let (_handle, conn) = setup_db();
+        assert!(halted_at(&conn).unwrap().is_none());
+
+        insert_halted(&conn, 1, 2).unwrap();
+        insert_halted(&conn, 1, 3).unwrap();
+        let halted = halted_at(&conn).unwrap().unwrap();
+        assert_eq!((halted.epoch_number, halted.input_index_in_epoch), (1, 2));
+
+        truncate_after(&conn, 1, 3).unwrap();
+        assert!(halted_at(&conn).unwrap().is_some());
+        truncate_after(&conn, 1, 2).unwrap();
+        assert!(halted_at(&conn).unwrap().is_none());
+    }
+
+    #[test]
+    fn input_executions_replace_and_order() {
+        let (_handle, conn) = setup_db();
+        let execution = |start_mcycle| InputExecution {
+            start_mcycle,
+            end_mcycle: start_mcycle + 10,
+            stride_count: 1,
+            reason: Some(0),
+            wall_time: std::time::Duration::from_micros(250),
+            output_count: 2,
+        };
+
+        insert_input_execution(&conn, 0, 1, &execution(20)).unwrap();
+        insert_input_execution(&conn, 0, 0, &execution(0)).unwrap();
+        insert_input_execution(&conn, 0, 1, &execution(30)).unwrap();
+        assert_eq!(
+            input_executions(&conn, 0).unwrap(),
+            vec![execution(0), execution(30)]
+        );
+        assert_eq!(input_execution(&conn, 0, 1).unwrap(), Some(execution(30)));
+        assert!(input_execution(&conn, 1, 0).unwrap().is_none());
+
+        truncate_after(&conn, 0, 1).unwrap();
+        assert_eq!(input_executions(&conn, 0).unwrap(), vec![execution(0)]);
+    }
+
+    #[test]
+    fn input_progress_appends_and_replaces() {
+        let (_handle, conn) = setup_db();
+        let progress = |strides: u8| InputProgress {
+            checkpoint_hash: [9; 32],
+            leafs: (0..strides)
+                .map(|i| CommitmentLeaf {
+                    hash: [i; 32],
+                    repetitions: 1,
+                })
+                .collect(),
+            start_mcycle: 100,
+            output_count: strides as u64,
+        };
+        assert!(input_progress(&conn).unwrap().is_none());
+
+        insert_input_progress(&conn, 0, 1, Path::new("/a"), &progress(2)).unwrap();
+        insert_input_progress(&conn, 0, 1, Path::new("/b"), &progress(4)).unwrap();
+        let (id, path, fetched) = input_progress(&conn).unwrap().unwrap();
+        assert_eq!((id.epoch_number, id.input_index_in_epoch), (0, 1));
+        assert_eq!(path, PathBuf::from("/b"));
+        assert_eq!(fetched, progress(4));
+
+        // progress of another input starts over
+        insert_input_progress(&conn, 0, 2, Path::new("/c"), &progress(1)).unwrap();
+        let (id, _, fetched) = input_progress(&conn).unwrap().unwrap();
+        assert_eq!(id.input_index_in_epoch, 2);
+        assert_eq!(fetched, progress(1));
+
+        truncate_after(&conn, 0, 3).unwrap();
+        assert!(input_progress(&conn).unwrap().is_some());
+        truncate_after(&conn, 0, 2).unwrap();
+        assert!(input_progress(&conn).unwrap().is_none());
+        assert_eq!(count_rows(&conn, "input_progress_hashes"), 0);
+
+        insert_input_progress(&conn, 0, 2, Path::new("/c"), &progress(1)).unwrap();
+        delete_input_progress(&conn).unwrap();
+        assert!(input_progress(&conn).unwrap().is_none());
+    }
+
     #[test]
     fn insert_template_machine_is_idempotent() {
         let (_handle, conn) = setup_db();
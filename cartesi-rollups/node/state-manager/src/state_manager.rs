@@ -3,8 +3,12 @@
 
 use std::path::PathBuf;
 
-use crate::{CommitmentLeaf, Epoch, Input, InputId, Settlement, rollups_machine::RollupsMachine};
+use crate::{
+    CommitmentLeaf, Epoch, Input, InputExecution, InputId, InputProgress, Settlement,
+    rollups_machine::RollupsMachine,
+};
 use cartesi_machine::error::MachineError;
+use cartesi_prt_core::strategy::scheduler::Deadline;
 use thiserror::Error;
 
 pub trait StateManager {
@@ -42,6 +46,38 @@ pub trait StateManager {
         leafs: &[CommitmentLeaf],
     ) -> Result<()>;
 
+    /// Like [StateManager::advance_accepted], for the input the application halted on, which is
+    /// recorded as where it halted. Inputs after it are skipped, advanced like rejected ones.
+    fn advance_halted(
+        &mut self,
+        machine: &mut RollupsMachine,
+        leafs: &[CommitmentLeaf],
+    ) -> Result<()>;
+
+    /// Input the application halted on, if it did.
+    fn halted_at(&mut self) -> Result<Option<InputId>>;
+
+    /// Records how input `id` ran, replacing any earlier record of it.
+    fn insert_input_execution(&mut self, id: &InputId, execution: &InputExecution) -> Result<()>;
+    fn input_execution(&mut self, id: &InputId) -> Result<Option<InputExecution>>;
+    fn input_executions(&mut self, epoch_number: u64) -> Result<Vec<InputExecution>>;
+
+    /// Replaces the tournament clock deadlines observed in the latest dispute reaction.
+    fn set_deadlines(&mut self, deadlines: &[Deadline]) -> Result<()>;
+    /// Deadlines last set by [StateManager::set_deadlines], earliest first.
+    fn deadlines(&mut self) -> Result<Vec<Deadline>>;
+
+    /// Stores `machine`, midway through its next input, along with the `progress` of that input,
+    /// replacing any progress saved before. Advancing the input drops it.
+    fn save_input_progress(
+        &mut self,
+        machine: &mut RollupsMachine,
+        progress: &InputProgress,
+    ) -> Result<()>;
+
+    /// Machine and progress last saved by [StateManager::save_input_progress], if any.
+    fn input_progress(&mut self) -> Result<Option<(RollupsMachine, InputProgress)>>;
+
     fn epoch_state_hashes(&mut self, epoch_number: u64) -> Result<Vec<CommitmentLeaf>>;
 
     fn settlement_info(&mut self, epoch_number: u64) -> Result<Option<Settlement>>;
@@ -88,6 +124,15 @@ pub enum StateAccessError {
     #[error("Couldn't find data: `{description}`")]
     DataNotFound { description: String },
 
+    #[error(
+        "Snapshot of epoch `{epoch_number}` has state hash `{provided}`, but `{expected}` was sealed on chain"
+    )]
+    CheckpointMismatch {
+        epoch_number: u64,
+        expected: String,
+        provided: String,
+    },
+
     #[error("Machine snapshot error")]
     MachineError {
         #[from]
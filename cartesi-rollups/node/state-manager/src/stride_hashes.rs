@@ -0,0 +1,189 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Hashing of strides overlapped with running the ones after them. A machine can't be copied, so
+//! the state a stride ends at is kept by forking the process, copy-on-write, and the child hashes
+//! it while the parent runs on.
+//!
+//! The emulator only rehashes the pages dirtied since its Merkle tree was last updated, and a
+//! child never updates the tree of its parent, so each child rehashes every page dirtied since the
+//! last stride the parent hashed itself. To bound that, every stride after `depth` forked ones is
+//! hashed in place.
+
+use std::collections::VecDeque;
+
+use cartesi_machine::{error::MachineResult, machine::Machine, types::Hash};
+
+use crate::CommitmentLeaf;
+
+/// Strides hashed by forked children between the ones hashed in place.
+#[cfg(target_os = "linux")]
+pub(crate) const HASH_PIPELINE_DEPTH: usize = 3;
+/// Strides hashed by forked children between the ones hashed in place.
+#[cfg(not(target_os = "linux"))]
+pub(crate) const HASH_PIPELINE_DEPTH: usize = 0;
+
+/// Hashes of the strides of an input, some still being computed.
+pub(crate) struct StrideHashes {
+    depth: usize,
+    pending: VecDeque<fork::PendingHash>,
+}
+
+impl StrideHashes {
+    pub fn new(depth: usize) -> Self {
+        Self {
+            depth,
+            pending: VecDeque::with_capacity(depth),
+        }
+    }
+
+    /// Number of hashes still being computed.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Hashes the current state of `machine`, which ends the stride after the ones already in
+    /// `leafs` and pending. The hash lands in `leafs` by the time [Self::finish] returns, or
+    /// right away if it's computed in place.
+    pub fn push(
+        &mut self,
+        machine: &mut Machine,
+        leafs: &mut Vec<CommitmentLeaf>,
+    ) -> MachineResult<()> {
+        if self.pending.len() < self.depth {
+            // if the process can't be forked, the stride is hashed in place instead
+            if let Some(pending) = fork::hash(machine) {
+                self.pending.push_back(pending);
+                return Ok(());
+            }
+        }
+
+        self.finish(leafs)?;
+        push_leaf(leafs, machine.root_hash()?);
+        Ok(())
+    }
+
+    /// Waits for the pending hashes, appending them to `leafs` in order.
+    pub fn finish(&mut self, leafs: &mut Vec<CommitmentLeaf>) -> MachineResult<()> {
+        while let Some(pending) = self.pending.pop_front() {
+            push_leaf(leafs, pending.wait()?);
+        }
+        Ok(())
+    }
+}
+
+fn push_leaf(leafs: &mut Vec<CommitmentLeaf>, hash: Hash) {
+    leafs.push(CommitmentLeaf {
+        hash,
+        repetitions: 1,
+    });
+}
+
+#[cfg(target_os = "linux")]
+mod fork {
+    use std::{
+        fs::File,
+        io::{self, Read, Write},
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use cartesi_machine::{
+        config::runtime::ConcurrencyRuntimeConfig,
+        constants::error_code,
+        error::{MachineError, MachineResult},
+        machine::Machine,
+        types::Hash,
+    };
+    use log::warn;
+
+    use crate::console::{cvt, pipe};
+
+    static FORK_FAILED: AtomicBool = AtomicBool::new(false);
+
+    /// Root hash of a machine, computed by a forked child.
+    pub struct PendingHash {
+        pid: libc::pid_t,
+        reader: File,
+    }
+
+    /// Forks a child that hashes `machine` as it is now, or returns `None` if it can't, warning
+    /// about it once per process.
+    pub fn hash(machine: &mut Machine) -> Option<PendingHash> {
+        match try_hash(machine) {
+            Ok(pending) => Some(pending),
+            Err(e) => {
+                if !FORK_FAILED.swap(true, Ordering::Relaxed) {
+                    warn!("could not fork to hash strides, hashing them in place: {e}");
+                }
+                None
+            }
+        }
+    }
+
+    fn try_hash(machine: &mut Machine) -> io::Result<PendingHash> {
+        let (reader, writer) = pipe()?;
+        let pid = cvt(unsafe { libc::fork() })?;
+        if pid == 0 {
+            let mut writer = File::from(writer);
+            let code = match child_hash(machine) {
+                Ok(hash) if writer.write_all(&hash).is_ok() => 0,
+                _ => 1,
+            };
+            // skips the destructors and exit handlers of the parent, which it still owns
+            unsafe { libc::_exit(code) }
+        }
+
+        Ok(PendingHash { pid, reader })
+    }
+
+    /// Only the forking thread lives on in the child, so the tree is updated without the worker
+    /// threads of the emulator, whose pool the child can't rely on.
+    fn child_hash(machine: &mut Machine) -> MachineResult<Hash> {
+        let mut runtime_config = machine.runtime_config()?;
+        runtime_config.concurrency = Some(ConcurrencyRuntimeConfig {
+            update_merkle_tree: Some(1),
+        });
+        machine.set_runtime_config(&runtime_config)?;
+        machine.root_hash()
+    }
+
+    impl PendingHash {
+        pub fn wait(mut self) -> MachineResult<Hash> {
+            let mut hash = Hash::default();
+            self.reader
+                .read_exact(&mut hash)
+                .map_err(|e| MachineError {
+                    code: error_code::SYSTEM_ERROR,
+                    message: format!("stride hash of child {} is missing: {e}", self.pid),
+                })?;
+            Ok(hash)
+        }
+    }
+
+    impl Drop for PendingHash {
+        fn drop(&mut self) {
+            // reaps the child, stopping it first if its hash is no longer waited for
+            unsafe {
+                libc::kill(self.pid, libc::SIGKILL);
+                libc::waitpid(self.pid, std::ptr::null_mut(), 0);
+            }
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod fork {
+    use cartesi_machine::{error::MachineResult, machine::Machine, types::Hash};
+
+    pub enum PendingHash {}
+
+    pub fn hash(_machine: &mut Machine) -> Option<PendingHash> {
+        None
+    }
+
+    impl PendingHash {
+        pub fn wait(self) -> MachineResult<Hash> {
+            match self {}
+        }
+    }
+}
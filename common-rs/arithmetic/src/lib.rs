@@ -1,3 +1,10 @@
+mod meta_cycle;
+pub use meta_cycle::{
+    BARCH_SPAN_TO_INPUT, INPUT_SPAN_TO_EPOCH, LOG2_BARCH_SPAN_TO_INPUT, LOG2_INPUT_SPAN_TO_EPOCH,
+    LOG2_UARCH_SPAN_TO_BARCH, LOG2_UARCH_SPAN_TO_EPOCH, LOG2_UARCH_SPAN_TO_INPUT, MetaCycle,
+    MetaCycleError, UARCH_SPAN_TO_BARCH, big_cycle_span,
+};
+
 pub const fn max_uint(k: u64) -> u64 {
     assert!(k <= u64::BITS as u64);
     (1u64.wrapping_shl(k as u32)).wrapping_sub(1)
@@ -0,0 +1,325 @@
+//! A [MetaCycle] addresses a single micro instruction of an epoch. As a number, it concatenates the
+//! index of the input being processed, the big instruction executing that input, and the micro
+//! instruction emulating that big instruction, from the most to the least significant bits.
+
+use crate::max_uint;
+use ruint::aliases::U256;
+use std::fmt;
+use thiserror::Error;
+
+// log2 value of the maximal number of micro instructions that emulates a big instruction
+pub const LOG2_UARCH_SPAN_TO_BARCH: u64 = 20;
+pub const UARCH_SPAN_TO_BARCH: u64 = max_uint(LOG2_UARCH_SPAN_TO_BARCH);
+
+// log2 value of the maximal number of big instructions that executes an input
+pub const LOG2_BARCH_SPAN_TO_INPUT: u64 = 48;
+pub const BARCH_SPAN_TO_INPUT: u64 = max_uint(LOG2_BARCH_SPAN_TO_INPUT);
+
+// log2 value of the maximal number of inputs that allowed in an epoch
+pub const LOG2_INPUT_SPAN_TO_EPOCH: u64 = 24;
+pub const INPUT_SPAN_TO_EPOCH: u64 = max_uint(LOG2_INPUT_SPAN_TO_EPOCH);
+
+// log2 value of the maximal number of micro instructions that executes an input
+pub const LOG2_UARCH_SPAN_TO_INPUT: u64 = LOG2_BARCH_SPAN_TO_INPUT + LOG2_UARCH_SPAN_TO_BARCH;
+
+// log2 value of the maximal number of micro instructions that executes an epoch
+pub const LOG2_UARCH_SPAN_TO_EPOCH: u64 = LOG2_INPUT_SPAN_TO_EPOCH + LOG2_UARCH_SPAN_TO_INPUT;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum MetaCycleError {
+    #[error("meta cycle {0} is past the end of the epoch")]
+    OutOfEpoch(U256),
+
+    #[error("input {0} is past the end of the epoch")]
+    InputOutOfRange(u64),
+
+    #[error("big cycle {0} is past the span of an input")]
+    BigCycleOutOfRange(u64),
+
+    #[error("micro cycle {0} is past the span of a big instruction")]
+    MicroCycleOutOfRange(u64),
+}
+
+/// Position of a micro instruction within an epoch. Every component is checked to fit its span, so
+/// converting to and from [U256] is lossless, and meta cycles order the same way their numbers do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MetaCycle {
+    input: u64,
+    big_cycle: u64,
+    micro_cycle: u64,
+}
+
+impl MetaCycle {
+    pub fn new(input: u64, big_cycle: u64, micro_cycle: u64) -> Result<Self, MetaCycleError> {
+        if input > INPUT_SPAN_TO_EPOCH {
+            return Err(MetaCycleError::InputOutOfRange(input));
+        }
+        if big_cycle > BARCH_SPAN_TO_INPUT {
+            return Err(MetaCycleError::BigCycleOutOfRange(big_cycle));
+        }
+        if micro_cycle > UARCH_SPAN_TO_BARCH {
+            return Err(MetaCycleError::MicroCycleOutOfRange(micro_cycle));
+        }
+
+        Ok(Self {
+            input,
+            big_cycle,
+            micro_cycle,
+        })
+    }
+
+    /// The first micro instruction of `input`, before the input is sent to the machine.
+    pub fn input_start(input: u64) -> Result<Self, MetaCycleError> {
+        Self::new(input, 0, 0)
+    }
+
+    pub fn input(&self) -> u64 {
+        self.input
+    }
+
+    pub fn big_cycle(&self) -> u64 {
+        self.big_cycle
+    }
+
+    pub fn micro_cycle(&self) -> u64 {
+        self.micro_cycle
+    }
+
+    /// Whether the machine still has to be sent the input at this position.
+    pub fn is_input_start(&self) -> bool {
+        self.big_cycle == 0 && self.micro_cycle == 0
+    }
+
+    /// Whether this is the first micro instruction of a big instruction.
+    pub fn is_big_step_start(&self) -> bool {
+        self.micro_cycle == 0
+    }
+
+    /// Whether this is the last micro instruction of a big instruction, after which the micro
+    /// architecture is reset.
+    pub fn is_big_step_end(&self) -> bool {
+        self.micro_cycle == UARCH_SPAN_TO_BARCH
+    }
+
+    /// Whether this meta cycle is a multiple of `2^log2_stride`, that is, the start of a stride.
+    pub fn is_aligned(&self, log2_stride: u64) -> bool {
+        let value: U256 = (*self).into();
+        value.is_zero() || value.trailing_zeros() as u64 >= log2_stride
+    }
+
+    /// Advances `count` strides of `2^log2_stride` micro instructions, or returns `None` if that
+    /// lands past the end of the epoch.
+    pub fn checked_add_strides(&self, log2_stride: u64, count: u64) -> Option<Self> {
+        if log2_stride >= LOG2_UARCH_SPAN_TO_EPOCH {
+            return (count == 0).then_some(*self);
+        }
+        let offset = U256::from(count).checked_shl(log2_stride as usize)?;
+        let value = Into::<U256>::into(*self).checked_add(offset)?;
+        Self::try_from(value).ok()
+    }
+}
+
+/// Number of big instructions covered by `2^log2_uarch_span` micro instructions. Panics if the span
+/// is shorter than a big instruction, or covers more than `u64::MAX` of them.
+pub const fn big_cycle_span(log2_uarch_span: u64) -> u64 {
+    assert!(log2_uarch_span >= LOG2_UARCH_SPAN_TO_BARCH);
+    assert!(log2_uarch_span - LOG2_UARCH_SPAN_TO_BARCH < u64::BITS as u64);
+    1 << (log2_uarch_span - LOG2_UARCH_SPAN_TO_BARCH)
+}
+
+impl From<MetaCycle> for U256 {
+    fn from(meta_cycle: MetaCycle) -> Self {
+        (U256::from(meta_cycle.input) << LOG2_UARCH_SPAN_TO_INPUT as usize)
+            | (U256::from(meta_cycle.big_cycle) << LOG2_UARCH_SPAN_TO_BARCH as usize)
+            | U256::from(meta_cycle.micro_cycle)
+    }
+}
+
+impl TryFrom<U256> for MetaCycle {
+    type Error = MetaCycleError;
+
+    fn try_from(value: U256) -> Result<Self, Self::Error> {
+        if value.bit_len() as u64 > LOG2_UARCH_SPAN_TO_EPOCH {
+            return Err(MetaCycleError::OutOfEpoch(value));
+        }
+
+        let field = |shift: u64, log2_span: u64| {
+            let bits = (value >> shift as usize) & U256::from(max_uint(log2_span));
+            bits.as_limbs()[0]
+        };
+
+        Ok(Self {
+            input: field(LOG2_UARCH_SPAN_TO_INPUT, LOG2_INPUT_SPAN_TO_EPOCH),
+            big_cycle: field(LOG2_UARCH_SPAN_TO_BARCH, LOG2_BARCH_SPAN_TO_INPUT),
+            micro_cycle: field(0, LOG2_UARCH_SPAN_TO_BARCH),
+        })
+    }
+}
+
+impl fmt::Display for MetaCycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "input {} big cycle {} micro cycle {}",
+            self.input, self.big_cycle, self.micro_cycle
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn epoch_value() -> impl Strategy<Value = U256> {
+        any::<u128>()
+            .prop_map(|v| U256::from(v) & U256::from(max_uint_u128(LOG2_UARCH_SPAN_TO_EPOCH)))
+    }
+
+    fn meta_cycle() -> impl Strategy<Value = MetaCycle> {
+        (
+            0..=INPUT_SPAN_TO_EPOCH,
+            0..=BARCH_SPAN_TO_INPUT,
+            0..=UARCH_SPAN_TO_BARCH,
+        )
+            .prop_map(|(input, big_cycle, micro_cycle)| {
+                MetaCycle::new(input, big_cycle, micro_cycle).unwrap()
+            })
+    }
+
+    fn value(m: MetaCycle) -> U256 {
+        m.into()
+    }
+
+    fn max_uint_u128(k: u64) -> u128 {
+        (1u128 << k) - 1
+    }
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(LOG2_UARCH_SPAN_TO_INPUT, 68);
+        assert_eq!(LOG2_UARCH_SPAN_TO_EPOCH, 92);
+
+        let m = MetaCycle::new(3, 2, 1).unwrap();
+        assert_eq!(
+            value(m),
+            (U256::from(3) << 68) + (U256::from(2) << 20) + U256::from(1)
+        );
+        assert_eq!(m.to_string(), "input 3 big cycle 2 micro cycle 1");
+
+        let last = MetaCycle::new(
+            INPUT_SPAN_TO_EPOCH,
+            BARCH_SPAN_TO_INPUT,
+            UARCH_SPAN_TO_BARCH,
+        )
+        .unwrap();
+        assert_eq!(
+            value(last),
+            (U256::from(1) << LOG2_UARCH_SPAN_TO_EPOCH as usize) - U256::from(1)
+        );
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        assert_eq!(
+            MetaCycle::new(INPUT_SPAN_TO_EPOCH + 1, 0, 0),
+            Err(MetaCycleError::InputOutOfRange(INPUT_SPAN_TO_EPOCH + 1))
+        );
+        assert_eq!(
+            MetaCycle::new(0, BARCH_SPAN_TO_INPUT + 1, 0),
+            Err(MetaCycleError::BigCycleOutOfRange(BARCH_SPAN_TO_INPUT + 1))
+        );
+        assert_eq!(
+            MetaCycle::new(0, 0, UARCH_SPAN_TO_BARCH + 1),
+            Err(MetaCycleError::MicroCycleOutOfRange(
+                UARCH_SPAN_TO_BARCH + 1
+            ))
+        );
+
+        let end = U256::from(1) << LOG2_UARCH_SPAN_TO_EPOCH as usize;
+        assert_eq!(
+            MetaCycle::try_from(end),
+            Err(MetaCycleError::OutOfEpoch(end))
+        );
+        assert!(MetaCycle::try_from(U256::MAX).is_err());
+    }
+
+    #[test]
+    fn test_positions() {
+        let start = MetaCycle::input_start(5).unwrap();
+        assert!(start.is_input_start());
+        assert!(start.is_big_step_start());
+        assert!(!start.is_big_step_end());
+
+        let end = MetaCycle::new(5, 7, UARCH_SPAN_TO_BARCH).unwrap();
+        assert!(!end.is_input_start());
+        assert!(!end.is_big_step_start());
+        assert!(end.is_big_step_end());
+
+        let next = end.checked_add_strides(0, 1).unwrap();
+        assert_eq!(next, MetaCycle::new(5, 8, 0).unwrap());
+        assert_eq!(
+            MetaCycle::default().checked_add_strides(LOG2_UARCH_SPAN_TO_INPUT, 5),
+            Some(start)
+        );
+        assert_eq!(
+            MetaCycle::default().checked_add_strides(LOG2_UARCH_SPAN_TO_EPOCH, 1),
+            None
+        );
+    }
+
+    #[test]
+    fn test_big_cycle_span() {
+        assert_eq!(big_cycle_span(LOG2_UARCH_SPAN_TO_BARCH), 1);
+        assert_eq!(big_cycle_span(LOG2_UARCH_SPAN_TO_BARCH + 4), 16);
+        assert_eq!(
+            big_cycle_span(LOG2_UARCH_SPAN_TO_INPUT),
+            BARCH_SPAN_TO_INPUT + 1
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_big_cycle_span_below_big_instruction() {
+        big_cycle_span(LOG2_UARCH_SPAN_TO_BARCH - 1);
+    }
+
+    proptest! {
+        #[test]
+        fn prop_u256_roundtrip(v in epoch_value()) {
+            let m = MetaCycle::try_from(v).unwrap();
+            prop_assert_eq!(value(m), v);
+        }
+
+        #[test]
+        fn prop_meta_cycle_roundtrip(m in meta_cycle()) {
+            prop_assert_eq!(MetaCycle::try_from(value(m)), Ok(m));
+        }
+
+        #[test]
+        fn prop_past_epoch_rejected(value in any::<[u64; 4]>()) {
+            let value = U256::from_limbs(value) | (U256::from(1) << LOG2_UARCH_SPAN_TO_EPOCH as usize);
+            prop_assert!(MetaCycle::try_from(value).is_err());
+        }
+
+        #[test]
+        fn prop_order_preserved(a in meta_cycle(), b in meta_cycle()) {
+            prop_assert_eq!(a.cmp(&b), value(a).cmp(&value(b)));
+        }
+
+        #[test]
+        fn prop_add_strides(m in meta_cycle(), log2_stride in 0..LOG2_UARCH_SPAN_TO_EPOCH, count in any::<u64>()) {
+            let expected = value(m) + (U256::from(count) << log2_stride as usize);
+            match m.checked_add_strides(log2_stride, count) {
+                Some(next) => prop_assert_eq!(value(next), expected),
+                None => prop_assert!(expected.bit_len() as u64 > LOG2_UARCH_SPAN_TO_EPOCH),
+            }
+        }
+
+        #[test]
+        fn prop_aligned(m in meta_cycle(), log2_stride in 0..=LOG2_UARCH_SPAN_TO_EPOCH) {
+            let stride = U256::from(1) << log2_stride as usize;
+            prop_assert_eq!(m.is_aligned(log2_stride), (value(m) % stride).is_zero());
+        }
+    }
+}
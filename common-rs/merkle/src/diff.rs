@@ -0,0 +1,111 @@
+//! This module contains the [MerkleDivergence] struct, which locates where two
+//! [GenericMerkleTree]s of the same height first disagree.
+
+use crate::{Digest, GenericMerkleTree, HashFunction};
+
+use ruint::aliases::U256;
+
+/// The first leaf at which two trees differ, and how the trees differ on the way to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MerkleDivergence {
+    /// index of the first divergent leaf
+    pub leaf: U256,
+    /// pairs of differing nodes, from the roots down to the divergent leafs
+    pub path: Vec<(Digest, Digest)>,
+}
+
+impl<H: HashFunction> GenericMerkleTree<H> {
+    /// Finds the first leaf at which `self` and `other` differ, or none if the trees are equal.
+    ///
+    /// Equal subtrees are recognized by their root hashes, so the search descends a single path
+    /// and takes time proportional to the height of the trees, regardless of repeated subtrees.
+    pub fn first_divergence(&self, other: &Self) -> Option<MerkleDivergence> {
+        assert_eq!(self.height(), other.height(), "tree size mismatch");
+
+        let mut divergence = MerkleDivergence {
+            leaf: U256::ZERO,
+            path: Vec::with_capacity(self.height() as usize + 1),
+        };
+        self.first_divergence_rec(other, &mut divergence)
+            .then_some(divergence)
+    }
+
+    fn first_divergence_rec(&self, other: &Self, divergence: &mut MerkleDivergence) -> bool {
+        if self.root_hash() == other.root_hash() {
+            return false;
+        }
+        divergence.path.push((self.root_hash(), other.root_hash()));
+
+        let (Some((left, right)), Some((other_left, other_right))) =
+            (self.subtrees(), other.subtrees())
+        else {
+            return true;
+        };
+
+        if !left.first_divergence_rec(&other_left, divergence) {
+            divergence.leaf |= U256::from(1) << (self.height() - 1) as usize;
+            right.first_divergence_rec(&other_right, divergence);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleBuilder, MerkleTree};
+
+    fn digest(n: u8) -> Digest {
+        Digest::new([n; 32])
+    }
+
+    fn build(leafs: &[(u8, u64)]) -> std::sync::Arc<MerkleTree> {
+        let mut builder = MerkleBuilder::default();
+        for (leaf, rep) in leafs {
+            builder.append_repeated(digest(*leaf), *rep);
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_equal_trees() {
+        let tree = build(&[(1, 3), (2, 5)]);
+        assert_eq!(tree.first_divergence(&build(&[(1, 3), (2, 5)])), None);
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let a = build(&[(1, 3), (2, 5)]);
+        let b = build(&[(1, 3), (2, 2), (3, 1), (2, 2)]);
+
+        let divergence = a.first_divergence(&b).unwrap();
+        assert_eq!(divergence.leaf, U256::from(5));
+        assert_eq!(divergence.path.len(), 4);
+        assert_eq!(divergence.path[0], (a.root_hash(), b.root_hash()));
+        assert_eq!(divergence.path[3], (digest(2), digest(3)));
+
+        let divergence = b.first_divergence(&a).unwrap();
+        assert_eq!(divergence.leaf, U256::from(5));
+        assert_eq!(divergence.path[3], (digest(3), digest(2)));
+    }
+
+    #[test]
+    fn test_first_divergence_repeated() {
+        let half = 1u64 << 47;
+        let a = build(&[(1, half), (2, half)]);
+        let b = build(&[(1, half + 5), (3, 1), (2, half - 6)]);
+
+        let divergence = a.first_divergence(&b).unwrap();
+        assert_eq!(divergence.leaf, U256::from(half));
+        assert_eq!(divergence.path.len(), 49);
+        assert_eq!(divergence.path[48], (digest(2), digest(1)));
+        assert_eq!(a.prove_leaf(divergence.leaf).node, digest(2));
+        assert_eq!(b.prove_leaf(divergence.leaf).node, digest(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "tree size mismatch")]
+    fn test_height_mismatch() {
+        build(&[(1, 2)]).first_divergence(&build(&[(1, 4)]));
+    }
+}
@@ -2,11 +2,14 @@
 
 use tiny_keccak::{Hasher, Keccak};
 
-use super::Digest;
+use super::{Digest, HashFunction};
 
-impl Digest {
-    /// Computes the Keccak256 hash of the given data and returns a new Digest.
-    pub fn from_data(data: &[u8]) -> Digest {
+/// The Keccak-256 hash function, used by the Cartesi Machine and the PRT contracts.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Keccak256;
+
+impl HashFunction for Keccak256 {
+    fn hash(data: &[u8]) -> Digest {
         let mut keccak = Keccak::v256();
         keccak.update(data);
         let mut digest: [u8; 32] = [0; 32];
@@ -14,20 +17,31 @@ impl Digest {
         Digest::from(digest)
     }
 
-    /// Joins the current Digest with another Digest to create a new Digest.
-    pub fn join(&self, digest: &Digest) -> Digest {
+    fn join(left: &Digest, right: &Digest) -> Digest {
         let mut keccak = Keccak::v256();
-        keccak.update(&self.data);
-        keccak.update(&digest.data);
+        keccak.update(&left.data);
+        keccak.update(&right.data);
         let mut digest: [u8; 32] = [0; 32];
         keccak.finalize(&mut digest);
         Digest::from(digest)
     }
 }
 
+impl Digest {
+    /// Computes the Keccak256 hash of the given data and returns a new Digest.
+    pub fn from_data(data: &[u8]) -> Digest {
+        Keccak256::hash(data)
+    }
+
+    /// Joins the current Digest with another Digest to create a new Digest.
+    pub fn join(&self, digest: &Digest) -> Digest {
+        Keccak256::join(self, digest)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::Digest;
+    use super::{Digest, HashFunction, Keccak256};
 
     fn assert_data_eq(expected_digest_hex: &str, digest: Digest) {
         assert_eq!(
@@ -57,4 +71,14 @@ mod tests {
             Digest::from_data("minhas".as_bytes()).join(&Digest::from_data("bananas".as_bytes())),
         );
     }
+
+    #[test]
+    fn test_join_matches_hash_of_concatenation() {
+        let left = Digest::from_data("minhas".as_bytes());
+        let right = Digest::from_data("bananas".as_bytes());
+        assert_eq!(
+            Keccak256::join(&left, &right),
+            Keccak256::hash(&[left.slice(), right.slice()].concat())
+        );
+    }
 }
@@ -3,10 +3,15 @@
 
 use alloy::primitives::B256;
 use hex::FromHex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error as _};
 use std::fmt;
 use thiserror::Error;
 
 pub mod keccak;
+pub use keccak::Keccak256;
+
+pub mod sha256;
+pub use sha256::Sha256;
 
 const HASH_SIZE: usize = 32;
 
@@ -19,6 +24,22 @@ pub enum DigestError {
     InvalidHexString(#[from] hex::FromHexError),
 }
 
+/// A hash function used to compute [Digest]s and to join them into merkle tree nodes.
+pub trait HashFunction:
+    Clone + Copy + fmt::Debug + Default + PartialEq + Eq + Send + Sync + 'static
+{
+    /// Hashes the given data.
+    fn hash(data: &[u8]) -> Digest;
+
+    /// Hashes the concatenation of two digests, as the parent of `left` and `right`.
+    fn join(left: &Digest, right: &Digest) -> Digest {
+        let mut data = [0u8; 2 * HASH_SIZE];
+        data[..HASH_SIZE].copy_from_slice(&left.data);
+        data[HASH_SIZE..].copy_from_slice(&right.data);
+        Self::hash(&data)
+    }
+}
+
 /// The output of a hash function.
 #[derive(Eq, Hash, PartialEq, Clone, Copy, Debug)]
 pub struct Digest {
@@ -93,6 +114,31 @@ impl From<Digest> for B256 {
     }
 }
 
+/// Serialized as a hex string in human-readable formats, and as raw bytes otherwise.
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_hex())
+        } else {
+            self.data.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let hex = String::deserialize(deserializer)?;
+            if !hex.starts_with("0x") {
+                return Err(D::Error::custom("digest hex string must start with 0x"));
+            }
+            Digest::from_digest_hex(&hex).map_err(D::Error::custom)
+        } else {
+            <[u8; HASH_SIZE]>::deserialize(deserializer).map(Digest::new)
+        }
+    }
+}
+
 impl fmt::Display for Digest {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.to_hex())
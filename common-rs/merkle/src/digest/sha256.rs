@@ -0,0 +1,62 @@
+//! SHA-256 hash for the Digest Type, for state trees of machines and settlement targets that use
+//! it instead of Keccak256.
+
+use sha2::Digest as _;
+
+use super::{Digest, HashFunction};
+
+/// The SHA-256 hash function.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sha256;
+
+impl HashFunction for Sha256 {
+    fn hash(data: &[u8]) -> Digest {
+        Digest::new(sha2::Sha256::digest(data).into())
+    }
+
+    fn join(left: &Digest, right: &Digest) -> Digest {
+        let mut sha = sha2::Sha256::new();
+        sha.update(left.data);
+        sha.update(right.data);
+        Digest::new(sha.finalize().into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Digest, HashFunction, Sha256};
+
+    fn assert_data_eq(expected_digest_hex: &str, digest: Digest) {
+        assert_eq!(
+            Digest::from_digest_hex(expected_digest_hex).expect("invalid hex"),
+            digest
+        );
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_data_eq(
+            "0xe3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            Sha256::hash(&[]), // sha256sum of ""
+        );
+
+        assert_data_eq(
+            "0xe4ba5cbd251c98e6cd1c23f126a3b81d8d8328abc95387229850952b3ef9f904",
+            Sha256::hash("bananas".as_bytes()),
+        );
+    }
+
+    #[test]
+    fn test_join() {
+        let left = Sha256::hash("minhas".as_bytes());
+        let right = Sha256::hash("bananas".as_bytes());
+        assert_data_eq(
+            "0xe5b6de9313651b6080f3b78bfaae8d0acc6641f7365ff6d339dd6bf9276ddedd",
+            Sha256::join(&left, &right),
+        );
+        assert_eq!(
+            Sha256::join(&left, &right),
+            Sha256::hash(&[left.slice(), right.slice()].concat())
+        );
+    }
+}
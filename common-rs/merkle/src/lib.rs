@@ -1,6 +1,9 @@
 //! This module exposes a bunch of structures for creating and managing [MerkleTree]. To create a new
 //! [MerkleTree] you need to use the [MerkleBuilder] struct. With a MerkleTree you can create a
-//! [MerkleProof] and verify it.
+//! [MerkleProof], or a [MerkleMultiProof] for many leafs, and verify it.
+//!
+//! These types hash with Keccak256. Trees hashed with another [HashFunction], like [Sha256], are
+//! built with [GenericMerkleBuilder], which yields a [GenericMerkleTree].
 //!
 //! # Examples
 //! ```rust
@@ -20,5 +23,11 @@ pub use digest::*;
 mod tree;
 pub use tree::*;
 
+mod multiproof;
+pub use multiproof::*;
+
+mod diff;
+pub use diff::*;
+
 mod tree_builder;
 pub use tree_builder::*;
@@ -0,0 +1,282 @@
+//! This module contains the [GenericMerkleMultiProof] struct, which proves many leafs of a
+//! [GenericMerkleTree] at once, and its Keccak256 instance [MerkleMultiProof].
+
+use crate::{Digest, GenericMerkleTree, HashFunction, Keccak256};
+
+use ruint::{UintTryFrom, aliases::U256};
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// A [MerkleMultiProof] for a [crate::MerkleTree] hashed with Keccak256.
+pub type MerkleMultiProof = GenericMerkleMultiProof<Keccak256>;
+
+/// A [GenericMerkleMultiProof] proves that a set of leafs is part of a [GenericMerkleTree] hashed
+/// with `H`. Siblings shared by
+/// the paths of many leafs, or derivable from the proven leafs, are included only once.
+///
+/// The siblings are ordered as a depth-first, left-to-right walk of the tree visits them, where
+/// every subtree without proven leafs contributes its root hash.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct GenericMerkleMultiProof<H: HashFunction> {
+    pub height: u32,
+    /// positions of the proven leafs, in ascending order
+    pub positions: Vec<U256>,
+    /// proven leafs, in the order of `positions`
+    pub nodes: Vec<Digest>,
+    pub siblings: Vec<Digest>,
+
+    #[serde(skip)]
+    hash: PhantomData<H>,
+}
+
+impl<H: HashFunction> GenericMerkleMultiProof<H> {
+    pub fn new(
+        height: u32,
+        positions: Vec<U256>,
+        nodes: Vec<Digest>,
+        siblings: Vec<Digest>,
+    ) -> Self {
+        Self {
+            height,
+            positions,
+            nodes,
+            siblings,
+            hash: PhantomData,
+        }
+    }
+
+    /// Returns the proven leafs along with their positions.
+    pub fn leafs(&self) -> impl Iterator<Item = (U256, Digest)> + '_ {
+        self.positions
+            .iter()
+            .copied()
+            .zip(self.nodes.iter().copied())
+    }
+
+    /// Computes the root of the tree, or none if the proof is malformed.
+    pub fn build_root(&self) -> Option<Digest> {
+        // positions are 256 bits, and the root is rebuilt recursing once per level
+        if self.height > 256
+            || self.positions.len() != self.nodes.len()
+            || !self.positions.windows(2).all(|w| w[0] < w[1])
+        {
+            return None;
+        }
+
+        if let Some(last) = self.positions.last()
+            && self.height < 256
+            && *last >> self.height as usize != U256::ZERO
+        {
+            return None;
+        }
+
+        let mut nodes = self.nodes.iter();
+        let mut siblings = self.siblings.iter();
+        let root = build_root_rec::<H>(
+            self.height,
+            U256::ZERO,
+            &self.positions,
+            &mut nodes,
+            &mut siblings,
+        )?;
+
+        // every hash in the proof must have been used
+        if nodes.next().is_some() || siblings.next().is_some() {
+            return None;
+        }
+
+        Some(root)
+    }
+
+    pub fn verify_root(&self, root: Digest) -> bool {
+        self.build_root() == Some(root)
+    }
+}
+
+fn build_root_rec<'a, H: HashFunction>(
+    height: u32,
+    offset: U256,
+    positions: &[U256],
+    nodes: &mut impl Iterator<Item = &'a Digest>,
+    siblings: &mut impl Iterator<Item = &'a Digest>,
+) -> Option<Digest> {
+    if positions.is_empty() {
+        return siblings.next().copied();
+    }
+
+    if height == 0 {
+        return nodes.next().copied();
+    }
+
+    let middle = offset + (U256::from(1) << (height - 1) as usize);
+    let (left, right) = positions.split_at(positions.partition_point(|p| *p < middle));
+
+    let left = build_root_rec::<H>(height - 1, offset, left, nodes, siblings)?;
+    let right = build_root_rec::<H>(height - 1, middle, right, nodes, siblings)?;
+    Some(H::join(&left, &right))
+}
+
+impl<H: HashFunction> GenericMerkleTree<H> {
+    /// Builds a [GenericMerkleMultiProof] for the leafs at `indices`. Leafs inside repeated subtrees
+    /// are proven without expanding the repetitions, so the proof takes time and space
+    /// proportional to the number of leafs times the height of the tree.
+    pub fn prove_leafs<T>(&self, indices: impl IntoIterator<Item = T>) -> GenericMerkleMultiProof<H>
+    where
+        U256: UintTryFrom<T>,
+    {
+        let mut positions: Vec<U256> = indices.into_iter().map(U256::from).collect();
+        positions.sort_unstable();
+        positions.dedup();
+
+        if let Some(last) = positions.last() {
+            assert!(
+                self.height() >= 256 || *last >> self.height() as usize == U256::ZERO,
+                "index out of bounds"
+            );
+        }
+
+        let mut proof =
+            GenericMerkleMultiProof::new(self.height(), Vec::new(), Vec::new(), Vec::new());
+        self.prove_leafs_rec(U256::ZERO, &positions, &mut proof);
+        proof.positions = positions;
+        proof
+    }
+
+    fn prove_leafs_rec(
+        &self,
+        offset: U256,
+        positions: &[U256],
+        proof: &mut GenericMerkleMultiProof<H>,
+    ) {
+        if positions.is_empty() {
+            proof.siblings.push(self.root_hash());
+            return;
+        }
+
+        let Some((left, right)) = self.subtrees() else {
+            proof.nodes.push(self.root_hash());
+            return;
+        };
+
+        let middle = offset + (U256::from(1) << (self.height() - 1) as usize);
+        let split = positions.partition_point(|p| *p < middle);
+
+        left.prove_leafs_rec(offset, &positions[..split], proof);
+        right.prove_leafs_rec(middle, &positions[split..], proof);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleBuilder, MerkleTree};
+    use std::sync::Arc;
+
+    fn digest(n: u8) -> Digest {
+        Digest::new([n; 32])
+    }
+
+    fn small_tree() -> Arc<MerkleTree> {
+        let mut builder = MerkleBuilder::default();
+        for i in 0..8 {
+            builder.append(digest(i));
+        }
+        builder.build()
+    }
+
+    #[test]
+    fn test_multiproof() {
+        let tree = small_tree();
+
+        let proof = tree.prove_leafs([5u64, 0, 1, 5]);
+        assert_eq!(
+            proof.positions,
+            vec![U256::from(0), U256::from(1), U256::from(5)]
+        );
+        assert_eq!(proof.nodes, vec![digest(0), digest(1), digest(5)]);
+        // siblings: [2, 3], [4], [6, 7]
+        assert_eq!(proof.siblings.len(), 3);
+        assert!(proof.verify_root(tree.root_hash()));
+
+        // a single leaf needs the siblings of a single proof
+        let proof = tree.prove_leafs([3u64]);
+        let siblings = tree.prove_leaf(3u64).siblings;
+        assert_eq!(proof.siblings.len(), siblings.len());
+        assert!(siblings.iter().all(|s| proof.siblings.contains(s)));
+        assert!(proof.verify_root(tree.root_hash()));
+
+        let proof = tree.prove_leafs(0u64..8);
+        assert!(proof.siblings.is_empty());
+        assert!(proof.verify_root(tree.root_hash()));
+
+        let proof = tree.prove_leafs(Vec::<u64>::new());
+        assert_eq!(proof.siblings, vec![tree.root_hash()]);
+        assert!(proof.verify_root(tree.root_hash()));
+    }
+
+    #[test]
+    fn test_multiproof_repeated() {
+        let mut builder = MerkleBuilder::default();
+        builder.append(digest(1));
+        builder.append_repeated(digest(2), 3);
+        builder.append_repeated(digest(3), 2u128.pow(64) - 4);
+        let tree = builder.build();
+
+        let indices = [0u128, 2, 4, 1 << 40, (1 << 63) + 7, u64::MAX as u128];
+        let proof = tree.prove_leafs(indices);
+        assert_eq!(
+            proof.nodes,
+            vec![
+                digest(1),
+                digest(2),
+                digest(3),
+                digest(3),
+                digest(3),
+                digest(3)
+            ]
+        );
+        assert!(proof.siblings.len() < indices.len() * 64);
+        assert!(proof.verify_root(tree.root_hash()));
+
+        for (position, node) in proof.leafs() {
+            assert_eq!(tree.prove_leaf(position).node, node);
+        }
+    }
+
+    #[test]
+    fn test_invalid_multiproof() {
+        let tree = small_tree();
+        let root = tree.root_hash();
+        let proof = tree.prove_leafs([1u64, 6]);
+
+        let mut wrong_leaf = proof.clone();
+        wrong_leaf.nodes[1] = digest(9);
+        assert!(!wrong_leaf.verify_root(root));
+
+        let mut missing_sibling = proof.clone();
+        missing_sibling.siblings.pop();
+        assert_eq!(missing_sibling.build_root(), None);
+
+        let mut extra_sibling = proof.clone();
+        extra_sibling.siblings.push(digest(0));
+        assert_eq!(extra_sibling.build_root(), None);
+
+        let mut unsorted = proof.clone();
+        unsorted.positions.reverse();
+        unsorted.nodes.reverse();
+        assert_eq!(unsorted.build_root(), None);
+
+        let mut out_of_bounds = proof.clone();
+        out_of_bounds.positions[1] = U256::from(8);
+        assert_eq!(out_of_bounds.build_root(), None);
+
+        let mut too_high = proof.clone();
+        too_high.height = u32::MAX;
+        assert_eq!(too_high.build_root(), None);
+
+        let mut moved = proof;
+        moved.positions[1] = U256::from(7);
+        assert!(!moved.verify_root(root));
+    }
+}
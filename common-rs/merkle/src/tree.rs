@@ -1,33 +1,50 @@
-//! This module contains the [MerkleTree] struct and related types like the
-//! [MerkleProof].
+//! This module contains the [GenericMerkleTree] struct and related types like the
+//! [GenericMerkleProof], along with their Keccak256 instances [MerkleTree] and [MerkleProof].
 
-use crate::Digest;
+use crate::{Digest, HashFunction, Keccak256};
 
 use ruint::{UintTryFrom, aliases::U256};
-use std::{ops::Rem, sync::Arc};
+use serde::{Deserialize, Serialize};
+use std::{marker::PhantomData, ops::Rem, sync::Arc};
 
-/// A [MerkleProof] is used to verify that a leaf is part of a [MerkleTree].
-pub struct MerkleProof {
+mod codec;
+pub use codec::MerkleDecodeError;
+
+/// A [MerkleTree] hashed with Keccak256.
+pub type MerkleTree = GenericMerkleTree<Keccak256>;
+
+/// A [MerkleProof] for a [MerkleTree] hashed with Keccak256.
+pub type MerkleProof = GenericMerkleProof<Keccak256>;
+
+/// A [GenericMerkleProof] is used to verify that a leaf is part of a [GenericMerkleTree] hashed
+/// with `H`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct GenericMerkleProof<H: HashFunction> {
     pub position: U256,
     pub node: Digest,
     pub siblings: Vec<Digest>,
+
+    #[serde(skip)]
+    hash: PhantomData<H>,
 }
 
-impl MerkleProof {
-    pub fn leaf(node: Digest, position: U256) -> Self {
+impl<H: HashFunction> GenericMerkleProof<H> {
+    pub fn new(node: Digest, position: U256, siblings: Vec<Digest>) -> Self {
         Self {
-            node,
             position,
-            siblings: Vec::new(),
+            node,
+            siblings,
+            hash: PhantomData,
         }
     }
 
+    pub fn leaf(node: Digest, position: U256) -> Self {
+        Self::new(node, position, Vec::new())
+    }
+
     pub fn empty() -> Self {
-        Self {
-            position: U256::ZERO,
-            node: Digest::ZERO,
-            siblings: Vec::new(),
-        }
+        Self::new(Digest::ZERO, U256::ZERO, Vec::new())
     }
 
     pub fn build_root(&self) -> Digest {
@@ -37,9 +54,9 @@ impl MerkleProof {
 
         for (i, s) in self.siblings.iter().enumerate() {
             if (self.position >> i).rem(two) == U256::ZERO {
-                root = root.join(s);
+                root = H::join(&root, s);
             } else {
-                root = s.join(&root);
+                root = H::join(s, &root);
             }
         }
 
@@ -56,38 +73,39 @@ impl MerkleProof {
     }
 }
 
-/// A [MerkleTree] is a binary tree where the leafs are the data and the nodes
-/// are the hashes of the children. The root of the tree is the hash of the
+/// A [GenericMerkleTree] is a binary tree where the leafs are the data and the nodes
+/// are the hashes of the children, computed with `H`. The root of the tree is the hash of the
 /// entire tree. The tree is balanced, so the height of the tree is log2(n)
 /// where n is the number of leafs.
 #[derive(Clone, Debug)]
-pub struct MerkleTree {
+pub struct GenericMerkleTree<H: HashFunction> {
     root_hash: Digest,
     height: u32,
 
-    subtrees: Option<InnerNode>,
+    subtrees: Option<InnerNode<H>>,
+    hash: PhantomData<H>,
 }
 
-impl PartialEq for MerkleTree {
+impl<H: HashFunction> PartialEq for GenericMerkleTree<H> {
     fn eq(&self, other: &Self) -> bool {
         self.height == other.height && self.root_hash == other.root_hash
     }
 }
 
 #[derive(Clone, Debug)]
-enum InnerNode {
+enum InnerNode<H: HashFunction> {
     Pair {
-        left: Arc<MerkleTree>,
-        right: Arc<MerkleTree>,
+        left: Arc<GenericMerkleTree<H>>,
+        right: Arc<GenericMerkleTree<H>>,
     },
 
     Iterated {
-        child: Arc<MerkleTree>,
+        child: Arc<GenericMerkleTree<H>>,
     },
 }
 
-impl InnerNode {
-    fn children(&self) -> (Arc<MerkleTree>, Arc<MerkleTree>) {
+impl<H: HashFunction> InnerNode<H> {
+    fn children(&self) -> (Arc<GenericMerkleTree<H>>, Arc<GenericMerkleTree<H>>) {
         match &self {
             InnerNode::Pair { left, right } => (Arc::clone(left), Arc::clone(right)),
             InnerNode::Iterated { child } => (Arc::clone(child), Arc::clone(child)),
@@ -95,18 +113,19 @@ impl InnerNode {
     }
 }
 
-impl From<Digest> for Arc<MerkleTree> {
+impl<H: HashFunction> From<Digest> for Arc<GenericMerkleTree<H>> {
     fn from(value: Digest) -> Self {
-        MerkleTree::leaf(value)
+        GenericMerkleTree::leaf(value)
     }
 }
 
-impl MerkleTree {
+impl<H: HashFunction> GenericMerkleTree<H> {
     pub fn leaf(hash: Digest) -> Arc<Self> {
         Arc::new(Self {
             height: 0,
             root_hash: hash,
             subtrees: None,
+            hash: PhantomData,
         })
     }
 
@@ -122,7 +141,7 @@ impl MerkleTree {
         self.height
     }
 
-    pub fn subtrees(&self) -> Option<(Arc<MerkleTree>, Arc<MerkleTree>)> {
+    pub fn subtrees(&self) -> Option<(Arc<Self>, Arc<Self>)> {
         self.subtrees.as_ref().map(|x| x.children())
     }
 
@@ -144,7 +163,7 @@ impl MerkleTree {
 
     pub fn join(self: &Arc<Self>, other: &Arc<Self>) -> Arc<Self> {
         assert_eq!(self.height, other.height, "tree size mismatch");
-        let root_hash = self.root_hash.join(&other.root_hash);
+        let root_hash = H::join(&self.root_hash, &other.root_hash);
 
         let subtrees = Some(InnerNode::Pair {
             left: Arc::clone(self),
@@ -155,6 +174,7 @@ impl MerkleTree {
             height: self.height + 1,
             root_hash,
             subtrees,
+            hash: PhantomData,
         })
     }
 
@@ -162,7 +182,7 @@ impl MerkleTree {
         let mut root = Arc::clone(self);
 
         for _ in 0..rep {
-            let root_hash = root.root_hash.join(&root.root_hash);
+            let root_hash = H::join(&root.root_hash, &root.root_hash);
             let height = root.height + 1;
             let subtrees = Some(InnerNode::Iterated { child: root });
 
@@ -170,13 +190,14 @@ impl MerkleTree {
                 root_hash,
                 height,
                 subtrees,
+                hash: PhantomData,
             });
         }
 
         root
     }
 
-    pub fn prove_leaf<T>(&self, index: T) -> MerkleProof
+    pub fn prove_leaf<T>(&self, index: T) -> GenericMerkleProof<H>
     where
         U256: UintTryFrom<T>,
     {
@@ -184,21 +205,21 @@ impl MerkleTree {
         self.prove_leaf_rec(index)
     }
 
-    pub fn prove_last(&self) -> MerkleProof {
+    pub fn prove_last(&self) -> GenericMerkleProof<H> {
         let one = U256::from(1);
         self.prove_leaf((one << self.height()) - one)
     }
 }
 
-impl MerkleTree {
-    fn prove_leaf_rec(&self, index: U256) -> MerkleProof {
+impl<H: HashFunction> GenericMerkleTree<H> {
+    fn prove_leaf_rec(&self, index: U256) -> GenericMerkleProof<H> {
         let one = U256::from(1);
         assert!((one << self.height) > index, "index out of bounds");
 
         let Some(subtree) = &self.subtrees else {
             assert_eq!(index, U256::ZERO);
             assert_eq!(self.height, 0);
-            return MerkleProof::leaf(self.root_hash, index);
+            return GenericMerkleProof::leaf(self.root_hash, index);
         };
 
         let shift = (self.height - 1) as usize;
@@ -224,7 +245,10 @@ impl MerkleTree {
 
 #[cfg(test)]
 mod tests {
-    use crate::{Digest, MerkleTree};
+    use crate::{
+        Digest, GenericMerkleBuilder, GenericMerkleTree, HashFunction, MerkleBuilder, MerkleTree,
+        Sha256,
+    };
 
     fn one_digest() -> Digest {
         Digest::from_digest_hex(
@@ -327,4 +351,36 @@ mod tests {
 
         assert_eq!(root, tree.root_hash());
     }
+
+    #[test]
+    pub fn sha256_tree() {
+        let hashes = [0u8, 1, 2, 3].map(|i| Sha256::hash(&[i]));
+        let root = Sha256::join(
+            &Sha256::join(&hashes[0], &hashes[1]),
+            &Sha256::join(&hashes[2], &hashes[3]),
+        );
+
+        let mut builder = GenericMerkleBuilder::<Sha256>::default();
+        let mut keccak_builder = MerkleBuilder::default();
+        for h in hashes {
+            builder.append(h);
+            keccak_builder.append(h);
+        }
+        let tree = builder.build();
+        assert_eq!(tree.root_hash(), root);
+        assert_ne!(keccak_builder.build().root_hash(), root);
+
+        for i in 0..4 {
+            assert!(tree.prove_leaf(i).verify_root(root));
+        }
+
+        let zeroed = GenericMerkleTree::<Sha256>::zeroed().iterated(2);
+        assert_eq!(
+            zeroed.root_hash(),
+            Sha256::join(
+                &Sha256::join(&Digest::ZERO, &Digest::ZERO),
+                &Sha256::join(&Digest::ZERO, &Digest::ZERO)
+            )
+        );
+    }
 }
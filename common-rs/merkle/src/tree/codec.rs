@@ -0,0 +1,412 @@
+//! Serialization of [GenericMerkleTree] and [GenericMerkleProof], both through serde and through a compact
+//! binary encoding.
+//!
+//! A tree is flattened into its distinct nodes, children before parents and the root last, with
+//! inner nodes referring to their children by index. Subtrees shared through an [Arc] are written
+//! once and shared again when the tree is read back, so the size of a tree is proportional to its
+//! distinct nodes, not to its leafs. Inner hashes are not stored; they are recomputed on decoding.
+
+use super::{GenericMerkleProof, GenericMerkleTree, InnerNode};
+use crate::{Digest, HashFunction};
+
+use ruint::aliases::U256;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{collections::HashMap, sync::Arc};
+use thiserror::Error;
+
+const TREE_ENCODING_VERSION: u8 = 1;
+
+const LEAF_TAG: u8 = 0;
+const PAIR_TAG: u8 = 1;
+const ITERATED_TAG: u8 = 2;
+
+const DIGEST_SIZE: usize = 32;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum MerkleDecodeError {
+    #[error("unexpected end of data")]
+    UnexpectedEnd,
+
+    #[error("unsupported tree encoding version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("invalid node tag {0}")]
+    InvalidTag(u8),
+
+    #[error("varint doesn't fit in 64 bits")]
+    VarintOverflow,
+
+    #[error("node {node} refers to a child that doesn't precede it")]
+    InvalidChild { node: u64 },
+
+    #[error("children of node {node} have different heights")]
+    HeightMismatch { node: u64 },
+
+    #[error("tree has no nodes")]
+    Empty,
+
+    #[error("{0} trailing bytes")]
+    TrailingBytes(usize),
+}
+
+/// A node of a flattened tree; children are indexes of preceding nodes.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum FlatNode {
+    Leaf(Digest),
+    Pair(u64, u64),
+    Iterated(u64),
+}
+
+fn flatten<H: HashFunction>(tree: &GenericMerkleTree<H>) -> Vec<FlatNode> {
+    let mut nodes = Vec::new();
+    flatten_rec(tree, &mut nodes, &mut HashMap::new());
+    nodes
+}
+
+fn flatten_rec<H: HashFunction>(
+    tree: &GenericMerkleTree<H>,
+    nodes: &mut Vec<FlatNode>,
+    indexes: &mut HashMap<*const GenericMerkleTree<H>, u64>,
+) -> u64 {
+    let key = tree as *const GenericMerkleTree<H>;
+    if let Some(index) = indexes.get(&key) {
+        return *index;
+    }
+
+    let node = match &tree.subtrees {
+        None => FlatNode::Leaf(tree.root_hash),
+        Some(InnerNode::Pair { left, right }) => {
+            let left = flatten_rec(left, nodes, indexes);
+            let right = flatten_rec(right, nodes, indexes);
+            FlatNode::Pair(left, right)
+        }
+        Some(InnerNode::Iterated { child }) => {
+            FlatNode::Iterated(flatten_rec(child, nodes, indexes))
+        }
+    };
+
+    let index = nodes.len() as u64;
+    nodes.push(node);
+    indexes.insert(key, index);
+    index
+}
+
+fn unflatten<H: HashFunction>(
+    nodes: impl IntoIterator<Item = FlatNode>,
+) -> Result<Arc<GenericMerkleTree<H>>, MerkleDecodeError> {
+    let mut trees: Vec<Arc<GenericMerkleTree<H>>> = Vec::new();
+
+    for node in nodes {
+        let index = trees.len() as u64;
+        let child = |c: u64| {
+            trees
+                .get(c as usize)
+                .ok_or(MerkleDecodeError::InvalidChild { node: index })
+        };
+
+        let tree = match node {
+            FlatNode::Leaf(digest) => GenericMerkleTree::leaf(digest),
+            FlatNode::Pair(left, right) => {
+                let (left, right) = (child(left)?, child(right)?);
+                if left.height != right.height {
+                    return Err(MerkleDecodeError::HeightMismatch { node: index });
+                }
+                left.join(right)
+            }
+            FlatNode::Iterated(c) => child(c)?.iterated(1),
+        };
+        trees.push(tree);
+    }
+
+    trees.pop().ok_or(MerkleDecodeError::Empty)
+}
+
+impl<H: HashFunction> GenericMerkleTree<H> {
+    /// Encodes the tree in a compact binary format, which [GenericMerkleTree::from_bytes] reads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let nodes = flatten(self);
+        let mut bytes = vec![TREE_ENCODING_VERSION];
+        write_varint(&mut bytes, nodes.len() as u64);
+
+        for (index, node) in nodes.iter().enumerate() {
+            let index = index as u64;
+            // children are written as their distance to the node, which is usually small
+            match node {
+                FlatNode::Leaf(digest) => {
+                    bytes.push(LEAF_TAG);
+                    bytes.extend_from_slice(digest.slice());
+                }
+                FlatNode::Pair(left, right) => {
+                    bytes.push(PAIR_TAG);
+                    write_varint(&mut bytes, index - left);
+                    write_varint(&mut bytes, index - right);
+                }
+                FlatNode::Iterated(child) => {
+                    bytes.push(ITERATED_TAG);
+                    write_varint(&mut bytes, index - child);
+                }
+            }
+        }
+
+        bytes
+    }
+
+    /// Decodes a tree encoded by [GenericMerkleTree::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Arc<Self>, MerkleDecodeError> {
+        let mut reader = Reader(bytes);
+        let version = reader.byte()?;
+        if version != TREE_ENCODING_VERSION {
+            return Err(MerkleDecodeError::UnsupportedVersion(version));
+        }
+
+        let count = reader.varint()?;
+        // every node takes at least two bytes
+        let mut nodes = Vec::with_capacity(count.min(bytes.len() as u64 / 2) as usize);
+        for index in 0..count {
+            let child = |distance: u64| {
+                if distance == 0 || distance > index {
+                    Err(MerkleDecodeError::InvalidChild { node: index })
+                } else {
+                    Ok(index - distance)
+                }
+            };
+
+            let node = match reader.byte()? {
+                LEAF_TAG => FlatNode::Leaf(reader.digest()?),
+                PAIR_TAG => FlatNode::Pair(child(reader.varint()?)?, child(reader.varint()?)?),
+                ITERATED_TAG => FlatNode::Iterated(child(reader.varint()?)?),
+                tag => return Err(MerkleDecodeError::InvalidTag(tag)),
+            };
+            nodes.push(node);
+        }
+        reader.finish()?;
+
+        unflatten(nodes)
+    }
+}
+
+impl<H: HashFunction> Serialize for GenericMerkleTree<H> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        flatten(self).serialize(serializer)
+    }
+}
+
+impl<'de, H: HashFunction> Deserialize<'de> for GenericMerkleTree<H> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let nodes = Vec::<FlatNode>::deserialize(deserializer)?;
+        let tree = unflatten(nodes).map_err(serde::de::Error::custom)?;
+        Ok(Arc::unwrap_or_clone(tree))
+    }
+}
+
+impl<H: HashFunction> GenericMerkleProof<H> {
+    /// Encodes the proof in a compact binary format, which [GenericMerkleProof::from_bytes] reads back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 * DIGEST_SIZE + 1 + self.siblings.len() * DIGEST_SIZE);
+        bytes.extend_from_slice(&self.position.to_le_bytes::<32>());
+        bytes.extend_from_slice(self.node.slice());
+        write_varint(&mut bytes, self.siblings.len() as u64);
+        for sibling in &self.siblings {
+            bytes.extend_from_slice(sibling.slice());
+        }
+        bytes
+    }
+
+    /// Decodes a proof encoded by [GenericMerkleProof::to_bytes].
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, MerkleDecodeError> {
+        let mut reader = Reader(bytes);
+        let position = U256::from_le_bytes::<32>(reader.digest()?.data());
+        let node = reader.digest()?;
+
+        let count = reader.varint()?;
+        if count > (reader.0.len() / DIGEST_SIZE) as u64 {
+            return Err(MerkleDecodeError::UnexpectedEnd);
+        }
+        let siblings = (0..count)
+            .map(|_| reader.digest())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+
+        Ok(Self::new(node, position, siblings))
+    }
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn take(&mut self, n: usize) -> Result<&[u8], MerkleDecodeError> {
+        if self.0.len() < n {
+            return Err(MerkleDecodeError::UnexpectedEnd);
+        }
+        let (taken, rest) = self.0.split_at(n);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8, MerkleDecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn digest(&mut self) -> Result<Digest, MerkleDecodeError> {
+        let data = self.take(DIGEST_SIZE)?;
+        Ok(Digest::from_digest(data).expect("slice has digest size"))
+    }
+
+    fn varint(&mut self) -> Result<u64, MerkleDecodeError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            // the tenth byte only has room for the top bit
+            if shift == 63 && byte > 1 {
+                return Err(MerkleDecodeError::VarintOverflow);
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(MerkleDecodeError::VarintOverflow)
+    }
+
+    fn finish(&self) -> Result<(), MerkleDecodeError> {
+        match self.0.len() {
+            0 => Ok(()),
+            n => Err(MerkleDecodeError::TrailingBytes(n)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MerkleBuilder, MerkleProof, MerkleTree};
+
+    fn digest(n: u8) -> Digest {
+        Digest::new([n; 32])
+    }
+
+    fn sample_tree() -> Arc<MerkleTree> {
+        let mut builder = MerkleBuilder::default();
+        builder.append(digest(1));
+        builder.append_repeated(digest(2), 3);
+        builder.append_repeated(digest(3), 2u128.pow(64) - 4);
+        builder.build()
+    }
+
+    fn assert_same_tree(a: &MerkleTree, b: &MerkleTree) {
+        assert_eq!(a, b);
+        match (a.subtrees(), b.subtrees()) {
+            (None, None) => {}
+            (Some((al, ar)), Some((bl, br))) => {
+                let shared = Arc::ptr_eq(&al, &ar);
+                assert_eq!(shared, Arc::ptr_eq(&bl, &br));
+                assert_same_tree(&al, &bl);
+                if !shared {
+                    assert_same_tree(&ar, &br);
+                }
+            }
+            _ => panic!("tree shapes differ"),
+        }
+    }
+
+    #[test]
+    fn test_tree_bytes_roundtrip() {
+        let tree = sample_tree();
+        let bytes = tree.to_bytes();
+        // a 2^64 leaf tree of mostly repeated leafs takes a few kilobytes
+        assert!(bytes.len() < 8 * 1024);
+
+        let decoded = MerkleTree::from_bytes(&bytes).unwrap();
+        assert_same_tree(&tree, &decoded);
+        assert_eq!(decoded.to_bytes(), bytes);
+
+        for i in [0u64, 1, 3, 4, u64::MAX] {
+            let proof = decoded.prove_leaf(i);
+            assert_eq!(proof, tree.prove_leaf(i));
+            assert!(proof.verify_root(tree.root_hash()));
+        }
+    }
+
+    #[test]
+    fn test_tree_serde_roundtrip() {
+        let tree = sample_tree();
+        let json = serde_json::to_string(tree.as_ref()).unwrap();
+        let decoded: MerkleTree = serde_json::from_str(&json).unwrap();
+        assert_same_tree(&tree, &decoded);
+
+        let leaf: MerkleTree =
+            serde_json::from_str(&format!(r#"[{{"Leaf":"{}"}}]"#, digest(7))).unwrap();
+        assert_eq!(leaf.root_hash(), digest(7));
+    }
+
+    #[test]
+    fn test_invalid_tree_bytes() {
+        let bytes = sample_tree().to_bytes();
+
+        assert_eq!(
+            MerkleTree::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            MerkleDecodeError::UnexpectedEnd
+        );
+        assert_eq!(
+            MerkleTree::from_bytes(&[bytes.as_slice(), &[0]].concat()).unwrap_err(),
+            MerkleDecodeError::TrailingBytes(1)
+        );
+        assert_eq!(
+            MerkleTree::from_bytes(&[2, 0]).unwrap_err(),
+            MerkleDecodeError::UnsupportedVersion(2)
+        );
+        assert_eq!(
+            MerkleTree::from_bytes(&[TREE_ENCODING_VERSION, 0]).unwrap_err(),
+            MerkleDecodeError::Empty
+        );
+        assert_eq!(
+            MerkleTree::from_bytes(&[TREE_ENCODING_VERSION, 1, ITERATED_TAG, 1]).unwrap_err(),
+            MerkleDecodeError::InvalidChild { node: 0 }
+        );
+
+        let mut mismatched = vec![TREE_ENCODING_VERSION, 3, LEAF_TAG];
+        mismatched.extend_from_slice(digest(1).slice());
+        mismatched.extend_from_slice(&[ITERATED_TAG, 1, PAIR_TAG, 2, 1]);
+        assert_eq!(
+            MerkleTree::from_bytes(&mismatched).unwrap_err(),
+            MerkleDecodeError::HeightMismatch { node: 2 }
+        );
+
+        let overlong = [[TREE_ENCODING_VERSION].as_slice(), &[0xff; 10]].concat();
+        assert_eq!(
+            MerkleTree::from_bytes(&overlong).unwrap_err(),
+            MerkleDecodeError::VarintOverflow
+        );
+        let unterminated = [[TREE_ENCODING_VERSION].as_slice(), &[0x80; 11]].concat();
+        assert_eq!(
+            MerkleTree::from_bytes(&unterminated).unwrap_err(),
+            MerkleDecodeError::VarintOverflow
+        );
+    }
+
+    #[test]
+    fn test_proof_roundtrip() {
+        let tree = sample_tree();
+        let proof = tree.prove_leaf(2);
+
+        let decoded = MerkleProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(decoded, proof);
+
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(serde_json::from_str::<MerkleProof>(&json).unwrap(), proof);
+
+        let bytes = proof.to_bytes();
+        assert_eq!(
+            MerkleProof::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            MerkleDecodeError::UnexpectedEnd
+        );
+    }
+}
@@ -1,23 +1,32 @@
 //! Module for building merkle trees from leafs.
 
-use crate::MerkleTree;
+use crate::{GenericMerkleTree, HashFunction, Keccak256};
 
 use ruint::{UintTryFrom, aliases::U256};
 use std::sync::Arc;
 
 #[derive(Clone, Debug)]
-struct Node {
-    tree: Arc<MerkleTree>,
+struct Node<H: HashFunction> {
+    tree: Arc<GenericMerkleTree<H>>,
     accumulated_count: U256,
 }
 
-/// A [MerkleBuilder] is used to build a [MerkleTree] from its leafs.
-#[derive(Debug, Default)]
-pub struct MerkleBuilder {
-    trees: Vec<Node>,
+/// A [MerkleBuilder] builds a [crate::MerkleTree] hashed with Keccak256.
+pub type MerkleBuilder = GenericMerkleBuilder<Keccak256>;
+
+/// A [GenericMerkleBuilder] is used to build a [GenericMerkleTree] from its leafs.
+#[derive(Clone, Debug)]
+pub struct GenericMerkleBuilder<H: HashFunction> {
+    trees: Vec<Node<H>>,
+}
+
+impl<H: HashFunction> Default for GenericMerkleBuilder<H> {
+    fn default() -> Self {
+        Self { trees: Vec::new() }
+    }
 }
 
-impl MerkleBuilder {
+impl<H: HashFunction> GenericMerkleBuilder<H> {
     /// Returns the height of the leaf trees, or none if there are no leafs added.
     pub fn height(&self) -> Option<u32> {
         self.trees.last().map(|last| last.tree.height())
@@ -40,7 +49,7 @@ impl MerkleBuilder {
     /// Adds a new leaf to the merkle tree.
     pub fn append<L>(&mut self, leaf: L)
     where
-        L: Into<Arc<MerkleTree>>,
+        L: Into<Arc<GenericMerkleTree<H>>>,
     {
         self.append_repeated(leaf, 1);
     }
@@ -48,7 +57,7 @@ impl MerkleBuilder {
     /// Adds a new leaf to the merkle tree, repeating this leaf `rep` times.
     pub fn append_repeated<L, I>(&mut self, leaf: L, rep: I)
     where
-        L: Into<Arc<MerkleTree>>,
+        L: Into<Arc<GenericMerkleTree<H>>>,
         U256: UintTryFrom<I>,
     {
         let leaf = leaf.into();
@@ -67,7 +76,7 @@ impl MerkleBuilder {
     }
 
     /// Builds the merkle tree from the leafs.
-    pub fn build(&self) -> Arc<MerkleTree> {
+    pub fn build(&self) -> Arc<GenericMerkleTree<H>> {
         let count = self.count().expect("no leafs in merkle builder");
         assert!(
             is_count_pow2(count),
@@ -76,11 +85,11 @@ impl MerkleBuilder {
         );
 
         let log2_size = count.trailing_zeros();
-        MerkleBuilder::build_merkle(self.trees.as_slice(), log2_size, U256::ZERO)
+        Self::build_merkle(self.trees.as_slice(), log2_size, U256::ZERO)
     }
 }
 
-impl MerkleBuilder {
+impl<H: HashFunction> GenericMerkleBuilder<H> {
     fn calculate_accumulated_count(&mut self, rep: U256) -> U256 {
         if let Some(last) = self.trees.last() {
             assert!(!last.accumulated_count.is_zero(), "merkle builder is full");
@@ -94,7 +103,11 @@ impl MerkleBuilder {
         }
     }
 
-    fn build_merkle(trees: &[Node], log2_size: usize, stride: U256) -> Arc<MerkleTree> {
+    fn build_merkle(
+        trees: &[Node<H>],
+        log2_size: usize,
+        stride: U256,
+    ) -> Arc<GenericMerkleTree<H>> {
         let one = U256::from(1);
         let size = one.wrapping_shl(log2_size);
 
@@ -110,12 +123,12 @@ impl MerkleBuilder {
             return iterated;
         }
 
-        let left = MerkleBuilder::build_merkle(
+        let left = Self::build_merkle(
             &trees[first_cell..(last_cell + 1)],
             log2_size - 1,
             stride << 1,
         );
-        let right = MerkleBuilder::build_merkle(
+        let right = Self::build_merkle(
             &trees[first_cell..(last_cell + 1)],
             log2_size - 1,
             (stride << 1) + one,
@@ -126,7 +139,7 @@ impl MerkleBuilder {
 }
 
 // Binary search to find the cell containing the element.
-fn find_cell_containing(trees: &[Node], elem: U256) -> usize {
+fn find_cell_containing<H: HashFunction>(trees: &[Node<H>], elem: U256) -> usize {
     let one = U256::from(1);
     let mut left = 0;
     let mut right = trees.len() - 1;
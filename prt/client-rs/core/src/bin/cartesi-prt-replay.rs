@@ -0,0 +1,89 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! Replays a dispute bundle exported by a node: re-executes the inputs of the epoch on the given
+//! machine, which must have the state hash recorded in the bundle, rebuilds the commitments, and
+//! checks them against the ones the node recorded and, when a provider is given, against the ones
+//! joined on chain. Exits with an error if any check fails.
+
+use alloy::providers::{Provider, ProviderBuilder};
+use anyhow::Result;
+use clap::Parser;
+use env_logger::Env;
+use log::info;
+use std::path::PathBuf;
+
+use cartesi_prt_core::{
+    db::dispute_bundle::DisputeBundle,
+    strategy::replay::{check_machine, replay_on_chain, replay_recorded},
+    tournament::StateReader,
+};
+
+#[derive(Debug, Parser)]
+#[command(name = "cartesi-prt-replay")]
+#[command(about = "Replays an exported dispute bundle offline")]
+struct Args {
+    /// path to the dispute bundle
+    bundle: PathBuf,
+    /// machine snapshot the epoch starts from
+    #[arg(long)]
+    machine_path: String,
+    /// directory for the replay database and snapshots
+    #[arg(long)]
+    work_dir: Option<PathBuf>,
+    /// also check the commitments joined on chain
+    #[arg(long, env)]
+    web3_rpc_url: Option<String>,
+    #[arg(long, env, default_values = &["-32005", "-32600", "-32602", "-32616"])]
+    long_block_range_error_codes: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
+    let args = Args::parse();
+
+    let mut bundle = DisputeBundle::read(&args.bundle)?;
+    let machine_path = args.machine_path;
+    check_machine(&machine_path, bundle.machine_hash)?;
+    let work_dir = args.work_dir.unwrap_or_else(|| {
+        std::env::temp_dir().join(format!("cartesi-prt-replay-{}", std::process::id()))
+    });
+    let root_tournament = bundle.root_tournament;
+    let block_created_number = bundle.block_created_number;
+    // the recorded state hashes are what is being checked, so they are always recomputed
+    bundle.leafs.clear();
+
+    info!(
+        "replaying dispute of tournament {} in {}",
+        root_tournament,
+        work_dir.display()
+    );
+    let (db, commitments) = bundle.import(work_dir)?;
+
+    let mut checks = replay_recorded(&db, machine_path.clone(), &commitments)?;
+    if let Some(url) = args.web3_rpc_url {
+        let provider = ProviderBuilder::new().connect_http(url.parse()?).erased();
+        let reader = StateReader::new(
+            provider,
+            block_created_number,
+            args.long_block_range_error_codes,
+        )?;
+        let tournament_states = reader.fetch_from_root(root_tournament).await?;
+        checks.extend(replay_on_chain(&db, machine_path, &tournament_states)?);
+    }
+
+    for check in &checks {
+        println!("{}", check);
+    }
+
+    let mismatches = checks.iter().filter(|c| !c.is_consistent()).count();
+    if mismatches > 0 {
+        anyhow::bail!(
+            "{} of {} commitment checks failed",
+            mismatches,
+            checks.len()
+        );
+    }
+    Ok(())
+}
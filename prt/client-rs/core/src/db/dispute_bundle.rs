@@ -0,0 +1,153 @@
+// (c) Cartesi and individual authors (see AUTHORS)
+// SPDX-License-Identifier: Apache-2.0 (see LICENSE)
+
+//! A [DisputeBundle] packages what is needed to rebuild the commitments of a dispute away from the
+//! node that took part in it: the inputs and state-hash leafs of the epoch, its root tournament,
+//! the state hash of the machine it starts from, and the commitments the node built.
+
+use crate::db::{
+    dispute_state_access::{DisputeStateAccess, Input, Leaf, RecordedCommitment},
+    sql::error::*,
+};
+
+use alloy::primitives::Address;
+use cartesi_dave_merkle::Digest;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+pub const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DisputeBundle {
+    pub version: u32,
+    pub root_tournament: Address,
+    pub block_created_number: u64,
+    /// state hash of the machine the epoch starts from, checked against the machine given to the
+    /// replay
+    pub machine_hash: Digest,
+    pub inputs: Vec<Input>,
+    pub leafs: Vec<Leaf>,
+    pub commitments: Vec<RecordedCommitment>,
+}
+
+impl DisputeBundle {
+    pub fn export(
+        db: &DisputeStateAccess,
+        root_tournament: Address,
+        block_created_number: u64,
+        machine_hash: Digest,
+    ) -> Result<Self> {
+        let (inputs, leafs) = db.compute_data()?;
+        Ok(Self {
+            version: BUNDLE_VERSION,
+            root_tournament,
+            block_created_number,
+            machine_hash,
+            inputs,
+            leafs,
+            commitments: db.commitments()?,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let bundle: Self = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if bundle.version != BUNDLE_VERSION {
+            return Err(DisputeStateAccessError::UnsupportedBundleVersion {
+                version: bundle.version,
+            });
+        }
+        Ok(bundle)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        serde_json::to_writer(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Seeds a dispute database at `work_path` with the inputs and leafs of the bundle.
+    /// Returns the database and the commitments recorded in the bundle.
+    pub fn import(
+        self,
+        work_path: PathBuf,
+    ) -> Result<(DisputeStateAccess, Vec<RecordedCommitment>)> {
+        let db = DisputeStateAccess::new(
+            self.inputs,
+            self.leafs,
+            self.root_tournament.to_string(),
+            work_path,
+        )?;
+        Ok((db, self.commitments))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::primitives::U256;
+
+    #[test]
+    fn test_export_import() {
+        let dir = tempfile::tempdir().unwrap();
+        let inputs = vec![Input(vec![1, 2, 3]), Input(vec![4])];
+        let leafs = vec![Leaf {
+            hash: [5; 32],
+            repetitions: 16,
+        }];
+        let commitment = RecordedCommitment {
+            level: 0,
+            base_cycle: U256::ZERO,
+            log2_stride: 44,
+            log2_stride_count: 4,
+            root_hash: [6; 32],
+        };
+
+        let db = DisputeStateAccess::new(
+            inputs,
+            leafs,
+            Address::ZERO.to_string(),
+            dir.path().join("node"),
+        )
+        .unwrap();
+        db.insert_commitment(&commitment).unwrap();
+
+        let path = dir.path().join("bundle.json");
+        DisputeBundle::export(&db, Address::ZERO, 7, Digest::new([8; 32]))
+            .unwrap()
+            .write(&path)
+            .unwrap();
+
+        let bundle = DisputeBundle::read(&path).unwrap();
+        assert_eq!(bundle.block_created_number, 7);
+        assert_eq!(bundle.machine_hash, Digest::new([8; 32]));
+
+        let (imported, commitments) = bundle.import(dir.path().join("replay")).unwrap();
+        assert_eq!(commitments, vec![commitment]);
+        assert_eq!(imported.inputs().unwrap(), vec![vec![1, 2, 3], vec![4]]);
+        assert_eq!(imported.compute_data().unwrap().1[0].repetitions, 16);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bundle.json");
+        let bundle = DisputeBundle {
+            version: BUNDLE_VERSION + 1,
+            root_tournament: Address::ZERO,
+            block_created_number: 0,
+            machine_hash: Digest::ZERO,
+            inputs: vec![],
+            leafs: vec![],
+            commitments: vec![],
+        };
+        bundle.write(&path).unwrap();
+
+        assert!(matches!(
+            DisputeBundle::read(&path),
+            Err(DisputeStateAccessError::UnsupportedBundleVersion { .. })
+        ));
+    }
+}
@@ -3,12 +3,15 @@
 
 use crate::{
     db::sql::{dispute_data, error::*, migrations},
-    machine::constants,
+    machine::{MachineCommitment, constants},
 };
-use cartesi_dave_arithmetic::max_uint;
+use cartesi_dave_arithmetic::big_cycle_span;
 use cartesi_dave_merkle::{Digest, MerkleBuilder, MerkleTree};
 
-use alloy::{hex as alloy_hex, primitives::U256};
+use alloy::{
+    hex as alloy_hex,
+    primitives::{Address, B256, Bytes, U256},
+};
 use log::info;
 use rusqlite::{Connection, OpenFlags};
 use serde::{Deserialize, Serialize};
@@ -34,6 +37,102 @@ pub struct Leaf {
     pub repetitions: u64,
 }
 
+/// Root of a [MachineCommitment](crate::machine::MachineCommitment) built by the player.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedCommitment {
+    pub level: u64,
+    pub base_cycle: U256,
+    pub log2_stride: u64,
+    pub log2_stride_count: u64,
+    #[serde(with = "alloy_hex::serde")]
+    pub root_hash: [u8; 32],
+}
+
+/// Progress of an interrupted commitment build. It is persisted together with the leafs computed
+/// so far, so the build can be resumed from `machine_path` instead of from its base cycle.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitmentCheckpoint {
+    pub initial_state: [u8; 32],
+    pub stride_index: u64,
+    pub cycle: u64,
+    pub input_count: u64,
+    pub machine_path: PathBuf,
+    pub revert_path: PathBuf,
+}
+
+/// Funds moved by a transaction sent to a tournament.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub tournament: Address,
+    pub tx_call: String,
+    pub tx_hash: Option<B256>,
+    pub bond: U256,
+    pub gas_used: u64,
+    pub gas_cost: U256,
+    pub refund: U256,
+    pub reverted: bool,
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+}
+
+/// Totals of a set of [LedgerEntry].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LedgerSummary {
+    pub transactions: u64,
+    pub bonded: U256,
+    pub gas_used: u64,
+    pub gas_spent: U256,
+    pub refunded: U256,
+}
+
+impl LedgerSummary {
+    pub fn from_entries<'a>(entries: impl Iterator<Item = &'a LedgerEntry>) -> Self {
+        entries.fold(LedgerSummary::default(), |mut summary, entry| {
+            summary.transactions += 1;
+            summary.bonded += entry.bond;
+            summary.gas_used += entry.gas_used;
+            summary.gas_spent += entry.gas_cost;
+            summary.refunded += entry.refund;
+            summary
+        })
+    }
+
+    pub fn average_gas_cost(&self) -> U256 {
+        if self.transactions == 0 {
+            U256::ZERO
+        } else {
+            self.gas_spent / U256::from(self.transactions)
+        }
+    }
+}
+
+impl std::fmt::Display for LedgerSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} transactions, {} wei bonded, {} wei spent on {} gas, {} wei refunded",
+            self.transactions, self.bonded, self.gas_spent, self.gas_used, self.refunded
+        )
+    }
+}
+
+/// A transaction the player attempted to send, with the decision that led to it.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// seconds since the unix epoch
+    pub timestamp: u64,
+    pub tournament: Address,
+    pub tx_call: String,
+    pub arguments: serde_json::Value,
+    /// state of the tournament and match the decision was based on
+    pub context: serde_json::Value,
+    pub tx_hash: Option<B256>,
+    pub reverted: bool,
+    pub revert_data: Option<Bytes>,
+    /// error that prevented the transaction from being sent
+    pub error: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct DisputeStateAccess {
     connection: Mutex<Connection>,
@@ -69,8 +168,9 @@ impl DisputeStateAccess {
         let db_path = work_path.join("db");
         let no_create_flags = OpenFlags::default() & !OpenFlags::SQLITE_OPEN_CREATE;
         match Connection::open_with_flags(&db_path, no_create_flags) {
-            // database already exists, return it
-            Ok(connection) => {
+            // database already exists, bring its schema up to date and return it
+            Ok(mut connection) => {
+                migrations::migrate_to_latest(&mut connection)?;
                 connection
                     .busy_timeout(std::time::Duration::from_secs(10))
                     .map_err(anyhow::Error::from)
@@ -83,7 +183,7 @@ impl DisputeStateAccess {
             Err(_) => {
                 info!("create new database for dispute");
                 let mut connection = Connection::open(&db_path)?;
-                migrations::migrate_to_latest(&mut connection).unwrap();
+                migrations::migrate_to_latest(&mut connection)?;
                 connection
                     .busy_timeout(std::time::Duration::from_secs(10))
                     .map_err(anyhow::Error::from)
@@ -143,6 +243,142 @@ impl DisputeStateAccess {
         dispute_data::insert_leafs(&conn, level, base_cycle, leafs)
     }
 
+    pub fn commitment_checkpoint(
+        &self,
+        level: u64,
+        base_cycle: U256,
+    ) -> Result<Option<CommitmentCheckpoint>> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::commitment_checkpoint(&conn, level, base_cycle)
+    }
+
+    pub fn insert_checkpoint_leafs<'a>(
+        &self,
+        level: u64,
+        base_cycle: U256,
+        leafs: impl Iterator<Item = &'a Leaf>,
+        checkpoint: &CommitmentCheckpoint,
+    ) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_checkpoint_leafs(&conn, level, base_cycle, leafs, checkpoint)
+    }
+
+    pub fn insert_final_leafs<'a>(
+        &self,
+        level: u64,
+        base_cycle: U256,
+        leafs: impl Iterator<Item = &'a Leaf>,
+    ) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_final_leafs(&conn, level, base_cycle, leafs)
+    }
+
+    pub fn discard_commitment(&self, level: u64, base_cycle: U256) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::discard_commitment(&conn, level, base_cycle)
+    }
+
+    pub fn insert_commitment(&self, commitment: &RecordedCommitment) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_commitment(&conn, commitment)
+    }
+
+    pub fn commitments(&self) -> Result<Vec<RecordedCommitment>> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::commitments(&conn)
+    }
+
+    /// Caches a built commitment, to be reloaded by [DisputeStateAccess::cached_commitment].
+    pub fn cache_commitment(
+        &self,
+        level: u64,
+        base_cycle: U256,
+        log2_stride: u64,
+        commitment: &MachineCommitment,
+    ) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_commitment_tree(
+            &conn,
+            level,
+            base_cycle,
+            log2_stride,
+            commitment.implicit_hash.slice(),
+            &commitment.merkle.to_bytes(),
+        )
+    }
+
+    pub fn cached_commitment(
+        &self,
+        level: u64,
+        base_cycle: U256,
+        log2_stride: u64,
+    ) -> Result<Option<MachineCommitment>> {
+        let conn = self.connection.lock().unwrap();
+        let Some((implicit_hash, tree)) =
+            dispute_data::commitment_tree(&conn, level, base_cycle, log2_stride)?
+        else {
+            return Ok(None);
+        };
+
+        Ok(Some(MachineCommitment {
+            implicit_hash: Digest::from_digest(&implicit_hash)?,
+            merkle: MerkleTree::from_bytes(&tree)?,
+        }))
+    }
+
+    /// Inputs and leafs this dispute was seeded with.
+    pub fn compute_data(&self) -> Result<(Vec<Input>, Vec<Leaf>)> {
+        let conn = self.connection.lock().unwrap();
+        let inputs = dispute_data::inputs(&conn)?
+            .into_iter()
+            .map(Input)
+            .collect();
+        let leafs = dispute_data::leafs(&conn, 0, U256::ZERO)?
+            .into_iter()
+            .map(|(hash, repetitions)| Leaf {
+                hash: hash.try_into().expect("leaf with incorrect length"),
+                repetitions,
+            })
+            .collect();
+        Ok((inputs, leafs))
+    }
+
+    pub fn insert_ledger_entry(&self, entry: &LedgerEntry) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_ledger_entry(&conn, entry)
+    }
+
+    /// Ledger entries of `tournament`, or of the whole dispute if `None`.
+    pub fn ledger(&self, tournament: Option<Address>) -> Result<Vec<LedgerEntry>> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::ledger_entries(&conn, tournament)
+    }
+
+    pub fn ledger_summary(&self, tournament: Option<Address>) -> Result<LedgerSummary> {
+        Ok(LedgerSummary::from_entries(self.ledger(tournament)?.iter()))
+    }
+
+    pub fn insert_audit_entry(&self, entry: &AuditEntry) -> Result<()> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::insert_audit_entry(&conn, entry)
+    }
+
+    pub fn audit_log(&self) -> Result<Vec<AuditEntry>> {
+        let conn = self.connection.lock().unwrap();
+        dispute_data::audit_entries(&conn)
+    }
+
+    /// Writes the audit log as a JSON array to `path`.
+    pub fn export_audit_log(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, &self.audit_log()?)?;
+        Ok(())
+    }
+
+    pub fn checkpoints_path(&self) -> PathBuf {
+        self.work_path.join("checkpoints")
+    }
+
     pub fn leafs(
         &self,
         level: u64,
@@ -178,7 +414,7 @@ impl DisputeStateAccess {
         log2_stride_count: u64,
     ) -> Result<Vec<(Arc<MerkleTree>, u64)>> {
         let mut main_tree = Vec::new();
-        let span_count = max_uint(log2_stride_count - constants::LOG2_UARCH_SPAN_TO_BARCH) + 1;
+        let span_count = big_cycle_span(log2_stride_count);
         let span_size = constants::UARCH_SPAN_TO_BARCH + 1;
         let mut accumulated_repetitions = 0;
         let mut uarch_tree_builder = MerkleBuilder::default();
@@ -1,6 +1,7 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
+pub mod dispute_bundle;
 pub mod dispute_state_access;
 
 pub(crate) mod sql;
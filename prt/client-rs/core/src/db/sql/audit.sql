@@ -0,0 +1,12 @@
+CREATE TABLE audit_log (
+    entry_index INTEGER NOT NULL PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    tournament BLOB NOT NULL,
+    tx_call TEXT NOT NULL,
+    arguments TEXT NOT NULL,
+    context TEXT NOT NULL,
+    tx_hash BLOB,
+    reverted INTEGER NOT NULL,
+    revert_data BLOB,
+    error TEXT
+);
@@ -0,0 +1,11 @@
+CREATE TABLE commitment_checkpoints (
+    level INTEGER NOT NULL,
+    base_cycle BLOB NOT NULL,
+    initial_state BLOB NOT NULL,
+    stride_index INTEGER NOT NULL,
+    cycle INTEGER NOT NULL,
+    input_count INTEGER NOT NULL,
+    machine_path TEXT NOT NULL,
+    revert_path TEXT NOT NULL,
+    PRIMARY KEY (level, base_cycle)
+);
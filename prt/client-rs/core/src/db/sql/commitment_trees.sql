@@ -0,0 +1,8 @@
+CREATE TABLE commitment_trees (
+    level INTEGER NOT NULL,
+    base_cycle BLOB NOT NULL,
+    log2_stride INTEGER NOT NULL,
+    implicit_hash BLOB NOT NULL,
+    tree BLOB NOT NULL,
+    PRIMARY KEY (level, base_cycle, log2_stride)
+);
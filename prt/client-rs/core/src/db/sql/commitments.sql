@@ -0,0 +1,8 @@
+CREATE TABLE commitments (
+    level INTEGER NOT NULL,
+    base_cycle BLOB NOT NULL,
+    log2_stride INTEGER NOT NULL,
+    log2_stride_count INTEGER NOT NULL,
+    root_hash BLOB NOT NULL,
+    PRIMARY KEY (level, base_cycle)
+);
@@ -2,9 +2,11 @@
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 
 use super::error::*;
-use crate::db::dispute_state_access::{Input, Leaf};
+use crate::db::dispute_state_access::{
+    AuditEntry, CommitmentCheckpoint, Input, Leaf, LedgerEntry, RecordedCommitment,
+};
 
-use alloy::primitives::U256;
+use alloy::primitives::{Address, B256, Bytes, U256};
 use rusqlite::{OptionalExtension, params};
 
 //
@@ -156,6 +158,368 @@ pub fn insert_compute_data<'a>(
     Ok(())
 }
 
+//
+// Commitment checkpoints
+//
+
+pub fn commitment_checkpoint(
+    conn: &rusqlite::Connection,
+    level: u64,
+    base_cycle: U256,
+) -> Result<Option<CommitmentCheckpoint>> {
+    let mut stmt = conn.prepare(
+        "\
+        SELECT * FROM commitment_checkpoints
+        WHERE level = ?1 AND base_cycle = ?2
+        ",
+    )?;
+
+    let checkpoint = stmt
+        .query_row(params![level, base_cycle.as_le_slice()], |r| {
+            let initial_state: Vec<u8> = r.get("initial_state")?;
+            let machine_path: String = r.get("machine_path")?;
+            let revert_path: String = r.get("revert_path")?;
+            Ok(CommitmentCheckpoint {
+                initial_state: initial_state
+                    .try_into()
+                    .expect("initial state with incorrect length"),
+                stride_index: r.get("stride_index")?,
+                cycle: r.get("cycle")?,
+                input_count: r.get("input_count")?,
+                machine_path: machine_path.into(),
+                revert_path: revert_path.into(),
+            })
+        })
+        .optional()?;
+
+    Ok(checkpoint)
+}
+
+pub fn insert_checkpoint_leafs<'a>(
+    conn: &rusqlite::Connection,
+    level: u64,
+    base_cycle: U256,
+    leafs: impl Iterator<Item = &'a Leaf>,
+    checkpoint: &CommitmentCheckpoint,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    insert_leafs(&tx, level, base_cycle, leafs)?;
+    tx.execute(
+        "\
+        INSERT OR REPLACE INTO commitment_checkpoints
+        (level, base_cycle, initial_state, stride_index, cycle, input_count, machine_path, revert_path)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        ",
+        params![
+            level,
+            base_cycle.as_le_slice(),
+            checkpoint.initial_state,
+            checkpoint.stride_index,
+            checkpoint.cycle,
+            checkpoint.input_count,
+            checkpoint.machine_path.to_string_lossy(),
+            checkpoint.revert_path.to_string_lossy(),
+        ],
+    )?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn insert_final_leafs<'a>(
+    conn: &rusqlite::Connection,
+    level: u64,
+    base_cycle: U256,
+    leafs: impl Iterator<Item = &'a Leaf>,
+) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    insert_leafs(&tx, level, base_cycle, leafs)?;
+    tx.execute(
+        "\
+        DELETE FROM commitment_trees
+        WHERE level = ?1 AND base_cycle = ?2
+        ",
+        params![level, base_cycle.as_le_slice()],
+    )?;
+    delete_checkpoint(&tx, level, base_cycle)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn discard_commitment(conn: &rusqlite::Connection, level: u64, base_cycle: U256) -> Result<()> {
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(
+        "\
+        DELETE FROM leafs
+        WHERE level = ?1 AND base_cycle = ?2
+        ",
+        params![level, base_cycle.as_le_slice()],
+    )?;
+    tx.execute(
+        "\
+        DELETE FROM commitment_trees
+        WHERE level = ?1 AND base_cycle = ?2
+        ",
+        params![level, base_cycle.as_le_slice()],
+    )?;
+    delete_checkpoint(&tx, level, base_cycle)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn delete_checkpoint(conn: &rusqlite::Connection, level: u64, base_cycle: U256) -> Result<()> {
+    conn.execute(
+        "\
+        DELETE FROM commitment_checkpoints
+        WHERE level = ?1 AND base_cycle = ?2
+        ",
+        params![level, base_cycle.as_le_slice()],
+    )?;
+
+    Ok(())
+}
+
+//
+// Commitments
+//
+
+pub fn insert_commitment(
+    conn: &rusqlite::Connection,
+    commitment: &RecordedCommitment,
+) -> Result<()> {
+    conn.execute(
+        "\
+        INSERT OR IGNORE INTO commitments
+        (level, base_cycle, log2_stride, log2_stride_count, root_hash)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ",
+        params![
+            commitment.level,
+            commitment.base_cycle.as_le_slice(),
+            commitment.log2_stride,
+            commitment.log2_stride_count,
+            commitment.root_hash,
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn commitments(conn: &rusqlite::Connection) -> Result<Vec<RecordedCommitment>> {
+    let mut stmt = conn.prepare(
+        "\
+        SELECT * FROM commitments
+        ",
+    )?;
+
+    let query = stmt.query_map([], |r| {
+        let base_cycle: Vec<u8> = r.get("base_cycle")?;
+        let root_hash: Vec<u8> = r.get("root_hash")?;
+        Ok(RecordedCommitment {
+            level: r.get("level")?,
+            base_cycle: U256::from_le_slice(&base_cycle),
+            log2_stride: r.get("log2_stride")?,
+            log2_stride_count: r.get("log2_stride_count")?,
+            root_hash: root_hash
+                .try_into()
+                .expect("root hash with incorrect length"),
+        })
+    })?;
+
+    let mut res = vec![];
+    for row in query {
+        res.push(row?);
+    }
+    // base cycles are stored little-endian, so they can't be ordered by the query
+    res.sort_by_key(|c| (c.level, c.base_cycle));
+
+    Ok(res)
+}
+
+pub fn insert_commitment_tree(
+    conn: &rusqlite::Connection,
+    level: u64,
+    base_cycle: U256,
+    log2_stride: u64,
+    implicit_hash: &[u8],
+    tree: &[u8],
+) -> Result<()> {
+    conn.execute(
+        "\
+        INSERT OR REPLACE INTO commitment_trees
+        (level, base_cycle, log2_stride, implicit_hash, tree)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ",
+        params![
+            level,
+            base_cycle.as_le_slice(),
+            log2_stride,
+            implicit_hash,
+            tree
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// Returns the implicit hash and the encoded tree of a cached commitment.
+pub fn commitment_tree(
+    conn: &rusqlite::Connection,
+    level: u64,
+    base_cycle: U256,
+    log2_stride: u64,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut stmt = conn.prepare(
+        "\
+        SELECT implicit_hash, tree FROM commitment_trees
+        WHERE level = ?1 AND base_cycle = ?2 AND log2_stride = ?3
+        ",
+    )?;
+
+    Ok(stmt
+        .query_row(params![level, base_cycle.as_le_slice(), log2_stride], |r| {
+            Ok((r.get("implicit_hash")?, r.get("tree")?))
+        })
+        .optional()?)
+}
+
+//
+// Ledger
+//
+
+pub fn insert_ledger_entry(conn: &rusqlite::Connection, entry: &LedgerEntry) -> Result<()> {
+    conn.execute(
+        "\
+        INSERT INTO ledger
+        (tournament, tx_call, tx_hash, bond, gas_used, gas_cost, refund, reverted, timestamp)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ",
+        params![
+            entry.tournament.as_slice(),
+            entry.tx_call,
+            entry.tx_hash.as_ref().map(|h| h.as_slice()),
+            entry.bond.as_le_slice(),
+            entry.gas_used,
+            entry.gas_cost.as_le_slice(),
+            entry.refund.as_le_slice(),
+            entry.reverted,
+            entry.timestamp,
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn ledger_entries(
+    conn: &rusqlite::Connection,
+    tournament: Option<Address>,
+) -> Result<Vec<LedgerEntry>> {
+    let mut stmt = conn.prepare(
+        "\
+        SELECT * FROM ledger
+        WHERE ?1 IS NULL OR tournament = ?1
+        ORDER BY entry_index ASC
+        ",
+    )?;
+
+    let query = stmt.query_map(params![tournament.as_ref().map(|t| t.as_slice())], |r| {
+        let tournament: Vec<u8> = r.get("tournament")?;
+        let tx_hash: Option<Vec<u8>> = r.get("tx_hash")?;
+        let bond: Vec<u8> = r.get("bond")?;
+        let gas_cost: Vec<u8> = r.get("gas_cost")?;
+        let refund: Vec<u8> = r.get("refund")?;
+        Ok(LedgerEntry {
+            tournament: Address::from_slice(&tournament),
+            tx_call: r.get("tx_call")?,
+            tx_hash: tx_hash.map(|h| B256::from_slice(&h)),
+            bond: U256::from_le_slice(&bond),
+            gas_used: r.get("gas_used")?,
+            gas_cost: U256::from_le_slice(&gas_cost),
+            refund: U256::from_le_slice(&refund),
+            reverted: r.get("reverted")?,
+            timestamp: r.get("timestamp")?,
+        })
+    })?;
+
+    let mut res = vec![];
+    for row in query {
+        res.push(row?);
+    }
+
+    Ok(res)
+}
+
+//
+// Audit log
+//
+
+pub fn insert_audit_entry(conn: &rusqlite::Connection, entry: &AuditEntry) -> Result<()> {
+    conn.execute(
+        "\
+        INSERT INTO audit_log
+        (timestamp, tournament, tx_call, arguments, context, tx_hash, reverted, revert_data, error)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ",
+        params![
+            entry.timestamp,
+            entry.tournament.as_slice(),
+            entry.tx_call,
+            serde_json::to_string(&entry.arguments)?,
+            serde_json::to_string(&entry.context)?,
+            entry.tx_hash.as_ref().map(|h| h.as_slice()),
+            entry.reverted,
+            entry.revert_data.as_ref().map(|d| d.as_ref()),
+            entry.error,
+        ],
+    )?;
+
+    Ok(())
+}
+
+pub fn audit_entries(conn: &rusqlite::Connection) -> Result<Vec<AuditEntry>> {
+    let mut stmt = conn.prepare(
+        "\
+        SELECT * FROM audit_log
+        ORDER BY entry_index ASC
+        ",
+    )?;
+
+    let query = stmt.query_map([], |r| {
+        let tournament: Vec<u8> = r.get("tournament")?;
+        let tx_hash: Option<Vec<u8>> = r.get("tx_hash")?;
+        let revert_data: Option<Vec<u8>> = r.get("revert_data")?;
+        let arguments: String = r.get("arguments")?;
+        let context: String = r.get("context")?;
+        Ok((
+            AuditEntry {
+                timestamp: r.get("timestamp")?,
+                tournament: Address::from_slice(&tournament),
+                tx_call: r.get("tx_call")?,
+                arguments: serde_json::Value::Null,
+                context: serde_json::Value::Null,
+                tx_hash: tx_hash.map(|h| B256::from_slice(&h)),
+                reverted: r.get("reverted")?,
+                revert_data: revert_data.map(Bytes::from),
+                error: r.get("error")?,
+            },
+            arguments,
+            context,
+        ))
+    })?;
+
+    let mut res = vec![];
+    for row in query {
+        let (mut entry, arguments, context) = row?;
+        entry.arguments = serde_json::from_str(&arguments)?;
+        entry.context = serde_json::from_str(&context)?;
+        res.push(entry);
+    }
+
+    Ok(res)
+}
+
 //
 // Tests
 //
@@ -303,3 +667,245 @@ mod leafs_tests {
         assert!(matches!(leafs(&conn, 1, U256::from(0)).unwrap().len(), 2));
     }
 }
+
+#[cfg(test)]
+mod checkpoints_tests {
+    use super::*;
+
+    fn leaf(repetitions: u64) -> Leaf {
+        Leaf {
+            hash: [7; 32],
+            repetitions,
+        }
+    }
+
+    fn checkpoint(stride_index: u64) -> CommitmentCheckpoint {
+        CommitmentCheckpoint {
+            initial_state: [1; 32],
+            stride_index,
+            cycle: stride_index * 4,
+            input_count: 3,
+            machine_path: format!("/tmp/checkpoint-{stride_index}").into(),
+            revert_path: "/tmp/revert".into(),
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let conn = test_helper::setup_db();
+        assert!(matches!(
+            commitment_checkpoint(&conn, 0, U256::ZERO),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_and_finish() {
+        let conn = test_helper::setup_db();
+        let base_cycle = U256::from(5);
+
+        insert_checkpoint_leafs(
+            &conn,
+            1,
+            base_cycle,
+            [leaf(1), leaf(1)].iter(),
+            &checkpoint(2),
+        )
+        .unwrap();
+        assert_eq!(
+            commitment_checkpoint(&conn, 1, base_cycle).unwrap(),
+            Some(checkpoint(2))
+        );
+
+        // later checkpoints replace earlier ones, leafs accumulate
+        insert_checkpoint_leafs(
+            &conn,
+            1,
+            base_cycle,
+            [leaf(1), leaf(1)].iter(),
+            &checkpoint(4),
+        )
+        .unwrap();
+        assert_eq!(
+            commitment_checkpoint(&conn, 1, base_cycle).unwrap(),
+            Some(checkpoint(4))
+        );
+        assert_eq!(leafs(&conn, 1, base_cycle).unwrap().len(), 4);
+
+        insert_final_leafs(&conn, 1, base_cycle, [leaf(4)].iter()).unwrap();
+        assert!(
+            commitment_checkpoint(&conn, 1, base_cycle)
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(leafs(&conn, 1, base_cycle).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn test_discard() {
+        let conn = test_helper::setup_db();
+        let base_cycle = U256::from(5);
+
+        insert_leafs(&conn, 1, U256::ZERO, [leaf(1)].iter()).unwrap();
+        insert_checkpoint_leafs(&conn, 1, base_cycle, [leaf(1)].iter(), &checkpoint(1)).unwrap();
+        discard_commitment(&conn, 1, base_cycle).unwrap();
+
+        assert!(
+            commitment_checkpoint(&conn, 1, base_cycle)
+                .unwrap()
+                .is_none()
+        );
+        assert!(leafs(&conn, 1, base_cycle).unwrap().is_empty());
+        // other commitments are untouched
+        assert_eq!(leafs(&conn, 1, U256::ZERO).unwrap().len(), 1);
+    }
+}
+
+#[cfg(test)]
+mod ledger_tests {
+    use super::*;
+
+    fn entry(tournament: Address, bond: u64, refund: u64) -> LedgerEntry {
+        LedgerEntry {
+            tournament,
+            tx_call: "joinTournament".to_owned(),
+            tx_hash: Some(B256::repeat_byte(3)),
+            bond: U256::from(bond),
+            gas_used: 21000,
+            gas_cost: U256::from(21000 * 7),
+            refund: U256::from(refund),
+            reverted: false,
+            timestamp: 1700000000,
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let conn = test_helper::setup_db();
+        assert!(ledger_entries(&conn, None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_and_filter() {
+        let conn = test_helper::setup_db();
+        let root = Address::repeat_byte(1);
+        let inner = Address::repeat_byte(2);
+
+        insert_ledger_entry(&conn, &entry(root, 100, 0)).unwrap();
+        insert_ledger_entry(&conn, &entry(inner, 0, 5)).unwrap();
+        let mut reverted = entry(inner, 0, 0);
+        reverted.tx_hash = None;
+        reverted.reverted = true;
+        insert_ledger_entry(&conn, &reverted).unwrap();
+
+        let all = ledger_entries(&conn, None).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[0], entry(root, 100, 0));
+        assert_eq!(all[2], reverted);
+
+        assert_eq!(ledger_entries(&conn, Some(root)).unwrap().len(), 1);
+        assert_eq!(ledger_entries(&conn, Some(inner)).unwrap().len(), 2);
+        assert!(
+            ledger_entries(&conn, Some(Address::ZERO))
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
+
+#[cfg(test)]
+mod audit_tests {
+    use super::*;
+
+    fn entry(tx_call: &str, error: Option<String>) -> AuditEntry {
+        AuditEntry {
+            timestamp: 1700000000,
+            tournament: Address::repeat_byte(1),
+            tx_call: tx_call.to_owned(),
+            arguments: serde_json::json!({ "left_node": "0x01", "right_node": "0x02" }),
+            context: serde_json::json!({ "match_height": 3, "direction": "left" }),
+            tx_hash: error.is_none().then(|| B256::repeat_byte(2)),
+            reverted: false,
+            revert_data: None,
+            error,
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let conn = test_helper::setup_db();
+        assert!(audit_entries(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_and_read() {
+        let conn = test_helper::setup_db();
+
+        let mut reverted = entry("advanceMatch", None);
+        reverted.reverted = true;
+        reverted.revert_data = Some(Bytes::from(vec![0xde, 0xad]));
+        let failed = entry("winLeafMatch", Some("connection refused".to_owned()));
+
+        insert_audit_entry(&conn, &entry("joinTournament", None)).unwrap();
+        insert_audit_entry(&conn, &reverted).unwrap();
+        insert_audit_entry(&conn, &failed).unwrap();
+
+        assert_eq!(
+            audit_entries(&conn).unwrap(),
+            vec![entry("joinTournament", None), reverted, failed]
+        );
+    }
+}
+
+#[cfg(test)]
+mod commitments_tests {
+    use super::*;
+
+    fn commitment(level: u64, base_cycle: u64, root_hash: u8) -> RecordedCommitment {
+        RecordedCommitment {
+            level,
+            base_cycle: U256::from(base_cycle),
+            log2_stride: 44,
+            log2_stride_count: 4,
+            root_hash: [root_hash; 32],
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        let conn = test_helper::setup_db();
+        assert!(commitments(&conn).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_insert_once() {
+        let conn = test_helper::setup_db();
+
+        insert_commitment(&conn, &commitment(1, 8, 2)).unwrap();
+        insert_commitment(&conn, &commitment(0, 0, 1)).unwrap();
+        // a commitment is only recorded the first time it's built
+        insert_commitment(&conn, &commitment(1, 8, 3)).unwrap();
+
+        assert_eq!(
+            commitments(&conn).unwrap(),
+            vec![commitment(0, 0, 1), commitment(1, 8, 2)]
+        );
+    }
+    #[test]
+    fn test_commitment_tree() {
+        let conn = test_helper::setup_db();
+        let base_cycle = U256::from(8);
+        assert!(commitment_tree(&conn, 1, base_cycle, 20).unwrap().is_none());
+
+        insert_commitment_tree(&conn, 1, base_cycle, 20, &[1; 32], &[1, 2, 3]).unwrap();
+        insert_commitment_tree(&conn, 1, base_cycle, 20, &[2; 32], &[4, 5]).unwrap();
+        assert_eq!(
+            commitment_tree(&conn, 1, base_cycle, 20).unwrap(),
+            Some((vec![2; 32], vec![4, 5]))
+        );
+        assert!(commitment_tree(&conn, 1, base_cycle, 10).unwrap().is_none());
+
+        discard_commitment(&conn, 1, base_cycle).unwrap();
+        assert!(commitment_tree(&conn, 1, base_cycle, 20).unwrap().is_none());
+    }
+}
@@ -11,6 +11,12 @@ pub enum DisputeStateAccessError {
         source: cartesi_dave_merkle::DigestError,
     },
 
+    #[error(transparent)]
+    MerkleDecode {
+        #[from]
+        source: cartesi_dave_merkle::MerkleDecodeError,
+    },
+
     #[error(transparent)]
     IO {
         #[from]
@@ -29,8 +35,17 @@ pub enum DisputeStateAccessError {
         source: rusqlite::Error,
     },
 
+    #[error(transparent)]
+    Migration {
+        #[from]
+        source: rusqlite_migration::Error,
+    },
+
     #[error("Failed to insert data: `{description}`")]
     InsertionFailed { description: String },
+
+    #[error("Unsupported dispute bundle version {version}")]
+    UnsupportedBundleVersion { version: u32 },
 }
 
 pub type Result<T> = std::result::Result<T, DisputeStateAccessError>;
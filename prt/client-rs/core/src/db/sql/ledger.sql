@@ -0,0 +1,14 @@
+CREATE TABLE ledger (
+    entry_index INTEGER NOT NULL PRIMARY KEY,
+    tournament BLOB NOT NULL,
+    tx_call TEXT NOT NULL,
+    tx_hash BLOB,
+    bond BLOB NOT NULL,
+    gas_used INTEGER NOT NULL,
+    gas_cost BLOB NOT NULL,
+    refund BLOB NOT NULL,
+    reverted INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL
+);
+
+CREATE INDEX ledger_tournament ON ledger (tournament);
@@ -3,8 +3,14 @@ use rusqlite::Connection;
 use rusqlite_migration::{M, Migrations};
 
 lazy_static! {
-    pub static ref MIGRATIONS: Migrations<'static> =
-        Migrations::new(vec![M::up(include_str!("migrations.sql")),]);
+    pub static ref MIGRATIONS: Migrations<'static> = Migrations::new(vec![
+        M::up(include_str!("migrations.sql")),
+        M::up(include_str!("checkpoints.sql")),
+        M::up(include_str!("ledger.sql")),
+        M::up(include_str!("audit.sql")),
+        M::up(include_str!("commitments.sql")),
+        M::up(include_str!("commitment_trees.sql")),
+    ]);
 }
 
 pub fn migrate_to_latest(conn: &mut Connection) -> Result<(), rusqlite_migration::Error> {
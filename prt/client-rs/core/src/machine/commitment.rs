@@ -2,19 +2,24 @@
 //! described on the paper https://arxiv.org/pdf/2212.12439.pdf.
 
 use alloy::primitives::U256;
-use log::{info, trace};
+use log::{info, trace, warn};
 use std::io::{self, Write};
+use std::ops::Range;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Instant;
 
 use crate::{
-    db::dispute_state_access::{DisputeStateAccess, Leaf},
+    db::dispute_state_access::{CommitmentCheckpoint, DisputeStateAccess, Leaf},
     machine::error::Result,
     machine::{MachineInstance, constants},
 };
-use cartesi_dave_arithmetic::max_uint;
+use cartesi_dave_arithmetic::big_cycle_span;
 use cartesi_dave_merkle::{Digest, MerkleBuilder, MerkleTree};
 
+/// Number of strides computed between two checkpoints of a big machine commitment.
+pub const LEAFS_CHECKPOINT_INTERVAL: u64 = 1 << 10;
+
 /// The [MachineCommitment] struct represents a `computation hash`, that is a [MerkleTree] of a set
 /// of steps of the Cartesi Machine.
 #[derive(Clone, Debug)]
@@ -56,29 +61,28 @@ pub fn build_machine_commitment(
         "Begin building commitment for level {level}: start cycle {base_cycle}, log2_stride {log2_stride} and log2_stride_count {log2_stride_count}"
     );
 
+    let initial_state = machine.root_hash()?;
+
     // If machine is at yielded awaiting input, we unyield it.
     // This puts the machine in an in-between state transion;
     // its state hash is now meaningless until we run an instruction.
     if machine.cycle == 0 && machine.ucycle == 0 {
-        assert!(machine.is_yielded()?);
+        assert!(machine.is_yielded()? || machine.is_halted()?);
         machine.feed_next_input(db)?;
     }
 
     let start = Instant::now();
 
     if log2_stride >= constants::LOG2_UARCH_SPAN_TO_BARCH {
-        assert!(
-            log2_stride + log2_stride_count
-                <= constants::LOG2_INPUT_SPAN_TO_EPOCH
-                    + constants::LOG2_BARCH_SPAN_TO_INPUT
-                    + constants::LOG2_UARCH_SPAN_TO_BARCH
-        );
+        assert!(log2_stride + log2_stride_count <= constants::LOG2_UARCH_SPAN_TO_EPOCH);
         build_big_machine_commitment(
             machine,
             level,
             base_cycle,
             log2_stride,
             log2_stride_count,
+            initial_state,
+            0..1 << log2_stride_count,
             db,
         )?;
     } else {
@@ -94,20 +98,83 @@ pub fn build_machine_commitment(
     Ok(db.leafs(level, log2_stride, log2_stride_count, base_cycle)?)
 }
 
+/// Resumes building the leafs of an interrupted big machine commitment from its last checkpoint.
+/// Returns `None` if the checkpoint can't be resumed, in which case its partial leafs are
+/// discarded and the commitment must be built from scratch.
+pub fn resume_machine_commitment(
+    checkpoint: &CommitmentCheckpoint,
+    base_cycle: U256,
+    level: u64,
+    log2_stride: u64,
+    log2_stride_count: u64,
+    db: &DisputeStateAccess,
+) -> Result<Option<Vec<(Arc<MerkleTree>, u64)>>> {
+    // the pre-input snapshot is needed in case the input is rejected
+    if !checkpoint.machine_path.exists() || !checkpoint.revert_path.exists() {
+        warn!(
+            "checkpoint for level {level} (start cycle {base_cycle}) is missing machine snapshots, discarding it"
+        );
+        db.discard_commitment(level, base_cycle)?;
+        remove_checkpoint_dir(&checkpoint.machine_path)?;
+        return Ok(None);
+    }
+
+    info!(
+        "Resume building commitment for level {level}: start cycle {base_cycle}, from stride {}",
+        checkpoint.stride_index
+    );
+
+    let start = Instant::now();
+    let mut machine = MachineInstance::from_checkpoint(checkpoint)?;
+    build_big_machine_commitment(
+        &mut machine,
+        level,
+        base_cycle,
+        log2_stride,
+        log2_stride_count,
+        Digest::new(checkpoint.initial_state),
+        checkpoint.stride_index..1 << log2_stride_count,
+        db,
+    )?;
+
+    info!(
+        "Finished building for level {level} (start cycle {base_cycle}, log2_stride {log2_stride} and log2_stride_count {log2_stride_count}) in {} seconds",
+        start.elapsed().as_secs()
+    );
+
+    Ok(Some(db.leafs(
+        level,
+        log2_stride,
+        log2_stride_count,
+        base_cycle,
+    )?))
+}
+
 /// Builds a [MachineCommitment] Hash for the Cartesi Machine using the big machine model.
+/// Leafs are persisted every [LEAFS_CHECKPOINT_INTERVAL] strides, along with a snapshot of the
+/// machine, so an interrupted build can be resumed with [resume_machine_commitment]. Only the
+/// `strides` given are run; the leafs are final once the last stride is reached or the machine
+/// halts or yields.
+#[allow(clippy::too_many_arguments)]
 fn build_big_machine_commitment(
     machine: &mut MachineInstance,
     level: u64,
     base_cycle: U256,
     log2_stride: u64,
     log2_stride_count: u64,
+    initial_state: Digest,
+    strides: Range<u64>,
     db: &DisputeStateAccess,
 ) -> Result<()> {
     let mut leafs = Vec::new();
+    let mut checkpoint_path = db
+        .commitment_checkpoint(level, base_cycle)?
+        .map(|checkpoint| checkpoint.machine_path);
     let instruction_count = 1 << log2_stride_count;
-    let stride = 1 << (log2_stride - constants::LOG2_UARCH_SPAN_TO_BARCH);
+    let stride = big_cycle_span(log2_stride);
+    let mut finished = strides.end == instruction_count;
 
-    for instruction in 0..instruction_count {
+    for instruction in strides {
         print_flush_same_line(&format!(
             "building big machine commitment ({}/{})...",
             instruction, instruction_count
@@ -127,13 +194,55 @@ fn build_big_machine_commitment(
                 hash: state.root_hash.into(),
                 repetitions: instruction_count - instruction,
             });
+            finished = true;
             break;
         }
+
+        let next_instruction = instruction + 1;
+        if next_instruction % LEAFS_CHECKPOINT_INTERVAL == 0 && next_instruction < instruction_count
+        {
+            let checkpoints_path = db.checkpoints_path();
+            std::fs::create_dir_all(&checkpoints_path)?;
+            let path = checkpoints_path.join(format!("{level}-{base_cycle}-{next_instruction}"));
+            // a crash before the checkpoint below is committed leaves this snapshot behind, with no
+            // row pointing to it; storing over it would fail and the build could never resume
+            remove_checkpoint_dir(&path)?;
+            machine.store(&path)?;
+
+            let checkpoint = CommitmentCheckpoint {
+                initial_state: initial_state.into(),
+                stride_index: next_instruction,
+                cycle: machine.cycle,
+                input_count: machine.input_count,
+                machine_path: path.clone(),
+                revert_path: machine.snapshot_path.clone(),
+            };
+            db.insert_checkpoint_leafs(level, base_cycle, leafs.iter(), &checkpoint)?;
+            leafs.clear();
+
+            // previous checkpoint is superseded by the one just persisted
+            if let Some(previous) = checkpoint_path.replace(path) {
+                remove_checkpoint_dir(&previous)?;
+            }
+        }
     }
     finish_print_flush_same_line();
 
-    db.insert_leafs(level, base_cycle, leafs.iter())?;
+    if !finished {
+        return Ok(());
+    }
+    db.insert_final_leafs(level, base_cycle, leafs.iter())?;
+    if let Some(path) = checkpoint_path {
+        remove_checkpoint_dir(&path)?;
+    }
+
+    Ok(())
+}
 
+fn remove_checkpoint_dir(path: &Path) -> Result<()> {
+    if path.exists() {
+        std::fs::remove_dir_all(path)?;
+    }
     Ok(())
 }
 
@@ -144,7 +253,7 @@ fn build_small_machine_commitment(
     log2_stride_count: u64,
     db: &DisputeStateAccess,
 ) -> Result<()> {
-    let span_count = max_uint(log2_stride_count - constants::LOG2_UARCH_SPAN_TO_BARCH);
+    let span_count = big_cycle_span(log2_stride_count) - 1;
 
     let mut span = 0;
     while span <= span_count {
@@ -231,3 +340,113 @@ fn finish_print_flush_same_line() {
     // Flush the output to ensure it appears immediately
     io::stdout().flush().unwrap();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cartesi_machine::{
+        Machine,
+        config::{
+            machine::{MachineConfig, RAMConfig},
+            runtime::RuntimeConfig,
+        },
+    };
+
+    const LOG2_STRIDE_COUNT: u64 = 12;
+
+    fn create_machine(dir: &Path) -> String {
+        let machine_path = dir.join("machine");
+        let mut machine = Machine::create(
+            &MachineConfig::new_with_ram(RAMConfig {
+                length: 134217728,
+                image_filename: "../../../test/programs/linux.bin".into(),
+            }),
+            &RuntimeConfig::default(),
+        )
+        .unwrap();
+        machine.store(&machine_path).unwrap();
+
+        machine_path.to_string_lossy().into_owned()
+    }
+
+    fn state_access(dir: &Path) -> DisputeStateAccess {
+        DisputeStateAccess::new(Vec::new(), Vec::new(), String::new(), dir.to_path_buf()).unwrap()
+    }
+
+    fn root_hashes(leafs: &[(Arc<MerkleTree>, u64)]) -> Vec<(Digest, u64)> {
+        leafs
+            .iter()
+            .map(|(tree, r)| (tree.root_hash(), *r))
+            .collect()
+    }
+
+    fn build(
+        machine_path: &str,
+        strides: Range<u64>,
+        db: &DisputeStateAccess,
+    ) -> Vec<(Arc<MerkleTree>, u64)> {
+        let mut machine = MachineInstance::new_from_path(machine_path).unwrap();
+        let initial_state = machine.root_hash().unwrap();
+        build_big_machine_commitment(
+            &mut machine,
+            0,
+            U256::ZERO,
+            constants::LOG2_UARCH_SPAN_TO_BARCH,
+            LOG2_STRIDE_COUNT,
+            initial_state,
+            strides,
+            db,
+        )
+        .unwrap();
+
+        db.leafs(
+            0,
+            constants::LOG2_UARCH_SPAN_TO_BARCH,
+            LOG2_STRIDE_COUNT,
+            U256::ZERO,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_resume_interrupted_commitment() {
+        let dir = tempfile::tempdir().unwrap();
+        let machine_path = create_machine(dir.path());
+        let stride_count = 1 << LOG2_STRIDE_COUNT;
+
+        let full = build(
+            &machine_path,
+            0..stride_count,
+            &state_access(&dir.path().join("full")),
+        );
+
+        // interrupted right after the checkpoint at stride 2048 is committed, while the snapshot of
+        // the next one was being stored
+        let db = state_access(&dir.path().join("resumed"));
+        build(&machine_path, 0..2 * LEAFS_CHECKPOINT_INTERVAL, &db);
+        let orphan = db
+            .checkpoints_path()
+            .join(format!("0-0-{}", 3 * LEAFS_CHECKPOINT_INTERVAL));
+        std::fs::create_dir_all(&orphan).unwrap();
+        std::fs::write(orphan.join("config.json"), "").unwrap();
+
+        let checkpoint = db.commitment_checkpoint(0, U256::ZERO).unwrap().unwrap();
+        assert_eq!(checkpoint.stride_index, 2 * LEAFS_CHECKPOINT_INTERVAL);
+
+        let resumed = resume_machine_commitment(
+            &checkpoint,
+            U256::ZERO,
+            0,
+            constants::LOG2_UARCH_SPAN_TO_BARCH,
+            LOG2_STRIDE_COUNT,
+            &db,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(root_hashes(&resumed), root_hashes(&full));
+        assert!(db.commitment_checkpoint(0, U256::ZERO).unwrap().is_none());
+        assert!(!checkpoint.machine_path.exists());
+        assert!(!orphan.exists());
+    }
+}
@@ -5,22 +5,33 @@ use crate::{
     db::dispute_state_access::DisputeStateAccess,
     machine::{
         MachineCommitment, MachineInstance, build_machine_commitment,
-        build_machine_commitment_from_leafs, error::Result,
+        build_machine_commitment_from_leafs, error::Result, resume_machine_commitment,
     },
 };
 
 use alloy::primitives::U256;
-use log::trace;
+use cartesi_dave_merkle::Digest;
+use log::{trace, warn};
+use std::collections::HashMap;
+
+/// Commitments are cached per level, base cycle and stride.
+type CommitmentKey = (u64, U256, u64);
 
 pub struct MachineCommitmentBuilder {
     machine_path: String,
+    cache: HashMap<CommitmentKey, MachineCommitment>,
 }
 
 impl MachineCommitmentBuilder {
     pub fn new(machine_path: String) -> Self {
-        MachineCommitmentBuilder { machine_path }
+        MachineCommitmentBuilder {
+            machine_path,
+            cache: HashMap::new(),
+        }
     }
 
+    /// Returns the commitment for the tournament, from memory or the database if it was built
+    /// before, building it otherwise.
     pub fn build_commitment(
         &mut self,
         base_cycle: U256,
@@ -29,6 +40,67 @@ impl MachineCommitmentBuilder {
         log2_stride_count: u64,
         db: &DisputeStateAccess,
     ) -> Result<MachineCommitment> {
+        let key = (level, base_cycle, log2_stride);
+        if let Some(commitment) = self.cache.get(&key) {
+            return Ok(commitment.clone());
+        }
+
+        let commitment = match db.cached_commitment(level, base_cycle, log2_stride) {
+            Ok(Some(commitment)) => commitment,
+            Ok(None) => {
+                self.build_and_cache(base_cycle, level, log2_stride, log2_stride_count, db)?
+            }
+            Err(e) => {
+                warn!(
+                    "failed to load cached commitment of level {} at base cycle {}, rebuilding it: {}",
+                    level, base_cycle, e
+                );
+                self.build_and_cache(base_cycle, level, log2_stride, log2_stride_count, db)?
+            }
+        };
+
+        self.cache.insert(key, commitment.clone());
+        Ok(commitment)
+    }
+
+    fn build_and_cache(
+        &self,
+        base_cycle: U256,
+        level: u64,
+        log2_stride: u64,
+        log2_stride_count: u64,
+        db: &DisputeStateAccess,
+    ) -> Result<MachineCommitment> {
+        let commitment =
+            self.build_uncached(base_cycle, level, log2_stride, log2_stride_count, db)?;
+        db.cache_commitment(level, base_cycle, log2_stride, &commitment)?;
+        Ok(commitment)
+    }
+
+    fn build_uncached(
+        &self,
+        base_cycle: U256,
+        level: u64,
+        log2_stride: u64,
+        log2_stride_count: u64,
+        db: &DisputeStateAccess,
+    ) -> Result<MachineCommitment> {
+        // an interrupted build left partial leafs behind, finish it before reading the cache
+        if let Some(checkpoint) = db.commitment_checkpoint(level, base_cycle)? {
+            if let Some(leafs) = resume_machine_commitment(
+                &checkpoint,
+                base_cycle,
+                level,
+                log2_stride,
+                log2_stride_count,
+                db,
+            )? {
+                let initial_state = Digest::from(checkpoint.initial_state);
+                trace!("initial state for commitment: {}", initial_state);
+                return build_machine_commitment_from_leafs(leafs, initial_state);
+            }
+        }
+
         let mut machine =
             MachineInstance::new_rollups_advanced_until(&self.machine_path, base_cycle, db)?;
         let initial_state = machine.root_hash()?;
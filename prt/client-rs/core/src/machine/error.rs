@@ -1,6 +1,7 @@
 // (c) Cartesi and individual authors (see AUTHORS)
 // SPDX-License-Identifier: Apache-2.0 (see LICENSE)
 use crate::db::sql::error::DisputeStateAccessError;
+use cartesi_dave_arithmetic::MetaCycleError;
 use cartesi_machine::error::MachineError;
 use thiserror::Error;
 
@@ -18,11 +19,17 @@ pub enum MachineInstanceError {
         source: DisputeStateAccessError,
     },
 
+    #[error(transparent)]
+    MetaCycle(#[from] MetaCycleError),
+
     #[error("Invalid hex string")]
     InvalidHexString(#[from] hex::FromHexError),
 
     #[error("Invalid io string")]
     IOError(#[from] std::io::Error),
+
+    #[error("Step proof failed local verification: {0}")]
+    ProofVerification(String),
 }
 
 pub type Result<T> = std::result::Result<T, MachineInstanceError>;
@@ -1,23 +1,20 @@
-use crate::db::dispute_state_access::DisputeStateAccess;
-use crate::machine::constants::{
-    BARCH_SPAN_TO_INPUT, INPUT_SPAN_TO_EPOCH, LOG2_UARCH_SPAN_TO_BARCH, LOG2_UARCH_SPAN_TO_INPUT,
-    UARCH_SPAN_TO_BARCH,
-};
-use crate::machine::error::Result;
-use cartesi_dave_arithmetic as arithmetic;
+use crate::db::dispute_state_access::{CommitmentCheckpoint, DisputeStateAccess};
+use crate::machine::error::{MachineInstanceError, Result};
+use crate::machine::step_proof::StepProofReplay;
+use cartesi_dave_arithmetic::{self as arithmetic, MetaCycle};
 use cartesi_dave_merkle::Digest;
 use cartesi_machine::{
     cartesi_machine_sys,
     config::runtime::{HTIFRuntimeConfig, RuntimeConfig},
     machine::Machine,
+    types::Hash,
     types::access_proof::AccessLog,
     types::{LogType, cmio::CmioResponseReason},
 };
 use log::trace;
-use num_traits::{One, ToPrimitive};
 
 use alloy::primitives::U256;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 pub struct MachineState {
@@ -91,6 +88,21 @@ impl MachineInstance {
         })
     }
 
+    /// Loads a machine stored by an interrupted commitment build, restoring its position so the
+    /// build can continue from the checkpoint.
+    pub fn from_checkpoint(checkpoint: &CommitmentCheckpoint) -> Result<Self> {
+        let mut machine = Self::new_from_path(&checkpoint.machine_path.to_string_lossy())?;
+        machine.input_count = checkpoint.input_count;
+        machine.cycle = checkpoint.cycle;
+        machine.snapshot_path = checkpoint.revert_path.clone();
+
+        Ok(machine)
+    }
+
+    pub fn store(&mut self, path: &Path) -> Result<()> {
+        Ok(self.machine.store(path)?)
+    }
+
     /*
         pub fn take_snapshot(&mut self, base_cycle: u64, db: &DisputeStateAccess) -> Result<()> {
             let mask = arithmetic::max_uint(constants::LOG2_BARCH_SPAN_TO_INPUT);
@@ -133,18 +145,12 @@ impl MachineInstance {
     */
 
     pub fn advance_rollups(&mut self, meta_cycle: U256, db: &DisputeStateAccess) -> Result<()> {
-        assert!(self.is_yielded()?);
+        assert!(self.is_yielded()? || self.is_halted()?);
 
-        let input_count = (meta_cycle >> LOG2_UARCH_SPAN_TO_INPUT)
-            .to_u64()
-            .expect("input count too big to fit in u64");
-        let cycle = {
-            let c = (meta_cycle >> LOG2_UARCH_SPAN_TO_BARCH) & U256::from(BARCH_SPAN_TO_INPUT);
-            c.to_u64().expect("cycle too big to fit in u64")
-        };
-        let ucycle = (meta_cycle & U256::from(UARCH_SPAN_TO_BARCH))
-            .to_u64()
-            .expect("ucycle too big to fit in u64");
+        let meta_cycle = MetaCycle::try_from(meta_cycle)?;
+        let input_count = meta_cycle.input();
+        let cycle = meta_cycle.big_cycle();
+        let ucycle = meta_cycle.micro_cycle();
 
         let snapshot_path = db.work_path.join(format!("{}", self.root_hash()?.to_hex()));
         if !snapshot_path.exists() {
@@ -163,7 +169,6 @@ impl MachineInstance {
                     break;
                 }
             }
-            assert!(!self.is_halted()?);
 
             self.input_count += 1;
         }
@@ -186,18 +191,17 @@ impl MachineInstance {
         meta_cycle: U256,
         db: &DisputeStateAccess,
     ) -> Result<MachineInstance> {
-        let input_count = (meta_cycle >> LOG2_UARCH_SPAN_TO_INPUT).to_u64().unwrap();
-        assert!(input_count <= INPUT_SPAN_TO_EPOCH);
-
         let mut machine = MachineInstance::new_from_path(path)?;
-        assert!(machine.is_yielded()?);
+        assert!(machine.is_yielded()? || machine.is_halted()?);
 
         machine.advance_rollups(meta_cycle, db)?;
         Ok(machine)
     }
 
     pub fn feed_next_input(&mut self, db: &DisputeStateAccess) -> Result<()> {
-        assert!(self.is_yielded()?);
+        // a halted machine only steps, as long as there is no input to send it: the emulator
+        // refuses to send one, like the step library `CartesiStateTransition` runs
+        assert!(self.is_yielded()? || self.is_halted()?);
         let input = db.input(self.input_count)?;
         let root_hash = self.root_hash()?;
         let new_snapshot_path = db.work_path.join(format!("{}", root_hash.to_hex()));
@@ -291,9 +295,9 @@ impl MachineInstance {
         loop {
             self.machine.run(target_physical_cycle)?;
 
-            let halted = self.is_halted()?;
-            if halted {
-                panic!("run break with halt");
+            if self.is_halted()? {
+                trace!("run break with halt");
+                break;
             }
 
             if self.is_yielded()? {
@@ -407,7 +411,7 @@ impl MachineInstance {
         Ok(proof)
     }
 
-    fn encode_access_logs(logs: Vec<&AccessLog>) -> Vec<u8> {
+    pub(crate) fn encode_access_logs(logs: Vec<&AccessLog>) -> Vec<u8> {
         let mut encoded: Vec<Vec<u8>> = Vec::new();
 
         for log in logs.into_iter() {
@@ -445,68 +449,142 @@ impl MachineInstance {
         meta_cycle: U256,
         db: &DisputeStateAccess,
     ) -> Result<(Vec<u8>, Digest)> {
-        let input_mask = (U256::one() << LOG2_UARCH_SPAN_TO_INPUT) - U256::one();
-        let big_step_mask = UARCH_SPAN_TO_BARCH;
-
-        assert!(((meta_cycle >> LOG2_UARCH_SPAN_TO_INPUT) & !input_mask).is_zero());
-
-        let meta_cycle_u128 = meta_cycle
-            .to_u128()
-            .expect("meta_cycle is too large to fit in u128");
-        let input_count = (meta_cycle_u128 >> LOG2_UARCH_SPAN_TO_INPUT) as u64;
+        let position = MetaCycle::try_from(meta_cycle)?;
 
         let mut logs = Vec::new();
 
         let mut machine = MachineInstance::new_rollups_advanced_until(path, meta_cycle, db)?;
         assert_eq!(machine.state()?.root_hash, agree_hash);
 
-        if (meta_cycle & input_mask).is_zero() {
-            let input = db.input(input_count)?;
+        if position.is_input_start() {
+            let input = db.input(position.input())?;
             let mut da_proof;
             let cmio_log;
 
-            if let Some(input_bin) = input {
+            // like `CartesiStateTransition.transitionState`, any input there is gets sent, which
+            // fails for a halted machine on chain as it does here
+            if let Some(input_bin) = &input {
                 let write_checkpoint_proof = machine.prove_write_leaf(CHECKPOINT_ADDRESS)?;
+                let before_cmio = machine.root_hash()?;
                 cmio_log = machine.machine.log_send_cmio_response(
                     CmioResponseReason::Advance,
-                    &input_bin,
+                    input_bin,
                     LogType::default(),
                 )?;
+                let after_cmio = machine.root_hash()?;
+                Self::verify_transition("send cmio response", before_cmio, after_cmio, |b, a| {
+                    Machine::verify_send_cmio_response(
+                        CmioResponseReason::Advance,
+                        input_bin,
+                        b,
+                        &cmio_log,
+                        a,
+                    )
+                })?;
 
                 logs.push(&cmio_log);
-                da_proof = Self::encode_da(&input_bin);
+                da_proof = Self::encode_da(input_bin);
                 da_proof = [da_proof, write_checkpoint_proof].concat();
             } else {
                 da_proof = Self::encode_da(&[]);
             }
 
+            let before_step = machine.root_hash()?;
             let uarch_step_log = machine.machine.log_step_uarch(LogType::default())?;
+            let after_step = machine.root_hash()?;
+            Self::verify_transition("uarch step", before_step, after_step, |b, a| {
+                Machine::verify_step_uarch(b, &uarch_step_log, a)
+            })?;
             logs.push(&uarch_step_log);
 
-            let cmio_step_proof = Self::encode_access_logs(logs);
+            let cmio_step_proof = Self::encode_access_logs(logs.clone());
             let proof = [da_proof, cmio_step_proof].concat();
-            Ok((proof, machine.state()?.root_hash))
-        } else if ((meta_cycle_u128 + 1) & (big_step_mask as u128)) == 0 {
+
+            let mut replay = StepProofReplay::new(agree_hash, &proof);
+            let replayed_input = replay.input()?;
+            // `CartesiStateTransition.transitionState` checkpoints and sends any input there is
+            if let Some(input_bin) = &input {
+                if replayed_input != input_bin.as_slice() {
+                    return Err(MachineInstanceError::ProofVerification(
+                        "input in the proof differs from the one sent".to_owned(),
+                    ));
+                }
+                replay.write_leaf(CHECKPOINT_ADDRESS, &agree_hash.data())?;
+                replay.access_log("send cmio response", logs[0])?;
+            }
+            replay.access_log("uarch step", &uarch_step_log)?;
+            replay.finish(after_step)?;
+
+            Ok((proof, after_step))
+        } else if position.is_big_step_end() {
             assert!(machine.is_uarch_halted()?);
 
             let uarch_step_log = machine.machine.log_step_uarch(LogType::default())?;
+            let after_step = machine.root_hash()?;
+            Self::verify_transition("uarch step", agree_hash, after_step, |b, a| {
+                Machine::verify_step_uarch(b, &uarch_step_log, a)
+            })?;
             logs.push(&uarch_step_log);
+
             let ureset_log = machine.machine.log_reset_uarch(LogType::default())?;
+            let after_reset = machine.root_hash()?;
+            Self::verify_transition("uarch reset", after_step, after_reset, |b, a| {
+                Machine::verify_reset_uarch(b, &ureset_log, a)
+            })?;
             logs.push(&ureset_log);
+
             let step_reset_proof = Self::encode_access_logs(logs);
             let revert_proof = machine.prove_revert_if_needed()?;
+            let proof = [step_reset_proof, revert_proof].concat();
 
-            Ok((
-                [step_reset_proof, revert_proof].concat(),
-                machine.state()?.root_hash,
-            ))
+            // as in `CmioStateTransition.revertIfNeeded`, a rejected input goes back to the state
+            // before it was sent: the snapshot taken then, whose hash is in the checkpoint
+            if machine.is_yielded()? {
+                machine.revert_if_needed()?;
+            }
+            let next_hash = machine.root_hash()?;
+
+            let mut replay = StepProofReplay::new(agree_hash, &proof);
+            replay.access_log("uarch step", &uarch_step_log)?;
+            replay.access_log("uarch reset", &ureset_log)?;
+            replay.revert_if_needed(
+                Machine::reg_address(cartesi_machine_sys::CM_REG_IFLAGS_Y)?,
+                Machine::reg_address(cartesi_machine_sys::CM_REG_HTIF_TOHOST)?,
+                CHECKPOINT_ADDRESS,
+            )?;
+            replay.finish(next_hash)?;
+
+            Ok((proof, next_hash))
         } else {
             let uarch_step_log = machine.machine.log_step_uarch(LogType::default())?;
+            let after_step = machine.root_hash()?;
+            Self::verify_transition("uarch step", agree_hash, after_step, |b, a| {
+                Machine::verify_step_uarch(b, &uarch_step_log, a)
+            })?;
             logs.push(&uarch_step_log);
-            Ok((Self::encode_access_logs(logs), machine.state()?.root_hash))
+
+            let proof = Self::encode_access_logs(logs);
+            let mut replay = StepProofReplay::new(agree_hash, &proof);
+            replay.access_log("uarch step", &uarch_step_log)?;
+            replay.finish(after_step)?;
+
+            Ok((proof, after_step))
         }
     }
 
+    /// Replays a logged state transition with the machine's own verifier. This checks the logs,
+    /// not their encoding, which [StepProofReplay] checks as the chain would.
+    fn verify_transition<F>(name: &str, before: Digest, after: Digest, verify: F) -> Result<()>
+    where
+        F: FnOnce(&Hash, &Hash) -> cartesi_machine::error::MachineResult<()>,
+    {
+        verify(&before.into(), &after.into()).map_err(|e| {
+            MachineInstanceError::ProofVerification(format!(
+                "{name} from {before} to {after} rejected: {e}"
+            ))
+        })
+    }
+
     pub fn get_logs(
         path: &str,
         agree_hash: Digest,
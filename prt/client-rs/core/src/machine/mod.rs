@@ -13,4 +13,6 @@ pub use commitment::*;
 mod commitment_builder;
 pub use commitment_builder::*;
 
+mod step_proof;
+
 pub mod error;
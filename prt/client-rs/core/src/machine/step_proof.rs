@@ -0,0 +1,482 @@
+//! Replays an encoded step proof the way `CartesiStateTransition.transitionState` consumes it, so
+//! a proof the chain would reject is caught before it is sent.
+//!
+//! Every access in the proof is a merkle proof of a leaf of the machine state: its 32 byte word
+//! (or the hash of a larger region), followed by its siblings from the leaf up to the root. Reads
+//! must match the current state hash, and writes replace it with the hash rolled up from the
+//! written data.
+
+use cartesi_dave_merkle::Digest;
+use cartesi_machine::types::access_proof::{Access, AccessLog, AccessType};
+
+use crate::machine::error::{MachineInstanceError, Result};
+
+const HASH_SIZE: usize = 32;
+const LOG2_LEAF_SIZE: u64 = 5;
+const LOG2_MEMORY_SIZE: u64 = 64;
+
+pub struct StepProofReplay<'a> {
+    root_hash: Digest,
+    buffer: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> StepProofReplay<'a> {
+    pub fn new(root_hash: Digest, buffer: &'a [u8]) -> Self {
+        Self {
+            root_hash,
+            buffer,
+            offset: 0,
+        }
+    }
+
+    /// Consumes the length prefixed input at the start of an input proof.
+    pub fn input(&mut self) -> Result<&'a [u8]> {
+        let length = u64::from_be_bytes(self.take(8)?.try_into().unwrap());
+        let length = usize::try_from(length)
+            .map_err(|_| error(format!("input length {length} is too large")))?;
+        self.take(length)
+    }
+
+    /// Replays the accesses of `log`, in the order the emulator made them.
+    pub fn access_log(&mut self, name: &str, log: &AccessLog) -> Result<()> {
+        for (i, access) in log.accesses.iter().enumerate() {
+            self.access(access).map_err(|e| match e {
+                MachineInstanceError::ProofVerification(reason) => error(format!(
+                    "{name} access {i} at 0x{:x}: {reason}",
+                    access.address
+                )),
+                e => e,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Consumes a word read, returning its value.
+    pub fn read_word(&mut self, address: u64) -> Result<u64> {
+        let leaf = self.read_leaf_data(address)?;
+        let offset = (address & 0x18) as usize;
+        Ok(u64::from_le_bytes(
+            leaf[offset..offset + 8].try_into().unwrap(),
+        ))
+    }
+
+    /// Consumes a leaf read that also carries the hash of the leaf, returning its data.
+    pub fn read_leaf(&mut self, address: u64) -> Result<[u8; HASH_SIZE]> {
+        let aligned_address = address & !0x1F;
+        let leaf: [u8; HASH_SIZE] = self.take(HASH_SIZE)?.try_into().unwrap();
+        let leaf_hash = self.take_hash()?;
+        if leaf_hash != Digest::from_data(&leaf) {
+            return Err(error(format!(
+                "leaf at 0x{aligned_address:x} doesn't match its hash"
+            )));
+        }
+        self.read(aligned_address, LOG2_LEAF_SIZE, leaf_hash)?;
+        Ok(leaf)
+    }
+
+    /// Consumes the proof of a leaf write, replacing the leaf with `leaf`.
+    pub fn write_leaf(&mut self, address: u64, leaf: &[u8; HASH_SIZE]) -> Result<()> {
+        let read_hash = self.take_hash()?;
+        let siblings = self.read(address, LOG2_LEAF_SIZE, read_hash)?;
+        self.root_hash = roll_up(address, LOG2_LEAF_SIZE, Digest::from_data(leaf), &siblings);
+        Ok(())
+    }
+
+    /// Like `CmioStateTransition.revertIfNeeded`: a machine that yielded rejecting its input goes
+    /// back to the state in the checkpoint written before the input was sent.
+    pub fn revert_if_needed(
+        &mut self,
+        iflags_y_address: u64,
+        to_host_address: u64,
+        checkpoint_address: u64,
+    ) -> Result<()> {
+        if self.read_word(iflags_y_address)? == 0 {
+            return Ok(());
+        }
+
+        let reason = (self.read_word(to_host_address)? >> 32) as u16;
+        if reason != cartesi_machine::constants::cmio::tohost::manual::RX_ACCEPTED {
+            self.root_hash = self.read_leaf(checkpoint_address)?.into();
+        }
+        Ok(())
+    }
+
+    /// Checks the whole proof was consumed, ending at `expected`.
+    pub fn finish(self, expected: Digest) -> Result<()> {
+        if self.offset != self.buffer.len() {
+            return Err(error(format!(
+                "{} unused bytes at the end",
+                self.buffer.len() - self.offset
+            )));
+        }
+        if self.root_hash != expected {
+            return Err(error(format!(
+                "ends at {} instead of {expected}",
+                self.root_hash
+            )));
+        }
+        Ok(())
+    }
+
+    fn access(&mut self, access: &Access) -> Result<()> {
+        // words are proven by the whole leaf that holds them
+        let log2_size = access.log2_size.max(LOG2_LEAF_SIZE);
+        let address = access.address & !((1 << log2_size) - 1);
+        let leaf = if access.log2_size < LOG2_LEAF_SIZE {
+            Some(self.take(HASH_SIZE)?)
+        } else {
+            None
+        };
+        let read_hash = match leaf {
+            Some(leaf) => Digest::from_data(leaf),
+            None => self.take_hash()?,
+        };
+        let siblings = self.read(address, log2_size, read_hash)?;
+
+        if let AccessType::Write = access.r#type {
+            let written_hash = match (leaf, &access.written) {
+                // the emulator logs either the word or the whole leaf it wrote
+                (Some(leaf), Some(written)) => {
+                    let offset = (access.address - address) as usize;
+                    let written = if written.len() == HASH_SIZE {
+                        &written[offset..offset + (1 << access.log2_size)]
+                    } else {
+                        written.as_slice()
+                    };
+                    let mut leaf = leaf.to_vec();
+                    leaf.get_mut(offset..offset + written.len())
+                        .ok_or_else(|| error("written data outside its leaf".to_owned()))?
+                        .copy_from_slice(written);
+                    Digest::from_data(&leaf)
+                }
+                (Some(_), None) => return Err(error("write with no written data".to_owned())),
+                (None, _) => access
+                    .written_hash
+                    .ok_or_else(|| error("write with no written hash".to_owned()))?
+                    .into(),
+            };
+            self.root_hash = roll_up(address, log2_size, written_hash, &siblings);
+        }
+        Ok(())
+    }
+
+    fn read_leaf_data(&mut self, address: u64) -> Result<[u8; HASH_SIZE]> {
+        let aligned_address = address & !0x1F;
+        let leaf: [u8; HASH_SIZE] = self.take(HASH_SIZE)?.try_into().unwrap();
+        self.read(aligned_address, LOG2_LEAF_SIZE, Digest::from_data(&leaf))?;
+        Ok(leaf)
+    }
+
+    /// Consumes the siblings of a read of `read_hash`, checking they lead to the current state.
+    fn read(&mut self, address: u64, log2_size: u64, read_hash: Digest) -> Result<Vec<Digest>> {
+        let siblings = (log2_size..LOG2_MEMORY_SIZE)
+            .map(|_| self.take_hash())
+            .collect::<Result<Vec<_>>>()?;
+        let root_hash = roll_up(address, log2_size, read_hash, &siblings);
+        if root_hash != self.root_hash {
+            return Err(error(format!(
+                "read at 0x{address:x} proves {root_hash} instead of {}",
+                self.root_hash
+            )));
+        }
+        Ok(siblings)
+    }
+
+    fn take_hash(&mut self) -> Result<Digest> {
+        Ok(Digest::from_digest(self.take(HASH_SIZE)?).unwrap())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8]> {
+        let end = self
+            .offset
+            .checked_add(length)
+            .filter(|end| *end <= self.buffer.len())
+            .ok_or_else(|| error(format!("truncated at byte {}", self.offset)))?;
+        let data = &self.buffer[self.offset..end];
+        self.offset = end;
+        Ok(data)
+    }
+}
+
+fn roll_up(address: u64, log2_size: u64, mut hash: Digest, siblings: &[Digest]) -> Digest {
+    for (log2, sibling) in (log2_size..).zip(siblings) {
+        hash = if (address >> log2) & 1 == 0 {
+            hash.join(sibling)
+        } else {
+            sibling.join(&hash)
+        };
+    }
+    hash
+}
+
+fn error(message: String) -> MachineInstanceError {
+    MachineInstanceError::ProofVerification(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::machine::MachineInstance;
+    use cartesi_machine::{constants::cmio::tohost::manual, types::access_proof::AccessLogType};
+    use std::collections::BTreeMap;
+
+    const CHECKPOINT_ADDRESS: u64 = 0x7ffff000;
+    const IFLAGS_Y_ADDRESS: u64 = 0x1008;
+    const TO_HOST_ADDRESS: u64 = 0x2000;
+
+    /// Machine memory where everything but a few leafs is zero.
+    #[derive(Default)]
+    struct Memory {
+        leafs: BTreeMap<u64, [u8; HASH_SIZE]>,
+    }
+
+    impl Memory {
+        fn write_word(&mut self, address: u64, value: u64) {
+            let leaf = self.leafs.entry(address & !0x1F).or_default();
+            let offset = (address & 0x18) as usize;
+            leaf[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        }
+
+        fn leaf(&self, address: u64) -> [u8; HASH_SIZE] {
+            self.leafs.get(&address).copied().unwrap_or_default()
+        }
+
+        fn hash(&self, address: u64, log2_size: u64) -> Digest {
+            let last = address | u64::MAX.checked_shr(64 - log2_size as u32).unwrap_or(0);
+            if self.leafs.range(address..=last).next().is_none() {
+                return (LOG2_LEAF_SIZE..log2_size)
+                    .fold(Digest::from_data(&[0; HASH_SIZE]), |h, _| h.join(&h));
+            }
+            if log2_size == LOG2_LEAF_SIZE {
+                return Digest::from_data(&self.leaf(address));
+            }
+            let half = 1 << (log2_size - 1);
+            self.hash(address, log2_size - 1)
+                .join(&self.hash(address + half, log2_size - 1))
+        }
+
+        fn root_hash(&self) -> Digest {
+            self.hash(0, LOG2_MEMORY_SIZE - 1)
+                .join(&self.hash(1 << (LOG2_MEMORY_SIZE - 1), LOG2_MEMORY_SIZE - 1))
+        }
+
+        fn siblings(&self, address: u64) -> Vec<Digest> {
+            (LOG2_LEAF_SIZE..LOG2_MEMORY_SIZE)
+                .map(|log2| self.hash(((address >> log2) ^ 1) << log2, log2))
+                .collect()
+        }
+
+        fn proof(&self, address: u64) -> Vec<u8> {
+            self.siblings(address)
+                .iter()
+                .flat_map(|h| h.data())
+                .collect()
+        }
+
+        /// Encoded like `MachineInstance::prove_read_word`.
+        fn prove_read_word(&self, address: u64) -> Vec<u8> {
+            let aligned_address = address & !0x1F;
+            [
+                self.leaf(aligned_address).to_vec(),
+                self.proof(aligned_address),
+            ]
+            .concat()
+        }
+
+        /// Encoded like `MachineInstance::prove_read_leaf`.
+        fn prove_read_leaf(&self, address: u64) -> Vec<u8> {
+            let leaf = self.leaf(address);
+            [
+                leaf.to_vec(),
+                Digest::from_data(&leaf).data().to_vec(),
+                self.proof(address),
+            ]
+            .concat()
+        }
+
+        fn access(&self, r#type: AccessType, address: u64) -> Access {
+            let leaf = self.leaf(address & !0x1F);
+            Access {
+                r#type,
+                address,
+                log2_size: 3,
+                read_hash: Digest::from_data(&leaf).into(),
+                read: Some(leaf.to_vec()),
+                written_hash: None,
+                written: None,
+                sibling_hashes: Some(
+                    self.siblings(address & !0x1F)
+                        .into_iter()
+                        .map(Into::into)
+                        .collect(),
+                ),
+            }
+        }
+    }
+
+    fn access_log(accesses: Vec<Access>) -> AccessLog {
+        AccessLog {
+            log_type: AccessLogType::default(),
+            accesses,
+            notes: None,
+            brackets: None,
+        }
+    }
+
+    /// Reads a word, writes another and reads it back, returning the log and the states before
+    /// and after.
+    fn read_write_log() -> (AccessLog, Digest, Digest) {
+        let mut memory = Memory::default();
+        memory.write_word(0x100, 7);
+        memory.write_word(0x4000, 11);
+        let before = memory.root_hash();
+
+        let read = memory.access(AccessType::Read, 0x100);
+        let mut write = memory.access(AccessType::Write, 0x4008);
+        memory.write_word(0x4008, 13);
+        let written = memory.leaf(0x4000);
+        write.written = Some(written.to_vec());
+        write.written_hash = Some(Digest::from_data(&written).into());
+        let read_back = memory.access(AccessType::Read, 0x4008);
+
+        (
+            access_log(vec![read, write, read_back]),
+            before,
+            memory.root_hash(),
+        )
+    }
+
+    fn replay(before: Digest, proof: &[u8], log: &AccessLog, after: Digest) -> Result<()> {
+        let mut replay = StepProofReplay::new(before, proof);
+        replay.access_log("test", log)?;
+        replay.finish(after)
+    }
+
+    #[test]
+    fn test_replay_access_log() {
+        let (log, before, after) = read_write_log();
+        let proof = MachineInstance::encode_access_logs(vec![&log]);
+
+        replay(before, &proof, &log, after).unwrap();
+        // the write is checked against where the proof leads
+        assert!(replay(before, &proof, &log, before).is_err());
+    }
+
+    #[test]
+    fn test_replay_corrupted_encoding() {
+        let (log, before, after) = read_write_log();
+        let proof = MachineInstance::encode_access_logs(vec![&log]);
+
+        // a flipped bit anywhere, in a word or in a sibling
+        for i in [0, HASH_SIZE + 5, proof.len() / 2, proof.len() - 1] {
+            let mut corrupted = proof.clone();
+            corrupted[i] ^= 1;
+            assert!(matches!(
+                replay(before, &corrupted, &log, after),
+                Err(MachineInstanceError::ProofVerification(_))
+            ));
+        }
+
+        // a missing sibling
+        let truncated = &proof[..proof.len() - HASH_SIZE];
+        assert!(replay(before, truncated, &log, after).is_err());
+
+        // trailing data the chain would ignore hides a shifted encoding
+        let extended = [proof.as_slice(), &[0]].concat();
+        assert!(replay(before, &extended, &log, after).is_err());
+
+        // siblings from the root down instead of from the leaf up
+        let mut reversed = log.clone();
+        for access in reversed.accesses.iter_mut() {
+            access.sibling_hashes.as_mut().unwrap().reverse();
+        }
+        let reversed = MachineInstance::encode_access_logs(vec![&reversed]);
+        assert!(replay(before, &reversed, &log, after).is_err());
+    }
+
+    #[test]
+    fn test_replay_input() {
+        let mut memory = Memory::default();
+        memory.write_word(CHECKPOINT_ADDRESS, 3);
+        let before = memory.root_hash();
+        let input = [1, 2, 3];
+
+        // encoded like `MachineInstance::encode_da` and `MachineInstance::prove_write_leaf`
+        let proof = [
+            (input.len() as u64).to_be_bytes().to_vec(),
+            input.to_vec(),
+            Digest::from_data(&memory.leaf(CHECKPOINT_ADDRESS))
+                .data()
+                .to_vec(),
+            memory.proof(CHECKPOINT_ADDRESS),
+        ]
+        .concat();
+        memory.leafs.insert(CHECKPOINT_ADDRESS, before.data());
+
+        let mut replay = StepProofReplay::new(before, &proof);
+        assert_eq!(replay.input().unwrap(), input);
+        replay
+            .write_leaf(CHECKPOINT_ADDRESS, &before.data())
+            .unwrap();
+        replay.finish(memory.root_hash()).unwrap();
+
+        let mut replay = StepProofReplay::new(before, &proof[..8 + input.len() - 1]);
+        assert!(replay.input().is_err());
+    }
+
+    fn revert_memory(yielded: bool, reason: u16, checkpoint: Digest) -> Memory {
+        let mut memory = Memory::default();
+        memory.write_word(IFLAGS_Y_ADDRESS, yielded as u64);
+        memory.write_word(TO_HOST_ADDRESS, (reason as u64) << 32);
+        memory.leafs.insert(CHECKPOINT_ADDRESS, checkpoint.data());
+        memory
+    }
+
+    fn replay_revert(memory: &Memory, proof: &[u8], after: Digest) -> Result<()> {
+        let mut replay = StepProofReplay::new(memory.root_hash(), proof);
+        replay.revert_if_needed(IFLAGS_Y_ADDRESS, TO_HOST_ADDRESS, CHECKPOINT_ADDRESS)?;
+        replay.finish(after)
+    }
+
+    #[test]
+    fn test_replay_revert_if_needed() {
+        let checkpoint = Digest::from_data(b"state before the input");
+
+        // a rejected input goes back to the state in the checkpoint
+        let memory = revert_memory(true, manual::RX_REJECTED, checkpoint);
+        let proof = [
+            memory.prove_read_word(IFLAGS_Y_ADDRESS),
+            memory.prove_read_word(TO_HOST_ADDRESS),
+            memory.prove_read_leaf(CHECKPOINT_ADDRESS),
+        ]
+        .concat();
+        replay_revert(&memory, &proof, checkpoint).unwrap();
+        assert!(replay_revert(&memory, &proof, memory.root_hash()).is_err());
+
+        // an accepted one stays
+        let memory = revert_memory(true, manual::RX_ACCEPTED, checkpoint);
+        let proof = [
+            memory.prove_read_word(IFLAGS_Y_ADDRESS),
+            memory.prove_read_word(TO_HOST_ADDRESS),
+        ]
+        .concat();
+        replay_revert(&memory, &proof, memory.root_hash()).unwrap();
+
+        // as does a machine that didn't yield
+        let memory = revert_memory(false, manual::RX_REJECTED, checkpoint);
+        let proof = memory.prove_read_word(IFLAGS_Y_ADDRESS);
+        replay_revert(&memory, &proof, memory.root_hash()).unwrap();
+
+        // a checkpoint leaf that doesn't match its hash
+        let memory = revert_memory(true, manual::RX_REJECTED, checkpoint);
+        let mut proof = [
+            memory.prove_read_word(IFLAGS_Y_ADDRESS),
+            memory.prove_read_word(TO_HOST_ADDRESS),
+            memory.prove_read_leaf(CHECKPOINT_ADDRESS),
+        ]
+        .concat();
+        proof[2 * (HASH_SIZE * 60)] ^= 1;
+        assert!(replay_revert(&memory, &proof, checkpoint).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+//! Persists every transaction the player attempts in the dispute database: the funds it moved go
+//! to the ledger, and the call, its arguments and the decision behind it go to the audit log.
+
+use alloy::primitives::Address;
+use serde_json::{Value, json};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    db::dispute_state_access::{AuditEntry, DisputeStateAccess},
+    machine::MachineCommitment,
+    strategy::error::Result,
+    tournament::{MatchID, MatchState, TxOutcome},
+};
+
+/// A call to a tournament the player decided to make.
+pub struct Action {
+    pub tournament: Address,
+    pub tx_call: &'static str,
+    pub arguments: Value,
+    pub context: Value,
+}
+
+/// Records the `result` of sending `action`, and returns it.
+pub(crate) fn record_transaction(
+    db: &DisputeStateAccess,
+    action: Action,
+    result: Result<TxOutcome>,
+) -> Result<TxOutcome> {
+    let (tx_hash, reverted, revert_data, error) = match &result {
+        Ok(outcome) => (
+            outcome.tx_hash,
+            outcome.reverted,
+            outcome.revert_data.clone(),
+            None,
+        ),
+        Err(e) => (None, false, None, Some(e.to_string())),
+    };
+
+    let timestamp = now();
+    db.insert_audit_entry(&AuditEntry {
+        timestamp,
+        tournament: action.tournament,
+        tx_call: action.tx_call.to_owned(),
+        arguments: action.arguments,
+        context: action.context,
+        tx_hash,
+        reverted,
+        revert_data,
+        error,
+    })?;
+
+    let outcome = result?;
+    db.insert_ledger_entry(&outcome.ledger_entry(action.tournament, timestamp))?;
+    Ok(outcome)
+}
+
+/// Decision context shared by all match actions.
+pub(crate) fn match_context(
+    match_state: &MatchState,
+    commitment: &MachineCommitment,
+    level: u64,
+) -> Value {
+    json!({
+        "level": level,
+        "commitment": commitment.merkle.root_hash().to_string(),
+        "match_id": match_id(&match_state.id),
+        "match_height": match_state.current_height,
+        "other_parent": match_state.other_parent.to_string(),
+        "left_node": match_state.left_node.to_string(),
+        "right_node": match_state.right_node.to_string(),
+        "running_leaf_position": match_state.running_leaf_position.to_string(),
+    })
+}
+
+pub(crate) fn match_id(id: &MatchID) -> Value {
+    json!({
+        "commitment_one": id.commitment_one.to_string(),
+        "commitment_two": id.commitment_two.to_string(),
+    })
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
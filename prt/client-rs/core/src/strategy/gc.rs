@@ -1,10 +1,15 @@
 use ::log::debug;
 use alloy::primitives::Address;
 use async_recursion::async_recursion;
+use serde_json::json;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use crate::strategy::error::Result;
+use crate::db::dispute_state_access::DisputeStateAccess;
+use crate::strategy::{
+    audit::{Action, match_id, record_transaction},
+    error::Result,
+};
 use crate::tournament::{ArenaSender, MatchState, TournamentStateMap};
 
 pub struct GarbageCollector<AS: ArenaSender> {
@@ -20,8 +25,12 @@ impl<AS: ArenaSender> GarbageCollector<AS> {
         }
     }
 
-    pub async fn react(&self, tournament_states: &TournamentStateMap) -> Result<()> {
-        self.react_tournament(self.root_tournamet, tournament_states)
+    pub async fn react(
+        &self,
+        tournament_states: &TournamentStateMap,
+        db: &DisputeStateAccess,
+    ) -> Result<()> {
+        self.react_tournament(self.root_tournamet, tournament_states, db)
             .await
     }
 
@@ -30,13 +39,14 @@ impl<AS: ArenaSender> GarbageCollector<AS> {
         &self,
         tournament_address: Address,
         tournament_states: &TournamentStateMap,
+        db: &DisputeStateAccess,
     ) -> Result<()> {
         let tournament_state = tournament_states
             .get(&tournament_address)
             .expect("tournament state not found");
 
         for m in tournament_state.matches.iter() {
-            self.react_match(m, tournament_states, tournament_address)
+            self.react_match(m, tournament_states, tournament_address, db)
                 .await?;
 
             let status_1 = tournament_state
@@ -60,11 +70,23 @@ impl<AS: ArenaSender> GarbageCollector<AS> {
                     tournament_state.level
                 );
 
-                self.arena_sender
+                let result = self
+                    .arena_sender
                     .lock()
                     .await
                     .eliminate_match(tournament_address, m.id)
-                    .await?;
+                    .await;
+                let action = Action {
+                    tournament: tournament_address,
+                    tx_call: "eliminateMatchByTimeout",
+                    arguments: json!({ "match_id": match_id(&m.id) }),
+                    context: json!({
+                        "level": tournament_state.level,
+                        "clock_one": status_1.clock.to_string(),
+                        "clock_two": status_2.clock.to_string(),
+                    }),
+                };
+                record_transaction(db, action, result)?;
             }
         }
         Ok(())
@@ -76,6 +98,7 @@ impl<AS: ArenaSender> GarbageCollector<AS> {
         match_state: &MatchState,
         tournament_states: &TournamentStateMap,
         tournament_address: Address,
+        db: &DisputeStateAccess,
     ) -> Result<()> {
         if let Some(inner_tournament_address) = match_state.inner_tournament {
             let inner_tournament_state = tournament_states
@@ -87,13 +110,26 @@ impl<AS: ArenaSender> GarbageCollector<AS> {
                     "eliminate inner tournament {inner_tournament_address} of level {}, child of tournament {tournament_address}",
                     inner_tournament_state.level
                 );
-                self.arena_sender
+                let result = self
+                    .arena_sender
                     .lock()
                     .await
                     .eliminate_inner_tournament(tournament_address, inner_tournament_address)
-                    .await?;
+                    .await;
+                let action = Action {
+                    tournament: tournament_address,
+                    tx_call: "eliminateInnerTournament",
+                    arguments: json!({
+                        "inner_tournament": inner_tournament_address.to_string(),
+                    }),
+                    context: json!({
+                        "level": inner_tournament_state.level,
+                        "match_id": match_id(&match_state.id),
+                    }),
+                };
+                record_transaction(db, action, result)?;
             } else {
-                self.react_tournament(inner_tournament_address, tournament_states)
+                self.react_tournament(inner_tournament_address, tournament_states, db)
                     .await?;
             }
         }
@@ -1,6 +1,11 @@
 //! This module defines the struct [Player] that is responsible for reacting to the states
-//! of tournaments; and the struct [GarbageCollector] that is responsible for collecting finished matches
+//! of tournaments; the struct [GarbageCollector] that is responsible for collecting finished matches;
+//! the struct [Scheduler] that is responsible for deciding when to react next; and the
+//! functions in [replay] that rebuild the commitments of a dispute offline
 
+pub mod audit;
 pub mod error;
 pub mod gc;
 pub mod player;
+pub mod replay;
+pub mod scheduler;
@@ -1,24 +1,41 @@
 use std::collections::HashMap;
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::sync::Mutex;
 
 use crate::strategy::error::Result;
-use ::log::{debug, error, info};
+use ::log::{debug, error, info, warn};
 use alloy::{primitives::Address, providers::DynProvider};
 use async_recursion::async_recursion;
 use num_traits::One;
 use ruint::aliases::U256;
 
 use crate::{
-    db::dispute_state_access::{DisputeStateAccess, Input, Leaf},
+    db::{
+        dispute_bundle::DisputeBundle,
+        dispute_state_access::{
+            DisputeStateAccess, Input, Leaf, LedgerSummary, RecordedCommitment,
+        },
+    },
     machine::{MachineCommitment, MachineCommitmentBuilder, MachineInstance},
-    strategy::gc::GarbageCollector,
+    strategy::{
+        audit::{self, Action, record_transaction},
+        gc::GarbageCollector,
+        scheduler::Deadline,
+    },
     tournament::{
         ArenaSender, CommitmentState, MatchState, StateReader, TournamentState, TournamentStateMap,
         TournamentWinner,
     },
 };
 use cartesi_dave_merkle::{Digest, MerkleProof};
+use serde_json::json;
+
+/// Number of transactions the signer should be able to pay for, on top of any bond, to see a
+/// dispute through.
+const BALANCE_RESERVE_TXS: u64 = 32;
 
 #[derive(Debug, PartialEq)]
 pub enum PlayerTournamentResult {
@@ -33,8 +50,11 @@ pub struct Player<AS: ArenaSender> {
     machine_path: String,
     commitment_builder: MachineCommitmentBuilder,
     root_tournament: Address,
+    block_created_number: u64,
     reader: StateReader,
     gc: GarbageCollector<AS>,
+    deadlines: Vec<Deadline>,
+    epoch_balance_checked: bool,
 }
 
 impl<AS: ArenaSender> Player<AS> {
@@ -63,19 +83,99 @@ impl<AS: ArenaSender> Player<AS> {
             machine_path,
             commitment_builder,
             root_tournament,
+            block_created_number,
             reader,
             gc,
+            deadlines: Vec::new(),
+            epoch_balance_checked: false,
         })
     }
 
     pub async fn react(&mut self) -> Result<PlayerTournamentResult> {
         let tournament_states = self.reader.fetch_from_root(self.root_tournament).await?;
 
-        self.gc.react(&tournament_states).await?;
+        self.deadlines.clear();
+        self.check_epoch_balance().await;
+        self.check_balance(U256::ZERO).await;
+        self.gc.react(&tournament_states, &self.db).await?;
         self.react_tournament(None, self.root_tournament, &tournament_states)
             .await
     }
 
+    /// Totals of the bonds, gas and refunds of this dispute, or of a single tournament.
+    pub fn ledger_summary(&self, tournament: Option<Address>) -> Result<LedgerSummary> {
+        Ok(self.db.ledger_summary(tournament)?)
+    }
+
+    /// Writes the audit log of this dispute as JSON to `path`.
+    pub fn export_audit_log(&self, path: &Path) -> Result<()> {
+        Ok(self.db.export_audit_log(path)?)
+    }
+
+    /// Writes a [DisputeBundle] of this dispute as JSON to `path`, to be replayed offline.
+    pub fn export_bundle(&self, path: &Path) -> Result<()> {
+        let machine_hash = MachineInstance::new_from_path(&self.machine_path)?.root_hash()?;
+        Ok(DisputeBundle::export(
+            &self.db,
+            self.root_tournament,
+            self.block_created_number,
+            machine_hash,
+        )?
+        .write(path)?)
+    }
+
+    /// Warns, once per epoch, if the signer can't even afford the bond of its root tournament.
+    async fn check_epoch_balance(&mut self) {
+        if self.epoch_balance_checked {
+            return;
+        }
+        self.epoch_balance_checked = true;
+
+        let bond_value = self
+            .arena_sender
+            .lock()
+            .await
+            .bond_value(self.root_tournament)
+            .await;
+        match bond_value {
+            Ok(bond_value) => self.check_balance(bond_value).await,
+            Err(e) => warn!(
+                "could not get the bond of tournament {}: {e}",
+                self.root_tournament
+            ),
+        }
+    }
+
+    /// Warns if the signer can't afford `required` plus the gas of the rest of the dispute,
+    /// estimated from the transactions sent so far. Failing to check is only warned about too.
+    async fn check_balance(&self, required: U256) {
+        if let Err(e) = self.try_check_balance(required).await {
+            warn!("could not check the signer balance: {e}");
+        }
+    }
+
+    async fn try_check_balance(&self, required: U256) -> Result<()> {
+        let gas_reserve =
+            self.db.ledger_summary(None)?.average_gas_cost() * U256::from(BALANCE_RESERVE_TXS);
+        if required.is_zero() && gas_reserve.is_zero() {
+            return Ok(());
+        }
+
+        let balance = self.arena_sender.lock().await.balance().await?;
+        if balance < required + gas_reserve {
+            warn!(
+                "signer balance of {} wei may not be enough to finish the dispute, {} wei needed plus an estimated {} wei for gas",
+                balance, required, gas_reserve
+            );
+        }
+        Ok(())
+    }
+
+    /// Deadlines of the ticking clocks observed in the last reaction.
+    pub fn deadlines(&self) -> &[Deadline] {
+        &self.deadlines
+    }
+
     #[async_recursion]
     async fn react_tournament<'a>(
         &mut self,
@@ -94,6 +194,13 @@ impl<AS: ArenaSender> Player<AS> {
             tournament_state.log2_stride_count,
             &self.db,
         )?;
+        self.db.insert_commitment(&RecordedCommitment {
+            level: tournament_state.level,
+            base_cycle: tournament_state.base_cycle,
+            log2_stride: tournament_state.log2_stride,
+            log2_stride_count: tournament_state.log2_stride_count,
+            root_hash: commitment.merkle.root_hash().into(),
+        })?;
 
         if let Some(winner) = &tournament_state.winner {
             match winner {
@@ -127,18 +234,38 @@ impl<AS: ArenaSender> Player<AS> {
                                     .merkle
                                     .subtrees()
                                     .expect("merkle tree should have subtrees");
-                                self.arena_sender
+                                let parent = tournament_state
+                                    .parent
+                                    .expect("parent tournament state not found");
+                                let result = self
+                                    .arena_sender
                                     .lock()
                                     .await
                                     .win_inner_match(
-                                        tournament_state
-                                            .parent
-                                            .expect("parent tournament state not found"),
+                                        parent,
                                         tournament_state.address,
                                         left.root_hash(),
                                         right.root_hash(),
                                     )
-                                    .await?;
+                                    .await;
+                                record_transaction(
+                                    &self.db,
+                                    Action {
+                                        tournament: parent,
+                                        tx_call: "winInnerTournament",
+                                        arguments: json!({
+                                            "child_tournament": tournament_state.address.to_string(),
+                                            "left_node": left.root_hash().to_string(),
+                                            "right_node": right.root_hash().to_string(),
+                                        }),
+                                        context: json!({
+                                            "level": tournament_state.level,
+                                            "commitment": old_commitment.merkle.root_hash().to_string(),
+                                            "inner_commitment": commitment.merkle.root_hash().to_string(),
+                                        }),
+                                    },
+                                    result,
+                                )?;
 
                                 return Ok(PlayerTournamentResult::TournamentRunning);
                             }
@@ -157,6 +284,12 @@ impl<AS: ArenaSender> Player<AS> {
         match commitment_state {
             Some(c) => {
                 info!("{}", c.clock);
+                self.deadlines.extend(Deadline::from_clock(
+                    tournament_state.address,
+                    commitment.merkle.root_hash(),
+                    true,
+                    &c.clock,
+                ));
                 if let Some(m) = c.latest_match {
                     let match_state = tournament_state
                         .matches
@@ -206,8 +339,10 @@ impl<AS: ArenaSender> Player<AS> {
             .await
             .bond_value(tournament_state.address)
             .await?;
+        self.check_balance(bond_value).await;
 
-        self.arena_sender
+        let result = self
+            .arena_sender
             .lock()
             .await
             .join_tournament(
@@ -217,7 +352,26 @@ impl<AS: ArenaSender> Player<AS> {
                 right.root_hash(),
                 bond_value,
             )
-            .await?;
+            .await;
+        record_transaction(
+            &self.db,
+            Action {
+                tournament: tournament_state.address,
+                tx_call: "joinTournament",
+                arguments: json!({
+                    "final_state": proof_last.node.to_string(),
+                    "final_state_position": proof_last.position.to_string(),
+                    "left_child": left.root_hash().to_string(),
+                    "right_child": right.root_hash().to_string(),
+                    "bond_value": bond_value.to_string(),
+                }),
+                context: json!({
+                    "level": tournament_state.level,
+                    "commitment": commitment.merkle.root_hash().to_string(),
+                }),
+            },
+            result,
+        )?;
 
         Ok(())
     }
@@ -273,17 +427,18 @@ impl<AS: ArenaSender> Player<AS> {
         commitment_states: &HashMap<Digest, CommitmentState>,
         tournament_level: u64,
     ) -> Result<()> {
-        let opponent_clock = if commitment.merkle.root_hash() == match_state.id.commitment_one {
-            commitment_states
-                .get(&match_state.id.commitment_two)
-                .unwrap()
-                .clock
+        let opponent = if commitment.merkle.root_hash() == match_state.id.commitment_one {
+            match_state.id.commitment_two
         } else {
-            commitment_states
-                .get(&match_state.id.commitment_one)
-                .unwrap()
-                .clock
+            match_state.id.commitment_one
         };
+        let opponent_clock = commitment_states.get(&opponent).unwrap().clock;
+        self.deadlines.extend(Deadline::from_clock(
+            match_state.tournament_address,
+            opponent,
+            false,
+            &opponent_clock,
+        ));
 
         if !opponent_clock.has_time() {
             let (left, right) = commitment
@@ -298,7 +453,11 @@ impl<AS: ArenaSender> Player<AS> {
                 commitment.merkle.root_hash(),
             );
 
-            self.arena_sender
+            let mut context = audit::match_context(match_state, commitment, tournament_level);
+            context["opponent_clock"] = json!(opponent_clock.to_string());
+
+            let result = self
+                .arena_sender
                 .lock()
                 .await
                 .win_timeout_match(
@@ -307,7 +466,21 @@ impl<AS: ArenaSender> Player<AS> {
                     left.root_hash(),
                     right.root_hash(),
                 )
-                .await?;
+                .await;
+            record_transaction(
+                &self.db,
+                Action {
+                    tournament: match_state.tournament_address,
+                    tx_call: "winMatchByTimeout",
+                    arguments: json!({
+                        "match_id": audit::match_id(&match_state.id),
+                        "left_node": left.root_hash().to_string(),
+                        "right_node": right.root_hash().to_string(),
+                    }),
+                    context,
+                },
+                result,
+            )?;
         }
         Ok(())
     }
@@ -336,6 +509,23 @@ impl<AS: ArenaSender> Player<AS> {
                 )?
             };
 
+            // the proof must take the agreed state to the state we committed to,
+            // otherwise submitting it would only revert on-chain
+            let claimed_hash = commitment
+                .merkle
+                .prove_leaf(match_state.running_leaf_position)
+                .node;
+            if proof.1 != claimed_hash {
+                return Err(anyhow::anyhow!(
+                    "step proof from {} leads to {}, but commitment {} claims {}",
+                    match_state.other_parent,
+                    proof.1,
+                    commitment.merkle.root_hash(),
+                    claimed_hash
+                )
+                .into());
+            }
+
             info!(
                 "win leaf match in tournament {} of level {} for commitment {}, proof size {}",
                 match_state.tournament_address,
@@ -343,7 +533,24 @@ impl<AS: ArenaSender> Player<AS> {
                 commitment.merkle.root_hash(),
                 proof.0.len()
             );
-            self.arena_sender
+
+            let mut context = audit::match_context(match_state, commitment, tournament_level);
+            context["leaf_cycle"] = json!(match_state.leaf_cycle.to_string());
+            context["next_state"] = json!(proof.1.to_string());
+            let action = Action {
+                tournament: match_state.tournament_address,
+                tx_call: "winLeafMatch",
+                arguments: json!({
+                    "match_id": audit::match_id(&match_state.id),
+                    "left_node": left.root_hash().to_string(),
+                    "right_node": right.root_hash().to_string(),
+                    "proofs": alloy::hex::encode_prefixed(&proof.0),
+                }),
+                context,
+            };
+
+            let result = self
+                .arena_sender
                 .lock()
                 .await
                 .win_leaf_match(
@@ -353,7 +560,8 @@ impl<AS: ArenaSender> Player<AS> {
                     right.root_hash(),
                     proof.0,
                 )
-                .await?;
+                .await;
+            record_transaction(&self.db, action, result)?;
         } else {
             self.react_tournament(
                 Some(commitment),
@@ -382,16 +590,19 @@ impl<AS: ArenaSender> Player<AS> {
 
         let (left, right) = r.subtrees().expect("merkle tree should have subtrees");
 
-        let running_leaf_position = {
+        let (running_leaf_position, direction) = {
             if left.root_hash() != match_state.left_node {
                 // disagree on left
-                match_state.running_leaf_position
+                (match_state.running_leaf_position, "left")
             } else {
                 // disagree on right
-                match_state.running_leaf_position + U256::one()
+                (match_state.running_leaf_position + U256::one(), "right")
             }
         };
 
+        let mut context = audit::match_context(match_state, commitment, tournament_level);
+        context["direction"] = json!(direction);
+
         let agree_state_proof = if running_leaf_position.is_zero() {
             MerkleProof::leaf(commitment.implicit_hash, U256::ZERO)
         } else {
@@ -407,7 +618,8 @@ impl<AS: ArenaSender> Player<AS> {
                 tournament_level,
                 commitment.merkle.root_hash(),
             );
-            self.arena_sender
+            let result = self
+                .arena_sender
                 .lock()
                 .await
                 .seal_leaf_match(
@@ -417,7 +629,22 @@ impl<AS: ArenaSender> Player<AS> {
                     right.root_hash(),
                     &agree_state_proof,
                 )
-                .await?;
+                .await;
+            record_transaction(
+                &self.db,
+                Action {
+                    tournament: match_state.tournament_address,
+                    tx_call: "sealLeafMatch",
+                    arguments: json!({
+                        "match_id": audit::match_id(&match_state.id),
+                        "left_leaf": left.root_hash().to_string(),
+                        "right_leaf": right.root_hash().to_string(),
+                        "agree_state": agree_state_proof.node.to_string(),
+                    }),
+                    context,
+                },
+                result,
+            )?;
         } else {
             info!(
                 "seal inner match in tournament {} of level {} for commitment {}",
@@ -425,7 +652,8 @@ impl<AS: ArenaSender> Player<AS> {
                 tournament_level,
                 commitment.merkle.root_hash(),
             );
-            self.arena_sender
+            let result = self
+                .arena_sender
                 .lock()
                 .await
                 .seal_inner_match(
@@ -435,7 +663,22 @@ impl<AS: ArenaSender> Player<AS> {
                     right.root_hash(),
                     &agree_state_proof,
                 )
-                .await?;
+                .await;
+            record_transaction(
+                &self.db,
+                Action {
+                    tournament: match_state.tournament_address,
+                    tx_call: "sealInnerMatchAndCreateInnerTournament",
+                    arguments: json!({
+                        "match_id": audit::match_id(&match_state.id),
+                        "left_leaf": left.root_hash().to_string(),
+                        "right_leaf": right.root_hash().to_string(),
+                        "agree_state": agree_state_proof.node.to_string(),
+                    }),
+                    context,
+                },
+                result,
+            )?;
         }
         Ok(())
     }
@@ -453,14 +696,19 @@ impl<AS: ArenaSender> Player<AS> {
 
         let (left, right) = r.subtrees().expect("merkle tree should have subtrees");
 
-        let (new_left, new_right) = if left.root_hash() != match_state.left_node {
+        let (new_left, new_right, direction) = if left.root_hash() != match_state.left_node {
             debug!("going down to the left");
-            left.subtrees().expect("left tree should have subtrees")
+            let (l, r) = left.subtrees().expect("left tree should have subtrees");
+            (l, r, "left")
         } else {
             debug!("going down to the right");
-            right.subtrees().expect("right tree should have subtrees")
+            let (l, r) = right.subtrees().expect("right tree should have subtrees");
+            (l, r, "right")
         };
 
+        let mut context = audit::match_context(match_state, commitment, tournament_level);
+        context["direction"] = json!(direction);
+
         info!(
             "advance match with current height {} in tournament {} of level {} for commitment {}",
             match_state.current_height,
@@ -468,7 +716,8 @@ impl<AS: ArenaSender> Player<AS> {
             tournament_level,
             commitment.merkle.root_hash(),
         );
-        self.arena_sender
+        let result = self
+            .arena_sender
             .lock()
             .await
             .advance_match(
@@ -479,7 +728,23 @@ impl<AS: ArenaSender> Player<AS> {
                 new_left.root_hash(),
                 new_right.root_hash(),
             )
-            .await?;
+            .await;
+        record_transaction(
+            &self.db,
+            Action {
+                tournament: match_state.tournament_address,
+                tx_call: "advanceMatch",
+                arguments: json!({
+                    "match_id": audit::match_id(&match_state.id),
+                    "left_node": left.root_hash().to_string(),
+                    "right_node": right.root_hash().to_string(),
+                    "new_left_node": new_left.root_hash().to_string(),
+                    "new_right_node": new_right.root_hash().to_string(),
+                }),
+                context,
+            },
+            result,
+        )?;
         Ok(())
     }
 }
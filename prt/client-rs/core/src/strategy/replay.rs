@@ -0,0 +1,184 @@
+//! Rebuilds the commitments of a dispute bundle offline, and checks them against the ones the node
+//! recorded or the ones joined on chain.
+
+use alloy::primitives::{Address, U256};
+use cartesi_dave_merkle::Digest;
+use std::fmt;
+
+use crate::{
+    db::dispute_state_access::{DisputeStateAccess, RecordedCommitment},
+    machine::{MachineCommitmentBuilder, MachineInstance},
+    strategy::error::Result,
+    tournament::TournamentStateMap,
+};
+
+/// What a rebuilt commitment is checked against.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expected {
+    /// the commitment recorded by the node
+    Recorded(Digest),
+    /// the commitments joined in a tournament on chain
+    OnChain {
+        tournament: Address,
+        joined: Vec<Digest>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CommitmentCheck {
+    pub level: u64,
+    pub base_cycle: U256,
+    pub log2_stride: u64,
+    pub log2_stride_count: u64,
+    pub computed: Digest,
+    pub expected: Expected,
+}
+
+impl CommitmentCheck {
+    pub fn is_consistent(&self) -> bool {
+        match &self.expected {
+            Expected::Recorded(recorded) => *recorded == self.computed,
+            Expected::OnChain { joined, .. } => joined.contains(&self.computed),
+        }
+    }
+}
+
+impl fmt::Display for CommitmentCheck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} commitment of level {} at base cycle {}: computed {}, ",
+            if self.is_consistent() {
+                "ok"
+            } else {
+                "MISMATCH"
+            },
+            self.level,
+            self.base_cycle,
+            self.computed
+        )?;
+        match &self.expected {
+            Expected::Recorded(recorded) => write!(f, "recorded {}", recorded),
+            Expected::OnChain { tournament, joined } => write!(
+                f,
+                "{} commitment(s) joined in tournament {}",
+                joined.len(),
+                tournament
+            ),
+        }
+    }
+}
+
+/// Checks that the machine at `machine_path` has the state hash a bundle was exported with, so
+/// that commitments are rebuilt from the same machine the node ran.
+pub fn check_machine(machine_path: &str, machine_hash: Digest) -> Result<()> {
+    let found = MachineInstance::new_from_path(machine_path)?.root_hash()?;
+    if found != machine_hash {
+        return Err(anyhow::anyhow!(
+            "machine at {} has state hash {}, the bundle expects {}",
+            machine_path,
+            found,
+            machine_hash
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Rebuilds every commitment recorded in `commitments`, using the inputs of `db`.
+pub fn replay_recorded(
+    db: &DisputeStateAccess,
+    machine_path: String,
+    commitments: &[RecordedCommitment],
+) -> Result<Vec<CommitmentCheck>> {
+    let mut builder = MachineCommitmentBuilder::new(machine_path);
+    commitments
+        .iter()
+        .map(|c| {
+            let commitment = builder.build_commitment(
+                c.base_cycle,
+                c.level,
+                c.log2_stride,
+                c.log2_stride_count,
+                db,
+            )?;
+            Ok(CommitmentCheck {
+                level: c.level,
+                base_cycle: c.base_cycle,
+                log2_stride: c.log2_stride,
+                log2_stride_count: c.log2_stride_count,
+                computed: commitment.merkle.root_hash(),
+                expected: Expected::Recorded(c.root_hash.into()),
+            })
+        })
+        .collect()
+}
+
+/// Rebuilds the commitment of every tournament in `tournament_states`, using the inputs of `db`,
+/// and checks that some joined commitment matches it.
+pub fn replay_on_chain(
+    db: &DisputeStateAccess,
+    machine_path: String,
+    tournament_states: &TournamentStateMap,
+) -> Result<Vec<CommitmentCheck>> {
+    let mut tournaments: Vec<_> = tournament_states.values().collect();
+    tournaments.sort_by_key(|t| (t.level, t.base_cycle));
+
+    let mut builder = MachineCommitmentBuilder::new(machine_path);
+    tournaments
+        .into_iter()
+        .map(|t| {
+            let commitment = builder.build_commitment(
+                t.base_cycle,
+                t.level,
+                t.log2_stride,
+                t.log2_stride_count,
+                db,
+            )?;
+            Ok(CommitmentCheck {
+                level: t.level,
+                base_cycle: t.base_cycle,
+                log2_stride: t.log2_stride,
+                log2_stride_count: t.log2_stride_count,
+                computed: commitment.merkle.root_hash(),
+                expected: Expected::OnChain {
+                    tournament: t.address,
+                    joined: t.commitment_states.keys().copied().collect(),
+                },
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(computed: Digest, expected: Expected) -> CommitmentCheck {
+        CommitmentCheck {
+            level: 0,
+            base_cycle: U256::ZERO,
+            log2_stride: 44,
+            log2_stride_count: 4,
+            computed,
+            expected,
+        }
+    }
+
+    #[test]
+    fn test_consistency() {
+        let a = Digest::from([1; 32]);
+        let b = Digest::from([2; 32]);
+
+        assert!(check(a, Expected::Recorded(a)).is_consistent());
+        assert!(!check(a, Expected::Recorded(b)).is_consistent());
+
+        let on_chain = |joined| Expected::OnChain {
+            tournament: Address::ZERO,
+            joined,
+        };
+        assert!(check(a, on_chain(vec![b, a])).is_consistent());
+        assert!(!check(a, on_chain(vec![b])).is_consistent());
+        assert!(!check(a, on_chain(vec![])).is_consistent());
+    }
+}
@@ -0,0 +1,272 @@
+//! The [Scheduler] decides when the player should react again, based on the [Deadline]s of the
+//! clocks it observed in its last reaction.
+//!
+//! Only the reactions to tournaments back off. Whoever drives them keeps polling every
+//! `idle_sleep` for everything else, such as settling epochs and joining new ones, and every block
+//! while a sealed epoch is about to be joined.
+
+use alloy::primitives::Address;
+use cartesi_dave_merkle::Digest;
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::tournament::ClockState;
+
+/// Block at which a ticking clock runs out of time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Deadline {
+    pub tournament: Address,
+    pub commitment: Digest,
+    /// whether the clock belongs to the player's own commitment
+    pub own: bool,
+    /// block at which the clock times out
+    pub block: u64,
+    /// latest block when the clock was read
+    pub block_number: u64,
+}
+
+impl Deadline {
+    /// Returns the deadline of `clock`, if it is ticking.
+    pub fn from_clock(
+        tournament: Address,
+        commitment: Digest,
+        own: bool,
+        clock: &ClockState,
+    ) -> Option<Self> {
+        clock.timeout_block().map(|block| Deadline {
+            tournament,
+            commitment,
+            own,
+            block,
+            block_number: clock.block_number,
+        })
+    }
+
+    /// Number of blocks left until the clock times out, zero if it already has.
+    pub fn blocks_left(&self) -> u64 {
+        self.block.saturating_sub(self.block_number)
+    }
+}
+
+impl std::fmt::Display for Deadline {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} clock of commitment {} in tournament {} times out at block {} ({} blocks left)",
+            if self.own { "own" } else { "opponent" },
+            self.commitment,
+            self.tournament,
+            self.block,
+            self.blocks_left()
+        )
+    }
+}
+
+/// Deadlines shared between the task reacting to tournaments and its observers.
+#[derive(Clone, Debug, Default)]
+pub struct Deadlines(Arc<RwLock<Vec<Deadline>>>);
+
+impl Deadlines {
+    /// Returns the deadlines observed in the last reaction, earliest first.
+    pub fn get(&self) -> Vec<Deadline> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub fn set(&self, mut deadlines: Vec<Deadline>) {
+        deadlines.sort_by_key(|d| d.block);
+        *self.0.write().unwrap() = deadlines;
+    }
+}
+
+/// Computes how long to sleep between reactions.
+///
+/// A ticking own clock means a move is due, so the player wakes on the next block. A ticking
+/// opponent clock wakes the player right after it times out, to claim the match. When no clock is
+/// ticking, the sleep grows exponentially from `idle_sleep` up to `max_sleep`, until the epoch
+/// under dispute changes.
+#[derive(Clone, Debug)]
+pub struct Scheduler {
+    block_time: Duration,
+    idle_sleep: Duration,
+    max_sleep: Duration,
+    idle_rounds: u32,
+    epoch: Option<u64>,
+    next_reaction: Option<Instant>,
+}
+
+impl Scheduler {
+    pub fn new(block_time: Duration, idle_sleep: Duration, max_sleep: Duration) -> Self {
+        assert!(!block_time.is_zero(), "block time must be positive");
+        Self {
+            block_time,
+            idle_sleep: idle_sleep.min(max_sleep),
+            max_sleep,
+            idle_rounds: 0,
+            epoch: None,
+            next_reaction: None,
+        }
+    }
+
+    /// Whether to react to the tournaments of `epoch` at `now`: once the sleep after the last
+    /// reaction is over, or right away if the epoch under dispute changed.
+    pub fn should_react(&mut self, epoch: Option<u64>, now: Instant) -> bool {
+        if epoch != self.epoch {
+            self.epoch = epoch;
+            self.idle_rounds = 0;
+            self.next_reaction = None;
+        }
+        self.next_reaction.is_none_or(|next| now >= next)
+    }
+
+    /// Schedules the reaction after one at `now` that observed `deadlines`.
+    pub fn reacted(&mut self, deadlines: &[Deadline], now: Instant) {
+        self.next_reaction = Some(now + self.next_sleep(deadlines));
+    }
+
+    /// How long to wait at `now` before polling again, at most `idle_sleep`.
+    pub fn poll_sleep(&self, now: Instant) -> Duration {
+        self.next_reaction
+            .map_or(self.idle_sleep, |next| next.saturating_duration_since(now))
+            .min(self.idle_sleep)
+    }
+
+    /// Like [Self::poll_sleep], but at most a block, for while the settlement of a sealed epoch
+    /// may show up any moment, so that its tournament is joined on the block it does.
+    pub fn poll_sleep_until_joined(&self, now: Instant) -> Duration {
+        self.poll_sleep(now).min(self.block_time)
+    }
+
+    pub fn next_sleep(&mut self, deadlines: &[Deadline]) -> Duration {
+        let wake_in_blocks = deadlines
+            .iter()
+            .map(|d| {
+                if d.own {
+                    1
+                } else {
+                    // the clock has run out only after its deadline block
+                    d.blocks_left() + 1
+                }
+            })
+            .min();
+
+        let sleep = match wake_in_blocks {
+            Some(blocks) => {
+                self.idle_rounds = 0;
+                self.block_time
+                    .saturating_mul(blocks.try_into().unwrap_or(u32::MAX))
+            }
+            None => {
+                let sleep = self
+                    .idle_sleep
+                    .saturating_mul(1 << self.idle_rounds.min(16));
+                self.idle_rounds = self.idle_rounds.saturating_add(1);
+                sleep
+            }
+        };
+
+        sleep.clamp(self.block_time.min(self.max_sleep), self.max_sleep)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deadline(own: bool, block: u64, block_number: u64) -> Deadline {
+        Deadline {
+            tournament: Address::ZERO,
+            commitment: Digest::ZERO,
+            own,
+            block,
+            block_number,
+        }
+    }
+
+    fn scheduler() -> Scheduler {
+        Scheduler::new(
+            Duration::from_secs(12),
+            Duration::from_secs(30),
+            Duration::from_secs(300),
+        )
+    }
+
+    #[test]
+    fn test_idle_backoff() {
+        let mut s = scheduler();
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(30));
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(60));
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(120));
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(240));
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(300));
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(300));
+
+        // any ticking clock resets the backoff
+        s.next_sleep(&[deadline(false, 100, 100)]);
+        assert_eq!(s.next_sleep(&[]), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_poll_while_backing_off() {
+        let mut s = scheduler();
+        let start = Instant::now();
+        let secs = |n| start + Duration::from_secs(n);
+
+        assert!(s.should_react(Some(0), start));
+        s.reacted(&[], start);
+        assert!(s.should_react(Some(0), secs(30)));
+        s.reacted(&[], secs(30));
+
+        // reactions back off to a minute, polling doesn't
+        assert!(!s.should_react(Some(0), secs(60)));
+        assert_eq!(s.poll_sleep(secs(60)), Duration::from_secs(30));
+        assert_eq!(s.poll_sleep(secs(80)), Duration::from_secs(10));
+        assert!(s.should_react(Some(0), secs(90)));
+        s.reacted(&[], secs(90));
+
+        // a new epoch is reacted to at once, with the backoff reset
+        assert!(!s.should_react(Some(0), secs(100)));
+        assert!(s.should_react(Some(1), secs(100)));
+        s.reacted(&[], secs(100));
+        assert!(!s.should_react(Some(1), secs(129)));
+        assert!(s.should_react(Some(1), secs(130)));
+    }
+
+    #[test]
+    fn test_poll_every_block_until_joined() {
+        let mut s = scheduler();
+        let start = Instant::now();
+
+        assert_eq!(s.poll_sleep_until_joined(start), Duration::from_secs(12));
+        s.reacted(&[], start);
+        assert_eq!(
+            s.poll_sleep_until_joined(start + Duration::from_secs(25)),
+            Duration::from_secs(5)
+        );
+    }
+
+    #[test]
+    fn test_own_clock_is_urgent() {
+        let mut s = scheduler();
+        let deadlines = [deadline(false, 100, 90), deadline(true, 1000, 90)];
+        assert_eq!(s.next_sleep(&deadlines), Duration::from_secs(12));
+    }
+
+    #[test]
+    fn test_wake_after_opponent_timeout() {
+        let mut s = scheduler();
+        assert_eq!(
+            s.next_sleep(&[deadline(false, 104, 100)]),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            s.next_sleep(&[deadline(false, 100, 110)]),
+            Duration::from_secs(12)
+        );
+        assert_eq!(
+            s.next_sleep(&[deadline(false, 10_000, 100)]),
+            Duration::from_secs(300)
+        );
+    }
+}
@@ -4,15 +4,18 @@
 use crate::strategy::error::Result;
 use alloy::{
     contract::Error,
-    network::Ethereum,
-    providers::{DynProvider, PendingTransactionBuilder},
+    network::{Ethereum, ReceiptResponse},
+    providers::{
+        DynProvider, PendingTransactionBuilder, PendingTransactionError, Provider, WatchTxError,
+    },
     sol_types::private::{Address, B256, Bytes},
 };
 use async_trait::async_trait;
 use log::{trace, warn};
 use ruint::aliases::U256;
+use std::time::Duration;
 
-use crate::{machine::MachineProof, tournament::MatchID};
+use crate::{db::dispute_state_access::LedgerEntry, machine::MachineProof, tournament::MatchID};
 use cartesi_dave_merkle::{Digest, MerkleProof};
 use cartesi_prt_contracts::tournament;
 
@@ -21,6 +24,10 @@ use cartesi_prt_contracts::tournament;
 /// Override with `GAS_LIMIT` env var if needed.
 const DEFAULT_GAS_LIMIT: u64 = 15_000_000;
 
+/// How long to wait for a transaction to be mined. Transactions are sent one at a time, so a
+/// stuck one must not hold back the reactions after it.
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(120);
+
 fn gas_limit() -> u64 {
     std::env::var("GAS_LIMIT")
         .ok()
@@ -31,11 +38,48 @@ fn gas_limit() -> u64 {
 #[derive(Clone, Debug)]
 pub struct EthArenaSender {
     provider: DynProvider,
+    signer: Address,
 }
 
 impl EthArenaSender {
-    pub fn new(provider: DynProvider) -> anyhow::Result<Self> {
-        Ok(Self { provider })
+    pub fn new(provider: DynProvider, signer: Address) -> anyhow::Result<Self> {
+        Ok(Self { provider, signer })
+    }
+}
+
+/// Outcome of a transaction sent by an [ArenaSender].
+#[derive(Clone, Debug, Default)]
+pub struct TxOutcome {
+    pub call: String,
+    pub tx_hash: Option<B256>,
+    /// value sent along with the transaction, e.g. the bond when joining a tournament
+    pub value: U256,
+    pub gas_used: u64,
+    pub effective_gas_price: u128,
+    /// bond refunded to the sender by the tournament
+    pub refund: U256,
+    pub reverted: bool,
+    pub revert_data: Option<Bytes>,
+}
+
+impl TxOutcome {
+    pub fn gas_cost(&self) -> U256 {
+        U256::from(self.gas_used) * U256::from(self.effective_gas_price)
+    }
+
+    /// Ledger entry of the transaction, sent to `tournament` at `timestamp`.
+    pub fn ledger_entry(&self, tournament: Address, timestamp: u64) -> LedgerEntry {
+        LedgerEntry {
+            tournament,
+            tx_call: self.call.clone(),
+            tx_hash: self.tx_hash,
+            bond: self.value,
+            gas_used: self.gas_used,
+            gas_cost: self.gas_cost(),
+            refund: self.refund,
+            reverted: self.reverted,
+            timestamp,
+        }
     }
 }
 
@@ -49,7 +93,7 @@ pub trait ArenaSender: Send + Sync {
         left_child: Digest,
         right_child: Digest,
         bond_value: U256,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn advance_match(
         &self,
@@ -59,7 +103,7 @@ pub trait ArenaSender: Send + Sync {
         right_node: Digest,
         new_left_node: Digest,
         new_right_node: Digest,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn seal_inner_match(
         &self,
@@ -68,7 +112,7 @@ pub trait ArenaSender: Send + Sync {
         left_leaf: Digest,
         right_leaf: Digest,
         initial_hash_proof: &MerkleProof,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn win_inner_match(
         &self,
@@ -76,7 +120,7 @@ pub trait ArenaSender: Send + Sync {
         child_tournament: Address,
         left_node: Digest,
         right_node: Digest,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn win_timeout_match(
         &self,
@@ -84,7 +128,7 @@ pub trait ArenaSender: Send + Sync {
         match_id: MatchID,
         left_node: Digest,
         right_node: Digest,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn seal_leaf_match(
         &self,
@@ -93,7 +137,7 @@ pub trait ArenaSender: Send + Sync {
         left_leaf: Digest,
         right_leaf: Digest,
         initial_hash_proof: &MerkleProof,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn win_leaf_match(
         &self,
@@ -102,17 +146,20 @@ pub trait ArenaSender: Send + Sync {
         left_node: Digest,
         right_node: Digest,
         proofs: MachineProof,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
-    async fn eliminate_match(&self, tournament: Address, match_id: MatchID) -> Result<()>;
+    async fn eliminate_match(&self, tournament: Address, match_id: MatchID) -> Result<TxOutcome>;
 
     async fn eliminate_inner_tournament(
         &self,
         tournament: Address,
         inner_tournament: Address,
-    ) -> Result<()>;
+    ) -> Result<TxOutcome>;
 
     async fn bond_value(&self, tournament: Address) -> Result<U256>;
+
+    /// Balance of the account paying for bonds and gas.
+    async fn balance(&self) -> Result<U256>;
 }
 
 #[async_trait]
@@ -124,7 +171,7 @@ impl ArenaSender for EthArenaSender {
         left_child: Digest,
         right_child: Digest,
         bond_value: U256,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let siblings = proof
             .siblings
@@ -145,7 +192,11 @@ impl ArenaSender for EthArenaSender {
             .value(bond_value)
             .send()
             .await;
-        allow_revert_rethrow_others("joinTournament", tx_result).await
+        let mut outcome = allow_revert_rethrow_others("joinTournament", tx_result).await?;
+        if !outcome.reverted {
+            outcome.value = bond_value;
+        }
+        Ok(outcome)
     }
 
     async fn advance_match(
@@ -156,7 +207,7 @@ impl ArenaSender for EthArenaSender {
         right_node: Digest,
         new_left_node: Digest,
         new_right_node: Digest,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .advanceMatch(
@@ -179,7 +230,7 @@ impl ArenaSender for EthArenaSender {
         left_leaf: Digest,
         right_leaf: Digest,
         initial_hash_proof: &MerkleProof,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let initial_hash_siblings = initial_hash_proof
             .siblings
@@ -206,7 +257,7 @@ impl ArenaSender for EthArenaSender {
         child_tournament: Address,
         left_node: Digest,
         right_node: Digest,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .winInnerTournament(child_tournament, left_node.into(), right_node.into())
@@ -222,7 +273,7 @@ impl ArenaSender for EthArenaSender {
         match_id: MatchID,
         left_node: Digest,
         right_node: Digest,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .winMatchByTimeout(match_id.into(), left_node.into(), right_node.into())
@@ -239,7 +290,7 @@ impl ArenaSender for EthArenaSender {
         left_leaf: Digest,
         right_leaf: Digest,
         initial_hash_proof: &MerkleProof,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let initial_hash_siblings = initial_hash_proof
             .siblings
@@ -267,7 +318,7 @@ impl ArenaSender for EthArenaSender {
         left_node: Digest,
         right_node: Digest,
         proofs: MachineProof,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .winLeafMatch(
@@ -282,7 +333,7 @@ impl ArenaSender for EthArenaSender {
         allow_revert_rethrow_others("winLeafMatch", tx_result).await
     }
 
-    async fn eliminate_match(&self, tournament: Address, match_id: MatchID) -> Result<()> {
+    async fn eliminate_match(&self, tournament: Address, match_id: MatchID) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .eliminateMatchByTimeout(match_id.into())
@@ -296,7 +347,7 @@ impl ArenaSender for EthArenaSender {
         &self,
         tournament: Address,
         inner_tournament: Address,
-    ) -> Result<()> {
+    ) -> Result<TxOutcome> {
         let tournament = tournament::Tournament::new(tournament, &self.provider);
         let tx_result = tournament
             .eliminateInnerTournament(inner_tournament)
@@ -311,24 +362,84 @@ impl ArenaSender for EthArenaSender {
         let bond_value_result = tournament.bondValue().call().await?;
         Ok(bond_value_result)
     }
+
+    async fn balance(&self) -> Result<U256> {
+        let balance = self
+            .provider
+            .get_balance(self.signer)
+            .await
+            .map_err(anyhow::Error::from)?;
+        Ok(balance)
+    }
 }
 
 pub async fn allow_revert_rethrow_others(
     tx_call: &str,
     tx_result: std::result::Result<PendingTransactionBuilder<Ethereum>, Error>,
-) -> Result<()> {
-    if let Err(e) = tx_result {
-        match e.as_revert_data() {
+) -> Result<TxOutcome> {
+    let mut outcome = TxOutcome {
+        call: tx_call.to_owned(),
+        ..Default::default()
+    };
+
+    match tx_result {
+        Ok(pending) => {
+            let tx_hash = *pending.tx_hash();
+            outcome.tx_hash = Some(tx_hash);
+            let receipt = match pending
+                .with_timeout(Some(RECEIPT_TIMEOUT))
+                .get_receipt()
+                .await
+            {
+                Ok(receipt) => receipt,
+                Err(PendingTransactionError::TxWatcher(WatchTxError::Timeout)) => {
+                    // the next reaction reads the tournament again, whether it was mined or not
+                    warn!(
+                        "{} transaction {} not mined after {} seconds, its cost is not recorded",
+                        tx_call,
+                        tx_hash,
+                        RECEIPT_TIMEOUT.as_secs()
+                    );
+                    return Ok(outcome);
+                }
+                Err(e) => return Err(anyhow::Error::from(e).into()),
+            };
+            outcome.gas_used = receipt.gas_used;
+            outcome.effective_gas_price = receipt.effective_gas_price;
+            outcome.reverted = !receipt.status();
+            // the refundable modifier pays part of the bond back to the caller
+            outcome.refund = receipt
+                .inner
+                .logs()
+                .iter()
+                .filter_map(|log| {
+                    log.log_decode::<tournament::Tournament::PartialBondRefund>()
+                        .ok()
+                })
+                .map(|log| log.inner.data)
+                .filter(|refund| refund.success && refund.recipient == receipt.from)
+                .fold(U256::ZERO, |acc, refund| acc + refund.value);
+
+            if outcome.reverted {
+                warn!(
+                    "{} transaction {} reverted",
+                    tx_call, receipt.transaction_hash
+                );
+            }
+        }
+        Err(e) => match e.as_revert_data() {
             Some(revert_data) => {
                 // allow transactions to be reverted
                 warn!("{} transaction reverted with data {}", tx_call, revert_data);
+                outcome.reverted = true;
+                outcome.revert_data = Some(revert_data);
             }
             None => {
                 // rethrow any other errors
                 return Err(e.into());
             }
-        }
+        },
     }
 
-    Ok(())
+    Ok(outcome)
 }
@@ -67,6 +67,11 @@ impl ClockState {
         }
     }
 
+    /// Block at which the clock times out, if it's ticking.
+    pub fn timeout_block(&self) -> Option<u64> {
+        (self.start_instant != 0).then(|| self.deadline())
+    }
+
     // deadline of clock if it's ticking
     fn deadline(&self) -> u64 {
         self.start_instant + self.allowance